@@ -0,0 +1,115 @@
+//! Counts the allocations and allocated bytes of passing a P1B with large
+//! operations around: cloning it, as a scout does for each response, and
+//! decoding it with each codec. Operations are shared rather than copied, so
+//! the bytes allocated stay far below the size of the payload.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    net::{IpAddr, Ipv4Addr},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use bytes::Bytes;
+
+use paxos::{
+    codec::{BinaryCodec, Codec, ProtobufCodec, WireMessage},
+    ds::Accepted,
+    env::ProcessId,
+    message::Message,
+    pval::{BallotNumber, Command, PValue},
+};
+
+struct Counting;
+
+static ALLOCS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(
+            new_size.saturating_sub(layout.size()) as u64,
+            Ordering::Relaxed,
+        );
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+static SLOTS: u64 = 1000;
+static OPERATION: usize = 4096;
+
+fn p1b() -> WireMessage {
+    let local_host = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let leader = ProcessId::new(local_host, 6060, 1);
+    let ballot = BallotNumber::new(7, leader.clone());
+    let value = "x".repeat(OPERATION);
+
+    let mut accepted = Accepted::new();
+    for slot in 1..=SLOTS {
+        let client = ProcessId::new(local_host, 7200, 0);
+        let command = Command::new_from_str(
+            client,
+            format!("1697040000000000000-{}", slot),
+            format!("PUT key-{} {}", slot, value),
+        );
+        accepted.insert(slot, PValue::new(ballot.clone(), slot, command));
+    }
+    let acceptor = ProcessId::new(local_host, 6061, 0);
+    WireMessage::new(leader, Message::P1B(acceptor, ballot, accepted, true))
+}
+
+/// Allocations and bytes allocated by `f`, whose result is dropped after
+/// counting.
+fn count<T>(f: impl FnOnce() -> T) -> (u64, u64) {
+    let (a, b) = (ALLOCS.load(Ordering::SeqCst), BYTES.load(Ordering::SeqCst));
+    let res = f();
+    let counted = (
+        ALLOCS.load(Ordering::SeqCst) - a,
+        BYTES.load(Ordering::SeqCst) - b,
+    );
+    drop(res);
+    counted
+}
+
+fn report(name: &str, (allocs, bytes): (u64, u64)) {
+    println!(
+        "{:<24} {:>8} allocs {:>12} bytes {:>8.1} bytes/slot",
+        name,
+        allocs,
+        bytes,
+        bytes as f64 / SLOTS as f64
+    );
+}
+
+fn main() {
+    let m = p1b();
+    println!("P1B with {} slots of {} byte operations", SLOTS, OPERATION);
+    report("clone", count(|| m.clone()));
+
+    let codecs: [(&str, &dyn Codec); 2] = [("protobuf", &ProtobufCodec), ("binary", &BinaryCodec)];
+    for (name, codec) in codecs {
+        let b = Bytes::from(codec.encode(m.clone()));
+        println!("{} payload {} bytes", name, b.len());
+        report(
+            &format!("encode/{}", name),
+            count(|| codec.encode(m.clone())),
+        );
+        report(
+            &format!("decode/{}", name),
+            count(|| codec.decode(b.clone())),
+        );
+    }
+}
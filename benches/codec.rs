@@ -0,0 +1,63 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use paxos::{
+    codec::{BinaryCodec, Codec, ProtobufCodec, WireMessage},
+    ds::Accepted,
+    env::ProcessId,
+    message::Message,
+    pval::{BallotNumber, Command, PValue},
+};
+
+/// What an acceptor reports for `slots` accepted values, as in a P1B, or a
+/// scout once it collected a majority of them, as in an Adopt.
+fn payload(slots: u64, adopt: bool) -> WireMessage {
+    let local_host = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let leader = ProcessId::new(local_host, 6060, 1);
+    let ballot = BallotNumber::new(7, leader.clone());
+
+    let mut accepted = Accepted::new();
+    for slot in 1..=slots {
+        let client = ProcessId::new(local_host, 7200, 0);
+        let command = Command::new_from_str(
+            client,
+            format!("1697040000000000000-{}", slot),
+            format!("PUT key-{} {}", slot, "x".repeat(16)),
+        );
+        accepted.insert(slot, PValue::new(ballot.clone(), slot, command));
+    }
+
+    let m = match adopt {
+        true => Message::Adopt(leader.clone(), ballot, accepted),
+        false => Message::P1B(ProcessId::new(local_host, 6061, 0), ballot, accepted, true),
+    };
+    WireMessage::new(leader, m)
+}
+
+fn codec(c: &mut Criterion) {
+    let codecs: [(&str, &dyn Codec); 2] = [("protobuf", &ProtobufCodec), ("binary", &BinaryCodec)];
+    for (name, adopt) in [("P1B", false), ("Adopt", true)] {
+        let mut group = c.benchmark_group(format!("codec/{}", name));
+        for slots in [1000, 10000] {
+            let m = payload(slots, adopt);
+            group.throughput(Throughput::Elements(slots));
+            for (codec_name, codec) in codecs {
+                let b = Bytes::from(codec.encode(m.clone()));
+                println!("{} {} slots {}: {} bytes", name, slots, codec_name, b.len());
+
+                group.bench_function(format!("encode/{}/{}", codec_name, slots), |bench| {
+                    bench.iter_batched(|| m.clone(), |m| codec.encode(m), BatchSize::LargeInput)
+                });
+                group.bench_function(format!("decode/{}/{}", codec_name, slots), |bench| {
+                    bench.iter(|| codec.decode(b.clone()).unwrap())
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, codec);
+criterion_main!(benches);
@@ -0,0 +1,26 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use paxos::bench::{Bench, Scenario, Transport};
+
+static BATCH: u64 = 100;
+
+fn commit(c: &mut Criterion) {
+    let mut group = c.benchmark_group("commit");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(BATCH));
+
+    for transport in [Transport::InMem, Transport::Zmq] {
+        let mut bench = Bench::start(&Scenario {
+            transport: transport,
+            ..Scenario::default()
+        });
+        bench.round(BATCH);
+        group.bench_function(format!("{:?}", transport), |b| {
+            b.iter(|| bench.round(BATCH))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, commit);
+criterion_main!(benches);
@@ -1,15 +1,63 @@
 use super::{
-    constants::SLEEP_TIME,
+    admin::{self, Status},
+    constants::{P1B_PAGE_SIZE, SLEEP_TIME, SYNOD_SLOTS},
     ds::Accepted,
     env::{Env, Executor, ProcessId, Receiver, Router},
     message::Message,
-    pval::{BallotNumber, PValue},
+    metrics::{process, REGISTRY},
+    pval::{BallotNumber, Command, PValue, SlotNumber},
+    trace::{self, Stage},
 };
 
+/// Answers a P1A with the values accepted from slot `from` on, and the ballot
+/// adopted: a scout with a lower one is preempted. A scout of the log is not
+/// sent the values of synod instances.
+pub(crate) fn promise<T: Router, E: Env<T>>(
+    me: &ProcessId,
+    adopted: &BallotNumber,
+    accepted: &Accepted,
+    src: &ProcessId,
+    from: SlotNumber,
+    env: &E,
+) {
+    // a scout from before paging expects everything at once
+    let pages = match from {
+        0 => vec![accepted.clone()],
+        _ if from < SYNOD_SLOTS => accepted.pages(from..SYNOD_SLOTS, P1B_PAGE_SIZE),
+        _ => accepted.pages(from..SlotNumber::MAX, P1B_PAGE_SIZE),
+    };
+    let n = pages.len() as u64;
+    for (i, page) in pages.into_iter().enumerate() {
+        let m = Message::P1B(me.clone(), adopted.clone(), page, (i as u64, n));
+        env.router().send(src, m);
+    }
+}
+
+/// Answers a P2A, accepting `command` in `slot` if `ballot` is the one
+/// adopted, and tells whether it did.
+pub(crate) fn accept<T: Router, E: Env<T>>(
+    me: &ProcessId,
+    adopted: &BallotNumber,
+    accepted: &mut Accepted,
+    src: &ProcessId,
+    (ballot, slot, command): (BallotNumber, SlotNumber, Command),
+    env: &E,
+) -> bool {
+    let ok = *adopted == ballot;
+    if ok {
+        accepted.insert(slot, PValue::new(ballot, slot, command));
+    }
+    env.router()
+        .send(src, Message::P2B(me.clone(), adopted.clone(), slot));
+    ok
+}
+
 pub struct Acceptor {
     me: ProcessId,
     ballot: BallotNumber,
     accepted: Accepted,
+    // the ballot is fast from this slot on, replicas propose straight to us
+    fast: Option<SlotNumber>,
 }
 
 impl Acceptor {
@@ -18,6 +66,14 @@ impl Acceptor {
             me: id.clone(),
             ballot: BallotNumber::first(id),
             accepted: Accepted::new(),
+            fast: None,
+        }
+    }
+
+    fn adopt(&mut self, ballot: BallotNumber) {
+        if self.ballot < ballot {
+            self.ballot = ballot;
+            self.fast = None;
         }
     }
 }
@@ -28,30 +84,56 @@ impl Executor for Acceptor {
             let msg = reciever.get(SLEEP_TIME);
 
             match msg {
-                Message::P1A(src, ballot) => {
-                    let bc = ballot.clone();
-                    if self.ballot < ballot {
-                        self.ballot = ballot;
-                    }
-
-                    env.router().send(
-                        &src,
-                        Message::P1B(self.me.clone(), bc, self.accepted.clone()),
-                    );
+                Message::P1A(src, ballot, from) => {
+                    self.adopt(ballot);
+                    promise(&self.me, &self.ballot, &self.accepted, &src, from, env);
                 }
                 Message::P2A(src, ballot, slot, command) => {
+                    trace::record(Stage::P2A, &self.me, &command, Some(slot));
+                    self.adopt(ballot.clone());
+                    let p2a = (ballot, slot, command);
+                    if accept(&self.me, &self.ballot, &mut self.accepted, &src, p2a, env) {
+                        REGISTRY.set(
+                            "paxos_acceptor_accepted",
+                            &[("process", &process(&self.me))],
+                            self.accepted.len() as f64,
+                        );
+                    }
+                }
+                Message::FastAny(_, ballot, from) => {
                     if self.ballot <= ballot {
-                        self.ballot = ballot;
-                        let p = PValue::new(self.ballot.clone(), slot, command);
-                        self.accepted.insert(slot, p);
+                        self.adopt(ballot);
+                        self.fast = Some(from);
+                    }
+                }
+                Message::FastRequest(_, slot, command) => {
+                    // the first value of a slot in the fast ballot is the one
+                    // accepted, the leader sorts out collisions; requests
+                    // outside of a fast ballot are left to the replica to retry
+                    // with the leaders
+                    if self.fast.map_or(false, |from| slot >= from) {
+                        let ballot = self.ballot.clone();
+                        if self.accepted.get(&slot).map_or(true, |p| p.ballot < ballot) {
+                            trace::record(Stage::P2A, &self.me, &command, Some(slot));
+                            let p = PValue::new(ballot.clone(), slot, command);
+                            self.accepted.insert(slot, p);
+                        }
+                        let command = self.accepted.get(&slot).unwrap().command.clone();
+                        let m =
+                            Message::FastAccepted(self.me.clone(), ballot.clone(), slot, command);
+                        env.router().send(&ballot.process_id, m);
                     }
-                    env.router().send(
-                        &src,
-                        Message::P2B(self.me.clone(), self.ballot.clone(), slot),
-                    )
                 }
                 _ => panic!("unexpected message"),
             }
+            admin::publish(
+                &self.me,
+                Status::Acceptor {
+                    ballot: self.ballot.clone(),
+                    accepted: self.accepted.len(),
+                    range: self.accepted.range(),
+                },
+            );
         }
     }
 }
@@ -0,0 +1,363 @@
+use std::{collections::BTreeMap, fmt::Write, fs, io, path::PathBuf, sync::Mutex, thread};
+
+use log::warn;
+use once_cell::sync::Lazy;
+
+use super::{
+    env::{Cluster, Env, ProcessId, Router},
+    http::{self, Response},
+    message::Message,
+    metrics::process,
+    pval::{BallotNumber, Command, SlotNumber},
+};
+
+/// What a local role last reported about itself.
+#[derive(Clone, Debug)]
+pub enum Status {
+    Leader {
+        ballot: BallotNumber,
+        active: bool,
+        // how many proposals are pending, and the slots of the first ones
+        pending: usize,
+        oldest: Vec<SlotNumber>,
+    },
+    Acceptor {
+        ballot: BallotNumber,
+        accepted: usize,
+        range: Option<(SlotNumber, SlotNumber)>,
+    },
+    Replica {
+        slot: SlotNumber,
+        backlog: usize,
+    },
+}
+
+/// The decided log of a replica, every command below `slot` has been performed.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub slot: SlotNumber,
+    pub log: Vec<Command>,
+}
+
+static STATUS: Lazy<Mutex<BTreeMap<ProcessId, Status>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+static SNAPSHOTS: Lazy<Mutex<BTreeMap<ProcessId, Snapshot>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+static STORAGE: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+/// Also write every snapshot to `dir`, one file per replica.
+pub fn persist_to(dir: PathBuf) {
+    *STORAGE.lock().unwrap() = Some(dir);
+}
+
+pub fn publish(id: &ProcessId, s: Status) {
+    STATUS.lock().unwrap().insert(id.clone(), s);
+}
+
+pub fn store_snapshot(id: &ProcessId, s: Snapshot) {
+    if let Some(dir) = STORAGE.lock().unwrap().as_ref() {
+        let path = dir.join(format!("replica-{}-{}.snapshot", id.port, id.id));
+        if let Err(e) = fs::write(&path, encode(&s)) {
+            warn!("unable to write snapshot {}: {}", path.display(), e);
+        }
+    }
+    SNAPSHOTS.lock().unwrap().insert(id.clone(), s);
+}
+
+/// The next slot on the first line then one `client req_id operation` line
+/// per performed command.
+fn encode(s: &Snapshot) -> String {
+    let mut out = format!("{}\n", s.slot);
+    for c in s.log.iter() {
+        let _ = writeln!(
+            out,
+            "{} {} {}",
+            process(&c.client),
+            String::from_utf8_lossy(&c.req_id),
+            String::from_utf8_lossy(&c.operation)
+        );
+    }
+    out
+}
+
+pub fn snapshot(id: &ProcessId) -> Option<Snapshot> {
+    SNAPSHOTS.lock().unwrap().get(id).cloned()
+}
+
+fn ballot(b: &BallotNumber) -> String {
+    format!("{}@{}", b.round, process(&b.process_id))
+}
+
+fn ids(v: &[ProcessId]) -> String {
+    let v: Vec<String> = v.iter().map(process).collect();
+    v.join(",")
+}
+
+pub fn render(cluster: &Cluster) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "cluster acceptors={}", ids(&cluster.acceptors()));
+    let _ = writeln!(out, "cluster leaders={}", ids(&cluster.leaders()));
+    let _ = writeln!(out, "cluster replicas={}", ids(&cluster.replicas()));
+
+    for (id, s) in STATUS.lock().unwrap().iter() {
+        let _ = match s {
+            Status::Leader {
+                ballot: b,
+                active,
+                pending,
+                oldest,
+            } => {
+                let shown: Vec<String> = oldest.iter().map(|s| s.to_string()).collect();
+                writeln!(
+                    out,
+                    "leader {} ballot={} active={} pending={} [{}]",
+                    process(id),
+                    ballot(b),
+                    active,
+                    pending,
+                    shown.join(",")
+                )
+            }
+            Status::Acceptor {
+                ballot: b,
+                accepted,
+                range,
+            } => writeln!(
+                out,
+                "acceptor {} ballot={} accepted={} slots={}",
+                process(id),
+                ballot(b),
+                accepted,
+                range.map_or(String::from("-"), |(l, h)| format!("{}..{}", l, h))
+            ),
+            Status::Replica { slot, backlog } => writeln!(
+                out,
+                "replica {} slot={} backlog={} snapshot={}",
+                process(id),
+                slot,
+                backlog,
+                snapshot(id).map_or(String::from("-"), |s| s.slot.to_string())
+            ),
+        };
+    }
+    out
+}
+
+fn local(filter: fn(&Status) -> bool) -> Vec<ProcessId> {
+    STATUS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, s)| filter(s))
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+/// Answers a request to the admin endpoint of `me`:
+///
+/// * `GET /status` reports the membership and the state of every local role
+/// * `POST /leader/<id>/step-down` makes the local leader with that id step down
+/// * `POST /snapshot` has every local replica take a snapshot of its log
+fn route<T: Router, E: Env<T>>(method: &str, path: &str, me: &ProcessId, env: &E) -> Response {
+    let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, parts.as_slice()) {
+        ("GET", ["status"]) => Response::ok(render(env.cluster())),
+        ("POST", ["leader", id, "step-down"]) => {
+            let leader = local(|s| matches!(s, Status::Leader { .. }))
+                .into_iter()
+                .find(|l| l.id.to_string() == *id);
+            match leader {
+                Some(l) => {
+                    env.router().send(&l, Message::StepDown(me.clone()));
+                    Response::ok(format!("step down sent to {}\n", process(&l)))
+                }
+                None => Response::not_found(),
+            }
+        }
+        ("POST", ["snapshot"]) => {
+            let replicas = local(|s| matches!(s, Status::Replica { .. }));
+            for r in replicas.iter() {
+                env.router().send(r, Message::Snapshot(me.clone()));
+            }
+            Response::ok(format!("snapshot sent to {}\n", ids(&replicas)))
+        }
+        _ => Response::not_found(),
+    }
+}
+
+/// Serves the admin endpoint of this node on `addr` from a background thread,
+/// once bound, see `route` for what it answers.
+pub fn serve<T, E>(addr: &str, me: ProcessId, env: &'static E) -> io::Result<()>
+where
+    T: Router,
+    E: Env<T> + Sync,
+{
+    let listener = http::bind(addr)?;
+    thread::spawn(move || http::serve(listener, |method, path| route(method, path, &me, env)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env::{Env, ProcessType},
+        fixture,
+        message::Message,
+        metrics::process,
+        pval::{BallotNumber, Command},
+    };
+
+    use super::{publish, render, route, store_snapshot, Snapshot, Status};
+
+    #[test]
+    fn status() {
+        let env = fixture::env();
+        let acceptors = fixture::acceptors(env, 2);
+        let (leader, _) = fixture::probe(env, ProcessType::Leader);
+        let (replica, _) = fixture::probe(env, ProcessType::Replica);
+        let ballot = BallotNumber::new(2, leader.clone());
+        publish(
+            &leader,
+            Status::Leader {
+                ballot: ballot.clone(),
+                active: true,
+                pending: 3,
+                oldest: vec![4, 5],
+            },
+        );
+        publish(
+            &acceptors[0],
+            Status::Acceptor {
+                ballot: ballot.clone(),
+                accepted: 2,
+                range: Some((4, 5)),
+            },
+        );
+        publish(
+            &acceptors[1],
+            Status::Acceptor {
+                ballot: ballot.clone(),
+                accepted: 0,
+                range: None,
+            },
+        );
+        publish(
+            &replica,
+            Status::Replica {
+                slot: 4,
+                backlog: 1,
+            },
+        );
+        let c = Command::new_from_str(replica.clone(), format!("1"), format!("PUT a 1"));
+        store_snapshot(
+            &replica,
+            Snapshot {
+                slot: 2,
+                log: vec![c],
+            },
+        );
+
+        // the status of roles of other tests is in there too
+        let out = render(env.cluster());
+        let lines: Vec<&str> = out.lines().collect();
+        let p = process;
+        let b = format!("2@{}", p(&leader));
+        for line in [
+            format!(
+                "cluster acceptors={},{}",
+                p(&acceptors[0]),
+                p(&acceptors[1])
+            ),
+            format!("cluster leaders={}", p(&leader)),
+            format!("cluster replicas={}", p(&replica)),
+            format!(
+                "leader {} ballot={} active=true pending=3 [4,5]",
+                p(&leader),
+                b
+            ),
+            format!(
+                "acceptor {} ballot={} accepted=2 slots=4..5",
+                p(&acceptors[0]),
+                b
+            ),
+            format!(
+                "acceptor {} ballot={} accepted=0 slots=-",
+                p(&acceptors[1]),
+                b
+            ),
+            format!("replica {} slot=4 backlog=1 snapshot=2", p(&replica)),
+        ] {
+            assert!(lines.contains(&line.as_str()), "{} missing", line);
+        }
+        assert!(lines[..3].iter().all(|l| l.starts_with("cluster ")));
+
+        let res = route("GET", "/status", &leader, env);
+        assert_eq!(res.status, 200);
+        assert!(res.body.starts_with("cluster acceptors="));
+    }
+
+    #[test]
+    fn routes() {
+        let env = fixture::env();
+        let me = fixture::new_id(env);
+        let (leader, l) = fixture::probe(env, ProcessType::Leader);
+        let (replica, r) = fixture::probe(env, ProcessType::Replica);
+        publish(
+            &leader,
+            Status::Leader {
+                ballot: BallotNumber::new(1, leader.clone()),
+                active: true,
+                pending: 0,
+                oldest: vec![],
+            },
+        );
+        publish(
+            &replica,
+            Status::Replica {
+                slot: 1,
+                backlog: 0,
+            },
+        );
+
+        let step_down = format!("/leader/{}/step-down", leader.id);
+        let res = route("POST", &step_down, &me, env);
+        assert_eq!(res.status, 200);
+        assert_eq!(
+            res.body,
+            format!("step down sent to {}\n", process(&leader))
+        );
+        match l.recv_timeout(fixture::WAIT) {
+            Ok(Message::StepDown(src)) => assert_eq!(src, me),
+            m => panic!("unexpected {:?}", m),
+        }
+        // a replica is no leader, and an unknown id none either
+        let unknown = [replica.id, fixture::new_id(env).id];
+        for id in unknown {
+            let path = format!("/leader/{}/step-down", id);
+            assert_eq!(route("POST", &path, &me, env).status, 404);
+        }
+
+        let res = route("POST", "/snapshot", &me, env);
+        assert_eq!(res.status, 200);
+        assert!(res.body.contains(&process(&replica)));
+        assert!(!res.body.contains(&process(&leader)));
+        match r.recv_timeout(fixture::WAIT) {
+            Ok(Message::Snapshot(src)) => assert_eq!(src, me),
+            m => panic!("unexpected {:?}", m),
+        }
+
+        // the wrong method, or a path that is not served
+        for (method, path) in [
+            ("GET", step_down.as_str()),
+            ("GET", "/snapshot"),
+            ("POST", "/status"),
+            ("GET", "/"),
+            ("GET", "/leader"),
+            ("GET", "/status/more"),
+        ] {
+            let res = route(method, path, &me, env);
+            assert_eq!(res.status, 404, "{} {}", method, path);
+            assert_eq!(res.body, "not found\n");
+        }
+        assert!(l.try_recv().is_err() && r.try_recv().is_err());
+    }
+}
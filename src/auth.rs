@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::env::ProcessId;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, PartialEq)]
+pub enum KeyErr {
+    Hex(String),
+    Empty,
+}
+
+/// Pre-shared HMAC-SHA256 keys of the nodes of a cluster. A message is signed
+/// with the key of the process it claims to come from, so a receiver holding
+/// the same keyring can tell whether that node really sent it.
+///
+/// Keys are registered per `ip:port` endpoint, or per `ip` for every endpoint
+/// on that host, which suits clients that bind a new port each run.
+#[derive(Clone, Default)]
+pub struct Keyring {
+    keys: HashMap<String, Vec<u8>>,
+}
+
+impl Keyring {
+    pub fn new() -> Keyring {
+        Keyring {
+            keys: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, endpoint: &str, key: Vec<u8>) -> Result<(), KeyErr> {
+        if key.is_empty() {
+            return Err(KeyErr::Empty);
+        }
+        self.keys.insert(endpoint.to_string(), key);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn key(&self, id: &ProcessId) -> Option<&Vec<u8>> {
+        self.keys
+            .get(&format!("{}:{}", id.ip, id.port))
+            .or_else(|| self.keys.get(&id.ip.to_string()))
+    }
+
+    fn mac(&self, id: &ProcessId, payload: &[u8]) -> Option<HmacSha256> {
+        let mut mac = HmacSha256::new_from_slice(self.key(id)?).unwrap();
+        mac.update(payload);
+        Some(mac)
+    }
+
+    /// Signature of `payload` sent by `id`, `None` if there is no key for it.
+    pub fn sign(&self, id: &ProcessId, payload: &[u8]) -> Option<Vec<u8>> {
+        self.mac(id, payload)
+            .map(|m| m.finalize().into_bytes().to_vec())
+    }
+
+    pub fn verify(&self, id: &ProcessId, payload: &[u8], tag: &[u8]) -> bool {
+        match self.mac(id, payload) {
+            // constant time comparison
+            Some(m) => m.verify_slice(tag).is_ok(),
+            None => false,
+        }
+    }
+}
+
+pub fn decode_hex(s: &str) -> Result<Vec<u8>, KeyErr> {
+    let s = s.trim();
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return Err(KeyErr::Hex(s.to_string()));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| KeyErr::Hex(s.to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use crate::env::ProcessId;
+
+    use super::{decode_hex, KeyErr, Keyring};
+
+    #[test]
+    fn sign_verify() {
+        let local_host = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let node = ProcessId::new(local_host, 6060, 1);
+        let other = ProcessId::new(local_host, 6061, 1);
+        let client = ProcessId::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9)), 7200, 0);
+
+        let mut k = Keyring::new();
+        k.add("127.0.0.1:6060", decode_hex("00ff10").unwrap())
+            .unwrap();
+        k.add("127.0.0.1:6061", b"other".to_vec()).unwrap();
+        k.add("10.0.0.9", b"client".to_vec()).unwrap();
+
+        let tag = k.sign(&node, b"payload").unwrap();
+        assert!(k.verify(&node, b"payload", &tag));
+        assert!(!k.verify(&node, b"tampered", &tag));
+        // a node cannot pass for another one
+        assert!(!k.verify(&other, b"payload", &tag));
+
+        let tag = k.sign(&client, b"payload").unwrap();
+        assert!(k.verify(&client, b"payload", &tag));
+
+        let unknown = ProcessId::new(local_host, 9999, 0);
+        assert_eq!(k.sign(&unknown, b"payload"), None);
+        assert!(!k.verify(&unknown, b"payload", &tag));
+
+        assert_eq!(decode_hex("0g"), Err(KeyErr::Hex(String::from("0g"))));
+        assert_eq!(k.add("x", vec![]), Err(KeyErr::Empty));
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use crossbeam::channel;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    acceptor::Acceptor,
+    client,
+    codec::Format,
+    constants::{CLIENT_RETRY_MS, CLIENT_TIMEOUT_MS},
+    env::{Env, Executor, ProcessId, ProcessType, Receiver, Router},
+    leader::Leader,
+    local::InMemEnv,
+    message::Message,
+    pval::Command,
+    replica::Replica,
+    state::Op,
+    zmq::ZMQEnv,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    InMem,
+    Zmq,
+}
+
+/// A cluster layout and the load to put on it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Scenario {
+    pub name: String,
+    pub transport: Transport,
+    pub acceptors: u32,
+    pub leaders: u32,
+    pub replicas: u32,
+    /// Clients sending concurrently, each waits for the response to its
+    /// command before sending the next one.
+    pub clients: u32,
+    pub requests: u64,
+    /// Requests sent before measuring, not part of the report.
+    pub warmup: u64,
+    pub payload: usize,
+    /// Where the `ZMQEnv` of the cluster listens.
+    pub port: u32,
+    /// How the `ZMQEnv` encodes messages.
+    pub codec: Format,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            name: String::from("default"),
+            transport: Transport::InMem,
+            acceptors: 3,
+            leaders: 1,
+            replicas: 2,
+            clients: 4,
+            requests: 1000,
+            warmup: 100,
+            payload: 16,
+            port: 7700,
+            codec: Format::Protobuf,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Latency {
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Report {
+    pub scenario: Scenario,
+    pub completed: u64,
+    pub failed: u64,
+    pub elapsed_ms: u64,
+    /// Committed commands per second.
+    pub throughput: f64,
+    pub latency: Latency,
+}
+
+/// Element at `p` (0 to 1) of the sorted samples.
+pub fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let i = ((sorted.len() as f64 - 1.0) * p).round() as usize;
+    sorted[i]
+}
+
+/// A client running inside the env, sends the operations it is handed one
+/// at a time and reports how long each took to be committed and applied.
+struct Driver {
+    me: ProcessId,
+    work: channel::Receiver<Bytes>,
+    done: channel::Sender<Option<Duration>>,
+}
+
+impl Executor for Driver {
+    fn exec<R: Receiver, T: Router, E: Env<T>>(self, reciever: R, env: &'static E) {
+        let mut n: u64 = 0;
+        for op in self.work.iter() {
+            n += 1;
+            let c = Command {
+                client: self.me.clone(),
+                req_id: Bytes::from(n.to_string()),
+                operation: op,
+            };
+            let sent = Instant::now();
+            let res = client::call(
+                &env.cluster().replicas(),
+                env.router(),
+                &reciever,
+                c,
+                Duration::from_millis(CLIENT_RETRY_MS),
+                Duration::from_millis(CLIENT_TIMEOUT_MS),
+            );
+            let _ = self.done.send(res.ok().map(|_| sent.elapsed()));
+        }
+    }
+}
+
+/// A running cluster of a `Scenario`. Envs live for the rest of the process,
+/// so start one per transport and reuse it across rounds.
+pub struct Bench {
+    work: channel::Sender<Bytes>,
+    done: channel::Receiver<Option<Duration>>,
+    payload: Bytes,
+    next: u64,
+}
+
+impl Bench {
+    pub fn start(s: &Scenario) -> Bench {
+        let local_host = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        match s.transport {
+            Transport::InMem => {
+                let env = Box::leak(Box::new(InMemEnv::new(|| {
+                    let (s, r) = channel::unbounded::<Message>();
+                    return (r, s);
+                })));
+                Self::setup(s, env, local_host)
+            }
+            Transport::Zmq => {
+                let env = Box::leak(Box::new(ZMQEnv::with_codec(
+                    || {
+                        let (s, r) = channel::unbounded::<Message>();
+                        return (r, s);
+                    },
+                    s.codec.codec(),
+                    None,
+                )));
+                let addr = ProcessId::new(local_host, s.port, 0).addr_sender();
+                let poller = &env.poller;
+                thread::spawn(move || poller.start(&addr));
+                thread::sleep(Duration::from_millis(100));
+                Self::setup(s, env, local_host)
+            }
+        }
+    }
+
+    fn setup<T: Router, E: Env<T>>(s: &Scenario, env: &'static E, ip: IpAddr) -> Bench {
+        let new_id = || ProcessId::new(ip, s.port, env.new_id());
+        for _ in 0..s.acceptors {
+            let id = new_id();
+            env.register(id.clone(), ProcessType::Acceptor, Acceptor::new(id));
+        }
+        for _ in 0..s.replicas {
+            let id = new_id();
+            env.register(id.clone(), ProcessType::Replica, Replica::new(id));
+        }
+        for _ in 0..s.leaders {
+            let id = new_id();
+            env.register(id.clone(), ProcessType::Leader, Leader::new(id));
+        }
+
+        let (work, work_r) = channel::unbounded();
+        let (done_s, done) = channel::unbounded();
+        for _ in 0..s.clients {
+            let id = new_id();
+            let driver = Driver {
+                me: id.clone(),
+                work: work_r.clone(),
+                done: done_s.clone(),
+            };
+            env.register(id, ProcessType::Client, driver);
+        }
+
+        Bench {
+            work,
+            done,
+            payload: Bytes::from("x".repeat(s.payload)),
+            next: 0,
+        }
+    }
+
+    /// Commits `n` new commands and returns the latency of each, `None` for
+    /// the ones that timed out.
+    pub fn round(&mut self, n: u64) -> Vec<Option<Duration>> {
+        for _ in 0..n {
+            self.next += 1;
+            let op = Op::Put(format!("bench-{}", self.next), self.payload.clone());
+            self.work.send(op.encode()).unwrap();
+        }
+        (0..n).map(|_| self.done.recv().unwrap()).collect()
+    }
+}
+
+pub fn run(s: &Scenario) -> Report {
+    let mut bench = Bench::start(s);
+    bench.round(s.warmup);
+
+    let start = Instant::now();
+    let res = bench.round(s.requests);
+    let elapsed = start.elapsed();
+
+    let mut latencies: Vec<Duration> = res.iter().flatten().cloned().collect();
+    latencies.sort();
+    let us = |p| percentile(&latencies, p).as_micros() as u64;
+
+    Report {
+        scenario: s.clone(),
+        completed: latencies.len() as u64,
+        failed: s.requests - latencies.len() as u64,
+        elapsed_ms: elapsed.as_millis() as u64,
+        throughput: latencies.len() as f64 / elapsed.as_secs_f64(),
+        latency: Latency {
+            p50_us: us(0.5),
+            p90_us: us(0.9),
+            p99_us: us(0.99),
+            max_us: us(1.0),
+        },
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::Path,
+    process::{self, Command},
+};
+
+use serde::Deserialize;
+
+use paxos::bench::{self, Report, Scenario, Transport};
+
+static USAGE: &str =
+    "usage: paxos-bench [--scenarios <file>] [--out <results.json>] [--baseline <results.json>]
+
+Runs every scenario in its own process and writes the reports as a JSON array,
+to stdout without --out. Scenarios are read from TOML (`[[scenario]]` tables)
+or JSON, see `bench::Scenario` for the fields. A summary of each report goes
+to stderr, and with --baseline the change in throughput and p99 latency
+against an earlier run.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+#[derive(Deserialize)]
+struct Scenarios {
+    scenario: Vec<Scenario>,
+}
+
+fn defaults() -> Vec<Scenario> {
+    let mut res = vec![];
+    for transport in [Transport::InMem, Transport::Zmq] {
+        for (acceptors, replicas) in [(3, 2), (5, 3)] {
+            res.push(Scenario {
+                name: format!("{:?}-{}a-1l-{}r", transport, acceptors, replicas).to_lowercase(),
+                transport,
+                acceptors,
+                replicas,
+                ..Scenario::default()
+            });
+        }
+    }
+    res
+}
+
+fn load<T: serde::de::DeserializeOwned>(path: &str) -> T {
+    let s = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("unable to read {}: {}", path, e);
+        process::exit(1);
+    });
+    let res = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("json") => serde_json::from_str(&s).map_err(|e| e.to_string()),
+        _ => toml::from_str(&s).map_err(|e| e.to_string()),
+    };
+    res.unwrap_or_else(|e| {
+        eprintln!("unable to parse {}: {}", path, e);
+        process::exit(1);
+    })
+}
+
+/// Runs `s` in a child process, envs never shut down so each scenario gets a
+/// fresh one.
+fn spawn(s: &Scenario) -> Option<Report> {
+    let exe = env::current_exe().unwrap();
+    let out = Command::new(exe)
+        .arg("--run")
+        .arg(serde_json::to_string(s).unwrap())
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        eprintln!(
+            "scenario {} failed: {}",
+            s.name,
+            String::from_utf8_lossy(&out.stderr)
+        );
+        return None;
+    }
+    serde_json::from_slice(&out.stdout).ok()
+}
+
+fn change(new: f64, old: f64) -> String {
+    if old == 0.0 {
+        return String::from("-");
+    }
+    format!("{:+.1}%", (new - old) / old * 100.0)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opt = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .map(|i| args.get(i + 1).cloned().unwrap_or_else(|| usage()))
+    };
+
+    if let Some(s) = opt("--run") {
+        let s: Scenario = serde_json::from_str(&s).unwrap_or_else(|_| usage());
+        println!("{}", serde_json::to_string(&bench::run(&s)).unwrap());
+        process::exit(0);
+    }
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        usage();
+    }
+
+    let scenarios = match opt("--scenarios") {
+        Some(path) => load::<Scenarios>(&path).scenario,
+        None => defaults(),
+    };
+
+    let mut reports = vec![];
+    for s in scenarios.iter() {
+        if let Some(r) = spawn(s) {
+            eprintln!(
+                "{:<24} {:>10.1} ops/s  p50 {:>8}us  p99 {:>8}us  failed {}",
+                r.scenario.name, r.throughput, r.latency.p50_us, r.latency.p99_us, r.failed
+            );
+            reports.push(r);
+        }
+    }
+
+    let json = serde_json::to_string_pretty(&reports).unwrap();
+    match opt("--out") {
+        Some(path) => fs::write(&path, json).unwrap_or_else(|e| {
+            eprintln!("unable to write {}: {}", path, e);
+            process::exit(1);
+        }),
+        None => println!("{}", json),
+    }
+
+    if let Some(path) = opt("--baseline") {
+        let old: HashMap<String, Report> = load::<Vec<Report>>(&path)
+            .into_iter()
+            .map(|r| (r.scenario.name.clone(), r))
+            .collect();
+        for r in reports.iter() {
+            if let Some(o) = old.get(&r.scenario.name) {
+                eprintln!(
+                    "{:<24} throughput {:>8}  p99 {:>8}",
+                    r.scenario.name,
+                    change(r.throughput, o.throughput),
+                    change(r.latency.p99_us as f64, o.latency.p99_us as f64)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    path::Path,
+    process,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+
+use paxos::{
+    auth::Keyring,
+    bench::percentile,
+    client::{Client, Reply},
+    codec::Format,
+    config::{self, NodeConfig},
+    env::ProcessId,
+    state::Op,
+};
+
+static USAGE: &str = "usage: paxos-cli --config <node config> [--bind ip:port] <command>
+
+commands:
+  exec <operation...>       send one operation, e.g. `exec PUT key value`
+  shell                     read operations from stdin, one per line
+  bench [--clients N] [--rate OPS] [--size BYTES] [--duration SECS]
+                            PUT random keys and report throughput and latency";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+struct Args {
+    config: Option<String>,
+    bind: String,
+    command: Vec<String>,
+}
+
+fn parse() -> Args {
+    let mut args = Args {
+        config: None,
+        bind: String::from("127.0.0.1:7200"),
+        command: vec![],
+    };
+    let mut it = env::args().skip(1);
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--config" => args.config = Some(it.next().unwrap_or_else(|| usage())),
+            "--bind" => args.bind = it.next().unwrap_or_else(|| usage()),
+            "-h" | "--help" => usage(),
+            _ => {
+                args.command.push(a);
+                args.command.extend(it.by_ref());
+            }
+        }
+    }
+    args
+}
+
+fn flag<T: std::str::FromStr>(opts: &[String], name: &str, default: T) -> T {
+    match opts.iter().position(|o| o == name) {
+        Some(i) => opts
+            .get(i + 1)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(|| usage()),
+        None => default,
+    }
+}
+
+fn connect(
+    me: ProcessId,
+    replicas: Vec<ProcessId>,
+    codec: Format,
+    keyring: &Option<Keyring>,
+) -> Client {
+    Client::with_codec(me, replicas, codec.codec(), keyring.clone())
+}
+
+fn print(r: &Reply) {
+    println!("{} (slot {})", String::from_utf8_lossy(&r.result), r.slot);
+}
+
+fn exec(client: &mut Client, line: &str) {
+    if Op::parse(line.as_bytes()).is_none() {
+        eprintln!("expected GET <key>, PUT <key> <value> or DEL <key>");
+        return;
+    }
+    match client.call(Bytes::from(line.trim().to_string())) {
+        Ok(r) => print(&r),
+        Err(e) => eprintln!("error: {:?}", e),
+    }
+}
+
+fn shell(client: &mut Client) {
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) if line.trim().is_empty() => {}
+            Ok(_) => exec(client, &line),
+        }
+    }
+}
+
+/// Runs `clients` closed loop clients on consecutive ports from `bind`, each
+/// sending at most its share of `rate` operations per second (0 for as fast
+/// as possible).
+fn bench(
+    bind: &str,
+    replicas: Vec<ProcessId>,
+    codec: Format,
+    keyring: Option<Keyring>,
+    opts: &[String],
+) {
+    let clients: u32 = flag(opts, "--clients", 1);
+    let rate: f64 = flag(opts, "--rate", 0.0);
+    let size: usize = flag(opts, "--size", 16);
+    let duration = Duration::from_secs(flag(opts, "--duration", 10));
+
+    let (ip, port) = config::endpoint(bind).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(2);
+    });
+    let value = "x".repeat(size);
+    let interval = if rate > 0.0 {
+        Some(Duration::from_secs_f64(clients as f64 / rate))
+    } else {
+        None
+    };
+
+    let (s, r) = mpsc::channel();
+    let start = Instant::now();
+    for i in 0..clients {
+        let s = s.clone();
+        let replicas = replicas.clone();
+        let value = value.clone();
+        let keyring = keyring.clone();
+        thread::spawn(move || {
+            let me = ProcessId::new(ip, port + i, 0);
+            let mut client = connect(me, replicas, codec, &keyring);
+            let mut next = Instant::now();
+            let mut n = 0;
+            while start.elapsed() < duration {
+                if let Some(interval) = interval {
+                    thread::sleep(next.saturating_duration_since(Instant::now()));
+                    next += interval;
+                }
+                n += 1;
+                let op = Op::Put(format!("bench-{}-{}", i, n), Bytes::from(value.clone()));
+                let sent = Instant::now();
+                let res = client.call(op.encode()).map(|_| sent.elapsed());
+                let _ = s.send(res.ok());
+            }
+        });
+    }
+    drop(s);
+
+    let mut latencies = vec![];
+    let mut failed = 0;
+    for res in r.iter() {
+        match res {
+            Some(l) => latencies.push(l),
+            None => failed += 1,
+        }
+    }
+    let elapsed = start.elapsed();
+    latencies.sort();
+
+    println!("clients      {}", clients);
+    println!("completed    {}", latencies.len());
+    println!("failed       {}", failed);
+    println!(
+        "throughput   {:.1} ops/s",
+        latencies.len() as f64 / elapsed.as_secs_f64()
+    );
+    for (name, p) in [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("max", 1.0)] {
+        println!("latency {}  {:?}", name, percentile(&latencies, p));
+    }
+}
+
+fn main() {
+    let args = parse();
+    let config = match args.config.as_ref() {
+        Some(c) => NodeConfig::load(Path::new(c)).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        }),
+        None => usage(),
+    };
+    let replicas = config.replicas();
+    let keyring = config.keyring().unwrap();
+    if replicas.is_empty() {
+        eprintln!("the cluster has no replicas");
+        process::exit(1);
+    }
+
+    let client = || {
+        let (ip, port) = config::endpoint(&args.bind).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(2);
+        });
+        connect(
+            ProcessId::new(ip, port, 0),
+            replicas.clone(),
+            config.codec,
+            &keyring,
+        )
+    };
+
+    match args.command.split_first() {
+        Some((c, op)) if c == "exec" && !op.is_empty() => exec(&mut client(), &op.join(" ")),
+        Some((c, _)) if c == "shell" => shell(&mut client()),
+        Some((c, opts)) if c == "bench" => bench(
+            &args.bind,
+            replicas.clone(),
+            config.codec,
+            keyring.clone(),
+            opts,
+        ),
+        _ => usage(),
+    }
+}
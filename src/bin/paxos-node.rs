@@ -0,0 +1,140 @@
+use std::{env, fs, path::Path, process, sync::mpsc, thread};
+
+use log::{error, info, Level, LevelFilter, Metadata, Record};
+
+use paxos::{
+    acceptor::Acceptor,
+    admin,
+    config::{self, NodeConfig},
+    env::{Env, ProcessId, ProcessType},
+    epaxos,
+    leader::Leader,
+    lock::LockService,
+    message::Message,
+    metrics,
+    replica::Replica,
+    vertical::ConfigMaster,
+    witness::Witness,
+    zmq::ZMQEnv,
+};
+
+type NodeEnv = ZMQEnv<crossbeam::channel::Receiver<Message>, crossbeam::channel::Sender<Message>>;
+
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{} - {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+fn main() {
+    let _ = log::set_logger(&LOGGER).map(|()| log::set_max_level(LevelFilter::Info));
+
+    let path = match env::args().nth(1) {
+        Some(p) => p,
+        None => {
+            eprintln!("usage: paxos-node <config.toml|config.json>");
+            process::exit(2);
+        }
+    };
+    let config = NodeConfig::load(Path::new(&path)).unwrap_or_else(|e| {
+        error!("{}", e);
+        process::exit(1);
+    });
+
+    if let Some(dir) = config.storage.as_ref() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            error!("unable to create storage dir {}: {}", dir.display(), e);
+            process::exit(1);
+        }
+        admin::persist_to(dir.clone());
+    }
+
+    let new_channel = || {
+        let (s, r) = crossbeam::channel::unbounded();
+        return (r, s);
+    };
+    let env: &'static NodeEnv = Box::leak(Box::new(ZMQEnv::with_codec(
+        new_channel,
+        config.codec.codec(),
+        config.keyring().unwrap(),
+    )));
+
+    let (ip, port) = config::endpoint(&config.endpoint).unwrap();
+    let addr = ProcessId::new(ip, port, 0).addr_sender();
+    thread::spawn(move || env.poller.start(&addr));
+
+    // peers first, a leader starts scouting as soon as it is registered
+    for (t, id) in config.remote() {
+        env.cluster().add(t, id);
+    }
+    for (t, id) in config.local() {
+        // keep the id generator in step, scouts and commanders take the next ids
+        assert_eq!(env.new_id(), id.id);
+        info!("starting {:?} {}", t, id);
+        match t {
+            ProcessType::Acceptor => env.register(id.clone(), t, Acceptor::new(id)),
+            ProcessType::Leader => env.register(
+                id.clone(),
+                t,
+                Leader::with_config(id, config.leader_config()),
+            ),
+            ProcessType::Witness => env.register(id.clone(), t, Witness::new(id)),
+            ProcessType::Replica if config.master => {
+                let state = Box::new(ConfigMaster::new());
+                env.register(id.clone(), t, Replica::with_state(id, state))
+            }
+            ProcessType::Replica if config.locks => {
+                let state = Box::new(LockService::new());
+                env.register(id.clone(), t, Replica::with_state(id, state))
+            }
+            ProcessType::Replica if config.epaxos => {
+                env.register(id.clone(), t, epaxos::Replica::new(id))
+            }
+            ProcessType::Replica => {
+                let replica = Replica::new(id.clone());
+                match (config.mencius, config.fast) {
+                    (true, _) => env.register(id, t, replica.mencius()),
+                    (_, true) => env.register(id, t, replica.fast()),
+                    _ => env.register(id, t, replica),
+                }
+            }
+            _ => panic!("unexpected"),
+        }
+    }
+
+    if let Some(addr) = config.metrics.as_ref() {
+        if let Err(e) = metrics::serve(addr) {
+            error!("unable to serve metrics on {}: {}", addr, e);
+            process::exit(1);
+        }
+    }
+    if let Some(addr) = config.admin.as_ref() {
+        let me = ProcessId::new(ip, port, env.new_id());
+        if let Err(e) = admin::serve(addr, me, env) {
+            error!("unable to serve the admin endpoint on {}: {}", addr, e);
+            process::exit(1);
+        }
+    }
+
+    let (s, r) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = s.send(());
+    })
+    .expect("unable to install the signal handler");
+
+    info!("node {} running", config.endpoint);
+    let _ = r.recv();
+    info!("node {} shutting down", config.endpoint);
+}
@@ -0,0 +1,148 @@
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use crossbeam::channel;
+
+use super::{
+    auth::Keyring,
+    codec::{Codec, ProtobufCodec},
+    constants::{CLIENT_RETRY_MS, CLIENT_TIMEOUT_MS, SLEEP_TIME},
+    env::{GetErr, ProcessId, Receiver, Router},
+    message::Message,
+    pval::{Command, SlotNumber},
+    zmq::{Wire, ZMQPoller, ZMQRouter},
+};
+
+#[derive(Debug)]
+pub enum ClientErr {
+    Timeout,
+}
+
+/// What a replica answered to a command.
+#[derive(Clone, Debug)]
+pub struct Reply {
+    pub replica: ProcessId,
+    pub slot: SlotNumber,
+    pub result: Bytes,
+}
+
+/// Sends commands to the replicas of a cluster over ZMQ, one at a time, and
+/// waits for the first response. Responses arrive on `me`, whose endpoint the
+/// client binds.
+pub struct Client {
+    me: ProcessId,
+    replicas: Vec<ProcessId>,
+    router: ZMQRouter,
+    responses: channel::Receiver<Message>,
+    // keeps request ids unique across restarts of a client on the same endpoint
+    session: u128,
+    next: u64,
+    pub retry: Duration,
+    pub timeout: Duration,
+}
+
+impl Client {
+    pub fn connect(me: ProcessId, replicas: Vec<ProcessId>) -> Client {
+        Self::with_codec(me, replicas, Box::new(ProtobufCodec), None)
+    }
+
+    /// A client of a cluster that authenticates messages, `keyring` needs a
+    /// key for `me`.
+    pub fn with_keyring(me: ProcessId, replicas: Vec<ProcessId>, keyring: Keyring) -> Client {
+        Self::with_codec(me, replicas, Box::new(ProtobufCodec), Some(keyring))
+    }
+
+    /// A client of a cluster whose nodes encode messages with `codec`.
+    pub fn with_codec(
+        me: ProcessId,
+        replicas: Vec<ProcessId>,
+        codec: Box<dyn Codec>,
+        keyring: Option<Keyring>,
+    ) -> Client {
+        let context = zmq::Context::new();
+        let wire = Arc::new(Wire::new(codec, keyring));
+        let poller = ZMQPoller::new(context.clone(), wire.clone());
+        let (s, r) = channel::unbounded();
+        poller.add(me.clone(), s);
+
+        let addr = me.addr_sender();
+        thread::spawn(move || poller.start(&addr));
+
+        Client {
+            me,
+            replicas,
+            router: ZMQRouter::new(context, wire),
+            responses: r,
+            session: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+            next: 0,
+            retry: Duration::from_millis(CLIENT_RETRY_MS),
+            timeout: Duration::from_millis(CLIENT_TIMEOUT_MS),
+        }
+    }
+
+    /// Has `operation` decided and applied, resending it to every replica
+    /// each `retry` until one responds or `timeout` has passed.
+    pub fn call(&mut self, operation: Bytes) -> Result<Reply, ClientErr> {
+        self.next += 1;
+        let c = Command {
+            client: self.me.clone(),
+            req_id: Bytes::from(format!("{}-{}", self.session, self.next)),
+            operation,
+        };
+        call(
+            &self.replicas,
+            &self.router,
+            &self.responses,
+            c,
+            self.retry,
+            self.timeout,
+        )
+    }
+}
+
+/// Sends `c` to `replicas` until a response for it arrives on `responses`,
+/// shared by `Client` and the clients the benchmarks run inside an env.
+pub fn call<T: Router, R: Receiver>(
+    replicas: &[ProcessId],
+    router: &T,
+    responses: &R,
+    c: Command,
+    retry: Duration,
+    timeout: Duration,
+) -> Result<Reply, ClientErr> {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        for r in replicas.iter() {
+            router.send(r, Message::Request(c.client.clone(), c.clone()));
+        }
+
+        let retry = Instant::now() + retry;
+        loop {
+            let left = retry
+                .min(deadline)
+                .saturating_duration_since(Instant::now());
+            match responses.get_timeout(SLEEP_TIME, left) {
+                Ok(Message::Response(replica, slot, command, result)) => {
+                    // late answers to earlier requests are skipped
+                    if command.req_id == c.req_id {
+                        return Ok(Reply {
+                            replica,
+                            slot,
+                            result,
+                        });
+                    }
+                }
+                Ok(_) => {}
+                Err(GetErr::None) => break,
+            }
+        }
+    }
+    Err(ClientErr::Timeout)
+}
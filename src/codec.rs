@@ -0,0 +1,693 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use bytes::Bytes;
+use protobuf::{Message as _, MessageField};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    constants::PROTOCOL_VERSION,
+    ds::Accepted,
+    env::ProcessId,
+    message::{required, DecodeErr, Message},
+    proto::proto,
+    pval::{BallotNumber, Command, Deps, Instance, PValue},
+};
+
+/// A message on the wire, with the protocol version of its sender.
+#[derive(Clone, Debug)]
+pub struct WireMessage {
+    pub to: ProcessId,
+    pub message: Message,
+    pub version: u32,
+}
+
+impl WireMessage {
+    pub fn new(to: ProcessId, message: Message) -> WireMessage {
+        WireMessage {
+            to,
+            message,
+            version: PROTOCOL_VERSION,
+        }
+    }
+}
+
+impl Into<proto::WireMessage> for WireMessage {
+    fn into(self) -> proto::WireMessage {
+        let mut def = proto::WireMessage::default();
+        def.to = MessageField::some(self.to.into());
+        def.message = MessageField::some(self.message.into());
+        def.version = self.version;
+        def
+    }
+}
+
+impl TryFrom<proto::WireMessage> for WireMessage {
+    type Error = DecodeErr;
+
+    fn try_from(value: proto::WireMessage) -> Result<Self, Self::Error> {
+        Ok(Self {
+            to: required(value.to, "to")?,
+            message: required(value.message, "message")?,
+            version: value.version,
+        })
+    }
+}
+
+/// How messages are turned into bytes and back. Every process of a cluster,
+/// clients included, must use the same codec.
+pub trait Codec: Send + Sync {
+    fn encode(&self, m: WireMessage) -> Vec<u8>;
+
+    /// Fails on input that is not a message, and on messages of a type from
+    /// a newer protocol version, which the caller should drop. Byte fields of
+    /// the message may share `b` rather than copy out of it.
+    fn decode(&self, b: Bytes) -> Result<WireMessage, DecodeErr>;
+}
+
+/// The codecs that can be picked in a config.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Protobuf,
+    Binary,
+}
+
+impl Format {
+    pub fn codec(&self) -> Box<dyn Codec> {
+        match self {
+            Format::Protobuf => Box::new(ProtobufCodec),
+            Format::Binary => Box::new(BinaryCodec),
+        }
+    }
+}
+
+/// The `proto.proto` schema. Peers can add fields, which older versions skip.
+pub struct ProtobufCodec;
+
+impl Codec for ProtobufCodec {
+    fn encode(&self, m: WireMessage) -> Vec<u8> {
+        let p: proto::WireMessage = m.into();
+        p.write_to_bytes().unwrap()
+    }
+
+    fn decode(&self, b: Bytes) -> Result<WireMessage, DecodeErr> {
+        let p = proto::WireMessage::parse_from_tokio_bytes(&b)
+            .map_err(|e| DecodeErr::Malformed(e.to_string()))?;
+        WireMessage::try_from(p)
+    }
+}
+
+/// A compact hand rolled encoding: integers as varints, bytes prefixed with
+/// their length and no field tags, so the layout of a message type is fixed.
+/// A new version may add message types, and fields at the end of existing
+/// ones, which older versions ignore and newer ones default when missing.
+///
+/// ```text
+/// message    = version:varint to:process type:u8 fields...
+/// process    = family:u8 (4 | 6) ip:[u8; 4 | 16] port:varint id:varint
+/// ballot     = round:varint process
+/// command    = client:process req_id:bytes operation:bytes
+/// accepted   = len:varint (slot:varint ballot command)*
+/// instance   = replica:process number:varint
+/// deps       = len:varint instance*
+/// bytes      = len:varint [u8; len]
+/// ```
+///
+/// Types are numbered as in `MessageType` of the protobuf schema.
+pub struct BinaryCodec;
+
+impl Codec for BinaryCodec {
+    fn encode(&self, m: WireMessage) -> Vec<u8> {
+        let mut w = Writer { b: Vec::new() };
+        w.varint(m.version as u64);
+        w.process(&m.to);
+        w.message(&m.message);
+        w.b
+    }
+
+    fn decode(&self, b: Bytes) -> Result<WireMessage, DecodeErr> {
+        let mut r = Reader { b: &b, pos: 0 };
+        let version = r.varint()? as u32;
+        let to = r.process()?;
+        let message = r.message()?;
+        Ok(WireMessage {
+            to,
+            message,
+            version,
+        })
+    }
+}
+
+struct Writer {
+    b: Vec<u8>,
+}
+
+impl Writer {
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.b.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.b.push(v as u8);
+    }
+
+    fn bytes(&mut self, v: &[u8]) {
+        self.varint(v.len() as u64);
+        self.b.extend_from_slice(v);
+    }
+
+    fn process(&mut self, id: &ProcessId) {
+        match id.ip {
+            IpAddr::V4(ip) => {
+                self.b.push(4);
+                self.b.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                self.b.push(6);
+                self.b.extend_from_slice(&ip.octets());
+            }
+        }
+        self.varint(id.port as u64);
+        self.varint(id.id as u64);
+    }
+
+    fn ballot(&mut self, b: &BallotNumber) {
+        self.varint(b.round);
+        self.process(&b.process_id);
+    }
+
+    fn command(&mut self, c: &Command) {
+        self.process(&c.client);
+        self.bytes(&c.req_id);
+        self.bytes(&c.operation);
+    }
+
+    fn instance(&mut self, i: &Instance) {
+        self.process(&i.replica);
+        self.varint(i.number);
+    }
+
+    fn deps(&mut self, d: &Deps) {
+        self.varint(d.len() as u64);
+        for i in d.iter() {
+            self.instance(i);
+        }
+    }
+
+    fn accepted(&mut self, a: &Accepted) {
+        self.varint(a.len() as u64);
+        for (_, pv) in a.iter() {
+            self.varint(pv.slot);
+            self.ballot(&pv.ballot);
+            self.command(&pv.command);
+        }
+    }
+
+    fn message(&mut self, m: &Message) {
+        match m {
+            Message::P1A(id, ballot, from) => {
+                self.b.push(0);
+                self.process(id);
+                self.ballot(ballot);
+                self.varint(*from);
+            }
+            Message::P1B(id, ballot, accepted, (page, pages)) => {
+                self.b.push(1);
+                self.process(id);
+                self.ballot(ballot);
+                self.accepted(accepted);
+                self.b.push((page + 1 == *pages) as u8);
+                self.varint(*page);
+                self.varint(*pages);
+            }
+            Message::P2A(id, ballot, slot, command) => {
+                self.b.push(2);
+                self.process(id);
+                self.ballot(ballot);
+                self.varint(*slot);
+                self.command(command);
+            }
+            Message::P2B(id, ballot, slot) => {
+                self.b.push(3);
+                self.process(id);
+                self.ballot(ballot);
+                self.varint(*slot);
+            }
+            Message::Preempt(id, ballot) => {
+                self.b.push(4);
+                self.process(id);
+                self.ballot(ballot);
+            }
+            Message::Adopt(id, ballot, accepted) => {
+                self.b.push(5);
+                self.process(id);
+                self.ballot(ballot);
+                self.accepted(accepted);
+            }
+            Message::Decision(id, slot, command, ballot) => {
+                self.b.push(6);
+                self.process(id);
+                self.varint(*slot);
+                self.command(command);
+                match ballot {
+                    Some(b) => {
+                        self.b.push(1);
+                        self.ballot(b);
+                    }
+                    None => self.b.push(0),
+                }
+            }
+            Message::Request(id, command) => {
+                self.b.push(7);
+                self.process(id);
+                self.command(command);
+            }
+            Message::Propose(id, slot, command) => {
+                self.b.push(8);
+                self.process(id);
+                self.varint(*slot);
+                self.command(command);
+            }
+            Message::Failed(id, ballot, slot) => {
+                self.b.push(9);
+                self.process(id);
+                self.ballot(ballot);
+                // slots start at 1, 0 stands for a scout
+                self.varint(slot.unwrap_or(0));
+            }
+            Message::StepDown(id) => {
+                self.b.push(10);
+                self.process(id);
+            }
+            Message::Snapshot(id) => {
+                self.b.push(11);
+                self.process(id);
+            }
+            Message::Response(id, slot, command, result) => {
+                self.b.push(12);
+                self.process(id);
+                self.varint(*slot);
+                self.command(command);
+                self.bytes(result);
+            }
+            Message::Hello(id) => {
+                self.b.push(13);
+                self.process(id);
+            }
+            Message::Heartbeat(id, ballot) => {
+                self.b.push(14);
+                self.process(id);
+                self.ballot(ballot);
+            }
+            Message::PreAccept(id, instance, command, seq, deps) => {
+                self.b.push(15);
+                self.process(id);
+                self.instance(instance);
+                self.command(command);
+                self.varint(*seq);
+                self.deps(deps);
+            }
+            Message::PreAcceptOk(id, instance, seq, deps) => {
+                self.b.push(16);
+                self.process(id);
+                self.instance(instance);
+                self.varint(*seq);
+                self.deps(deps);
+            }
+            Message::Accept(id, instance, command, seq, deps) => {
+                self.b.push(17);
+                self.process(id);
+                self.instance(instance);
+                self.command(command);
+                self.varint(*seq);
+                self.deps(deps);
+            }
+            Message::AcceptOk(id, instance) => {
+                self.b.push(18);
+                self.process(id);
+                self.instance(instance);
+            }
+            Message::Commit(id, instance, command, seq, deps) => {
+                self.b.push(19);
+                self.process(id);
+                self.instance(instance);
+                self.command(command);
+                self.varint(*seq);
+                self.deps(deps);
+            }
+            Message::FastAny(id, ballot, from) => {
+                self.b.push(20);
+                self.process(id);
+                self.ballot(ballot);
+                self.varint(*from);
+            }
+            Message::FastRequest(id, slot, command) => {
+                self.b.push(21);
+                self.process(id);
+                self.varint(*slot);
+                self.command(command);
+            }
+            Message::FastAccepted(id, ballot, slot, command) => {
+                self.b.push(22);
+                self.process(id);
+                self.ballot(ballot);
+                self.varint(*slot);
+                self.command(command);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    b: &'a Bytes,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn eof() -> DecodeErr {
+        DecodeErr::Malformed(String::from("unexpected end of message"))
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeErr> {
+        if self.b.len() - self.pos < n {
+            return Err(Self::eof());
+        }
+        let res = &self.b[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(res)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeErr> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// A field added after the first version, `default` when the sender
+    /// predates it.
+    fn added<T>(
+        &mut self,
+        default: T,
+        f: impl FnOnce(&mut Self) -> Result<T, DecodeErr>,
+    ) -> Result<T, DecodeErr> {
+        match self.pos == self.b.len() {
+            true => Ok(default),
+            false => f(self),
+        }
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeErr> {
+        let mut res = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.u8()?;
+            res |= ((b & 0x7f) as u64) << shift;
+            if b < 0x80 {
+                return Ok(res);
+            }
+        }
+        Err(DecodeErr::Malformed(String::from("varint too long")))
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeErr> {
+        u32::try_from(self.varint()?).map_err(|e| DecodeErr::Malformed(e.to_string()))
+    }
+
+    fn bytes(&mut self) -> Result<Bytes, DecodeErr> {
+        let n = self.varint()?;
+        let n = usize::try_from(n).map_err(|_| Self::eof())?;
+        let start = self.pos;
+        self.take(n)?;
+        Ok(self.b.slice(start..self.pos))
+    }
+
+    fn process(&mut self) -> Result<ProcessId, DecodeErr> {
+        let ip = match self.u8()? {
+            4 => {
+                let b: [u8; 4] = self.take(4)?.try_into().unwrap();
+                IpAddr::V4(Ipv4Addr::from(b))
+            }
+            6 => {
+                let b: [u8; 16] = self.take(16)?.try_into().unwrap();
+                IpAddr::V6(Ipv6Addr::from(b))
+            }
+            f => {
+                return Err(DecodeErr::Malformed(format!(
+                    "unknown address family {}",
+                    f
+                )))
+            }
+        };
+        Ok(ProcessId::new(ip, self.u32()?, self.u32()?))
+    }
+
+    fn ballot(&mut self) -> Result<BallotNumber, DecodeErr> {
+        Ok(BallotNumber {
+            round: self.varint()?,
+            process_id: self.process()?,
+        })
+    }
+
+    fn command(&mut self) -> Result<Command, DecodeErr> {
+        Ok(Command {
+            client: self.process()?,
+            req_id: self.bytes()?,
+            operation: self.bytes()?,
+        })
+    }
+
+    fn instance(&mut self) -> Result<Instance, DecodeErr> {
+        Ok(Instance::new(self.process()?, self.varint()?))
+    }
+
+    fn deps(&mut self) -> Result<Deps, DecodeErr> {
+        let mut res = Deps::new();
+        for _ in 0..self.varint()? {
+            res.insert(self.instance()?);
+        }
+        Ok(res)
+    }
+
+    fn accepted(&mut self) -> Result<Accepted, DecodeErr> {
+        let mut res = Accepted::new();
+        for _ in 0..self.varint()? {
+            let slot = self.varint()?;
+            let pv = PValue {
+                slot,
+                ballot: self.ballot()?,
+                command: self.command()?,
+            };
+            res.insert(slot, pv);
+        }
+        Ok(res)
+    }
+
+    fn message(&mut self) -> Result<Message, DecodeErr> {
+        let res = match self.u8()? {
+            0 => Message::P1A(
+                self.process()?,
+                self.ballot()?,
+                self.added(0, Self::varint)?,
+            ),
+            1 => {
+                let (id, ballot, accepted) = (self.process()?, self.ballot()?, self.accepted()?);
+                // whether it is the last page, for the scouts that predate
+                // page numbers
+                self.added(true, |r| Ok(r.u8()? != 0))?;
+                let page = self.added(0, Self::varint)?;
+                Message::P1B(id, ballot, accepted, (page, self.added(1, Self::varint)?))
+            }
+            2 => Message::P2A(
+                self.process()?,
+                self.ballot()?,
+                self.varint()?,
+                self.command()?,
+            ),
+            3 => Message::P2B(self.process()?, self.ballot()?, self.varint()?),
+            4 => Message::Preempt(self.process()?, self.ballot()?),
+            5 => Message::Adopt(self.process()?, self.ballot()?, self.accepted()?),
+            6 => Message::Decision(
+                self.process()?,
+                self.varint()?,
+                self.command()?,
+                self.added(None, |r| match r.u8()? {
+                    0 => Ok(None),
+                    _ => Ok(Some(r.ballot()?)),
+                })?,
+            ),
+            7 => Message::Request(self.process()?, self.command()?),
+            8 => Message::Propose(self.process()?, self.varint()?, self.command()?),
+            9 => {
+                let (id, ballot) = (self.process()?, self.ballot()?);
+                let slot = Some(self.varint()?).filter(|s| *s != 0);
+                Message::Failed(id, ballot, slot)
+            }
+            10 => Message::StepDown(self.process()?),
+            11 => Message::Snapshot(self.process()?),
+            12 => Message::Response(
+                self.process()?,
+                self.varint()?,
+                self.command()?,
+                self.bytes()?,
+            ),
+            13 => Message::Hello(self.process()?),
+            14 => Message::Heartbeat(self.process()?, self.ballot()?),
+            15 => Message::PreAccept(
+                self.process()?,
+                self.instance()?,
+                self.command()?,
+                self.varint()?,
+                self.deps()?,
+            ),
+            16 => Message::PreAcceptOk(
+                self.process()?,
+                self.instance()?,
+                self.varint()?,
+                self.deps()?,
+            ),
+            17 => Message::Accept(
+                self.process()?,
+                self.instance()?,
+                self.command()?,
+                self.varint()?,
+                self.deps()?,
+            ),
+            18 => Message::AcceptOk(self.process()?, self.instance()?),
+            19 => Message::Commit(
+                self.process()?,
+                self.instance()?,
+                self.command()?,
+                self.varint()?,
+                self.deps()?,
+            ),
+            20 => Message::FastAny(self.process()?, self.ballot()?, self.varint()?),
+            21 => Message::FastRequest(self.process()?, self.varint()?, self.command()?),
+            22 => Message::FastAccepted(
+                self.process()?,
+                self.ballot()?,
+                self.varint()?,
+                self.command()?,
+            ),
+            t => return Err(DecodeErr::UnknownType(t as i32)),
+        };
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use bytes::Bytes;
+
+    use crate::{
+        ds::Accepted,
+        env::ProcessId,
+        message::{DecodeErr, Message},
+        proto::proto,
+        pval::{BallotNumber, Command, Deps, Instance, PValue},
+    };
+
+    use super::{BinaryCodec, Codec, ProtobufCodec, WireMessage};
+
+    fn sorted(a: &Accepted) -> Vec<PValue> {
+        let mut res: Vec<PValue> = a.iter().map(|(_, pv)| (**pv).clone()).collect();
+        res.sort_by_key(|pv| pv.slot);
+        res
+    }
+
+    #[test]
+    fn roundtrip() {
+        let id = ProcessId::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 6060, 3);
+        let ballot = BallotNumber {
+            round: 300,
+            process_id: id.clone(),
+        };
+        let command = Command::new_from_str(id.clone(), format!("1-1"), format!("PUT k v"));
+        let mut accepted = Accepted::new();
+        for slot in 1..100 {
+            let pv = PValue {
+                slot,
+                ballot: ballot.clone(),
+                command: command.clone(),
+            };
+            accepted.insert(slot, pv);
+        }
+
+        let deps: Deps = (1..4).map(|n| Instance::new(id.clone(), n)).collect();
+        let messages = vec![
+            Message::P1A(id.clone(), ballot.clone(), 12),
+            Message::P1B(id.clone(), ballot.clone(), accepted.clone(), (1, 3)),
+            Message::Adopt(id.clone(), ballot.clone(), accepted),
+            Message::Decision(id.clone(), 3, command.clone(), Some(ballot.clone())),
+            Message::Failed(id.clone(), ballot.clone(), None),
+            Message::Failed(id.clone(), ballot.clone(), Some(7)),
+            Message::Response(id.clone(), 7, command.clone(), Bytes::from("OK")),
+            Message::PreAcceptOk(id.clone(), Instance::new(id.clone(), 4), 2, deps),
+            Message::FastAccepted(id.clone(), ballot.clone(), 5, command.clone()),
+        ];
+        for codec in [&ProtobufCodec as &dyn Codec, &BinaryCodec] {
+            for m in messages.iter() {
+                let b = codec.encode(WireMessage::new(id.clone(), m.clone()));
+                let res = codec.decode(b.into()).unwrap();
+                assert_eq!(res.to, id);
+                match (&res.message, m) {
+                    (Message::P1B(_, _, a, _), Message::P1B(_, _, b, _))
+                    | (Message::Adopt(_, _, a), Message::Adopt(_, _, b)) => {
+                        assert_eq!(sorted(a), sorted(b))
+                    }
+                    _ => assert_eq!(format!("{:?}", res.message), format!("{:?}", m)),
+                }
+            }
+        }
+
+        let v6 = ProcessId::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 6060, 0);
+        for codec in [&ProtobufCodec as &dyn Codec, &BinaryCodec] {
+            let b = codec.encode(WireMessage::new(v6.clone(), Message::Hello(v6.clone())));
+            assert_eq!(codec.decode(b.into()).unwrap().to, v6);
+        }
+
+        // a P1A from before the from slot
+        let b = BinaryCodec.encode(WireMessage::new(
+            id.clone(),
+            Message::P1A(id.clone(), ballot, 12),
+        ));
+        match BinaryCodec.decode(Bytes::copy_from_slice(&b[..b.len() - 1])) {
+            Ok(WireMessage {
+                message: Message::P1A(_, _, 0),
+                ..
+            }) => {}
+            m => panic!("unexpected {:?}", m),
+        }
+
+        // a type from a newer version, and a truncated message
+        let mut b = BinaryCodec.encode(WireMessage::new(id.clone(), Message::Hello(id.clone())));
+        assert!(BinaryCodec
+            .decode(Bytes::copy_from_slice(&b[..b.len() - 1]))
+            .is_err());
+        b[9] = 42;
+        assert_eq!(
+            BinaryCodec.decode(b.into()).err(),
+            Some(DecodeErr::UnknownType(42))
+        );
+    }
+
+    #[test]
+    fn missing_fields() {
+        for codec in [&ProtobufCodec as &dyn Codec, &BinaryCodec] {
+            assert!(codec.decode(Bytes::new()).is_err());
+        }
+
+        // a frame without a message, and a message without its process
+        let id = ProcessId::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 6060, 3);
+        let mut p = proto::WireMessage::new();
+        p.to = protobuf::MessageField::some(id.clone().into());
+        let b = protobuf::Message::write_to_bytes(&p).unwrap();
+        assert!(ProtobufCodec.decode(b.into()).is_err());
+        p.message = protobuf::MessageField::some(proto::Message::new());
+        let b = protobuf::Message::write_to_bytes(&p).unwrap();
+        assert!(ProtobufCodec.decode(b.into()).is_err());
+
+        // only the version and the address of a frame
+        let b = BinaryCodec.encode(WireMessage::new(id.clone(), Message::Hello(id)));
+        assert!(BinaryCodec.decode(Bytes::copy_from_slice(&b[..9])).is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use super::{
+    auth::{self, Keyring},
+    codec::Format,
+    constants::{
+        DEADLINE_MS, HEARTBEAT_MS, LEADER_TIMEOUT_MS, RETRANSMIT_MAX_MS, RETRANSMIT_MS,
+        THRIFTY_TIMEOUT_MS,
+    },
+    env::{ProcessId, ProcessType},
+    leader::LeaderConfig,
+    quorum::QuorumErr,
+};
+
+#[derive(Debug)]
+pub enum ConfigErr {
+    Io(std::io::Error),
+    Parse(String),
+    Endpoint(String),
+    Key(String),
+    Modes(&'static str, &'static str),
+    Quorum(QuorumErr),
+}
+
+impl Display for ConfigErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigErr::Io(e) => write!(f, "unable to read config: {}", e),
+            ConfigErr::Parse(e) => write!(f, "unable to parse config: {}", e),
+            ConfigErr::Endpoint(e) => write!(f, "bad endpoint {}, expected ip:port", e),
+            ConfigErr::Key(e) => write!(f, "bad key for {}, expected non empty hex", e),
+            ConfigErr::Modes(a, b) => write!(f, "{} and {} cannot be combined", a, b),
+            ConfigErr::Quorum(e) => write!(f, "bad quorums: {}", e),
+        }
+    }
+}
+
+/// How many instances of each role a node hosts.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Roles {
+    pub acceptors: u32,
+    pub leaders: u32,
+    pub replicas: u32,
+    pub witnesses: u32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Peer {
+    pub endpoint: String,
+    #[serde(default)]
+    pub roles: Roles,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Timeouts {
+    pub thrifty_ms: u64,
+    pub retransmit_ms: u64,
+    pub retransmit_max_ms: u64,
+    pub deadline_ms: u64,
+    pub heartbeat_ms: u64,
+    pub leader_timeout_ms: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            thrifty_ms: THRIFTY_TIMEOUT_MS,
+            retransmit_ms: RETRANSMIT_MS,
+            retransmit_max_ms: RETRANSMIT_MAX_MS,
+            deadline_ms: DEADLINE_MS,
+            heartbeat_ms: HEARTBEAT_MS,
+            leader_timeout_ms: LEADER_TIMEOUT_MS,
+        }
+    }
+}
+
+/// Configuration of a single `paxos-node`, read from TOML or, when the file
+/// ends in `.json`, from JSON:
+///
+/// ```toml
+/// endpoint = "10.0.0.1:6060"
+/// storage = "/var/lib/paxos"
+/// metrics = "0.0.0.0:9100"
+/// admin = "127.0.0.1:9101"
+/// codec = "binary"
+///
+/// [roles]
+/// acceptors = 1
+/// leaders = 1
+/// replicas = 1
+///
+/// [[peers]]
+/// endpoint = "10.0.0.2:6060"
+/// roles = { acceptors = 1, leaders = 1, replicas = 1 }
+///
+/// [keys]
+/// "10.0.0.1:6060" = "9f86d081884c7d65"
+/// "10.0.0.2:6060" = "2c26b46b68ffc68f"
+/// "10.0.0.3" = "fcde2b2edba56bf4"
+/// ```
+///
+/// Process ids on every node are handed out in the same order, acceptors
+/// first then leaders then replicas, so the ids of a peer follow from its
+/// roles and need not be listed.
+///
+/// With `keys`, hex encoded HMAC keys per endpoint or per host (e.g. for
+/// clients), every message must be signed by the node it comes from.
+///
+/// `codec` is `protobuf` (the default) or `binary`, and must be the same on
+/// every node and client of the cluster.
+///
+/// The modes `stable`, `mencius`, `fast`, `masters`, `epaxos`, `master` and
+/// `locks` are exclusive, a node runs one of them at most.
+#[derive(Deserialize, Clone, Debug)]
+pub struct NodeConfig {
+    pub endpoint: String,
+    #[serde(default)]
+    pub roles: Roles,
+    #[serde(default)]
+    pub peers: Vec<Peer>,
+    pub storage: Option<PathBuf>,
+    pub metrics: Option<String>,
+    pub admin: Option<String>,
+    #[serde(default)]
+    pub thrifty: bool,
+    /// Distinguished leader mode, see `LeaderConfig::stable`.
+    #[serde(default)]
+    pub stable: bool,
+    /// Rotating slot ownership, see `LeaderConfig::mencius`.
+    #[serde(default)]
+    pub mencius: bool,
+    /// Host EPaxos replicas in place of the replicas, see `epaxos::Replica`.
+    /// They need no leaders or acceptors.
+    #[serde(default)]
+    pub epaxos: bool,
+    /// Fast Paxos rounds, see `LeaderConfig::fast`.
+    #[serde(default)]
+    pub fast: bool,
+    /// Vertical Paxos: the nodes of the configuration master cluster, whose
+    /// replicas the leaders ask for rounds, see `LeaderConfig::masters`.
+    #[serde(default)]
+    pub masters: Vec<Peer>,
+    /// The node is part of a configuration master cluster, its replicas run
+    /// `vertical::ConfigMaster`.
+    #[serde(default)]
+    pub master: bool,
+    /// The replicas run `lock::LockService` in place of the key-value store.
+    #[serde(default)]
+    pub locks: bool,
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    #[serde(default)]
+    pub keys: HashMap<String, String>,
+    #[serde(default)]
+    pub codec: Format,
+}
+
+impl NodeConfig {
+    pub fn load(path: &Path) -> Result<NodeConfig, ConfigErr> {
+        let s = fs::read_to_string(path).map_err(ConfigErr::Io)?;
+        let config: NodeConfig = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => {
+                serde_json::from_str(&s).map_err(|e| ConfigErr::Parse(e.to_string()))?
+            }
+            _ => toml::from_str(&s).map_err(|e| ConfigErr::Parse(e.to_string()))?,
+        };
+
+        endpoint(&config.endpoint)?;
+        for p in config.peers.iter().chain(config.masters.iter()) {
+            endpoint(&p.endpoint)?;
+        }
+        config.modes()?;
+        config.quorum()?;
+        config.keyring()?;
+        Ok(config)
+    }
+
+    /// At most one of the modes can be turned on.
+    fn modes(&self) -> Result<(), ConfigErr> {
+        let modes = [
+            ("stable", self.stable),
+            ("mencius", self.mencius),
+            ("fast", self.fast),
+            ("masters", !self.masters.is_empty()),
+            ("epaxos", self.epaxos),
+            ("master", self.master),
+            ("locks", self.locks),
+        ];
+        let on: Vec<&'static str> = modes
+            .iter()
+            .filter(|(_, on)| *on)
+            .map(|(m, _)| *m)
+            .collect();
+        match on.as_slice() {
+            [a, b, ..] => Err(ConfigErr::Modes(a, b)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The quorums of the leaders fit the acceptors and witnesses of the
+    /// cluster. In Vertical Paxos, the master hands out the acceptors.
+    fn quorum(&self) -> Result<(), ConfigErr> {
+        let all: Vec<(ProcessType, ProcessId)> =
+            self.local().into_iter().chain(self.remote()).collect();
+        let leaders = all.iter().any(|(t, _)| *t == ProcessType::Leader);
+        if !leaders || !self.masters.is_empty() {
+            return Ok(());
+        }
+        let mut voters: Vec<ProcessId> = vec![];
+        for t in [ProcessType::Acceptor, ProcessType::Witness] {
+            voters.extend(
+                all.iter()
+                    .filter(|(u, _)| *u == t)
+                    .map(|(_, id)| id.clone()),
+            );
+        }
+        self.leader_config()
+            .quorum
+            .validate(&voters)
+            .map_err(ConfigErr::Quorum)
+    }
+
+    /// The keys to authenticate messages with, `None` when there are none.
+    pub fn keyring(&self) -> Result<Option<Keyring>, ConfigErr> {
+        if self.keys.is_empty() {
+            return Ok(None);
+        }
+        let mut k = Keyring::new();
+        for (e, key) in self.keys.iter() {
+            auth::decode_hex(key)
+                .and_then(|key| k.add(e, key))
+                .map_err(|_| ConfigErr::Key(e.clone()))?;
+        }
+        Ok(Some(k))
+    }
+
+    pub fn leader_config(&self) -> LeaderConfig {
+        LeaderConfig {
+            thrifty: self.thrifty,
+            thrifty_timeout: Duration::from_millis(self.timeouts.thrifty_ms),
+            retransmit: Duration::from_millis(self.timeouts.retransmit_ms),
+            retransmit_max: Duration::from_millis(self.timeouts.retransmit_max_ms),
+            deadline: Duration::from_millis(self.timeouts.deadline_ms),
+            stable: self.stable,
+            heartbeat: Duration::from_millis(self.timeouts.heartbeat_ms),
+            leader_timeout: Duration::from_millis(self.timeouts.leader_timeout_ms),
+            mencius: self.mencius,
+            leaders: match self.mencius {
+                true => self
+                    .local()
+                    .into_iter()
+                    .chain(self.remote())
+                    .filter(|(t, _)| *t == ProcessType::Leader)
+                    .map(|(_, id)| id)
+                    .collect(),
+                false => vec![],
+            },
+            fast: self.fast,
+            masters: self
+                .masters
+                .iter()
+                .flat_map(|p| processes(&p.endpoint, &p.roles).unwrap())
+                .filter(|(t, _)| *t == ProcessType::Replica)
+                .map(|(_, id)| id)
+                .collect(),
+            ..LeaderConfig::default()
+        }
+    }
+
+    /// The processes hosted by this node.
+    pub fn local(&self) -> Vec<(ProcessType, ProcessId)> {
+        processes(&self.endpoint, &self.roles).unwrap()
+    }
+
+    /// The processes hosted by every peer.
+    pub fn remote(&self) -> Vec<(ProcessType, ProcessId)> {
+        self.peers
+            .iter()
+            .flat_map(|p| processes(&p.endpoint, &p.roles).unwrap())
+            .collect()
+    }
+
+    /// Every replica of the cluster, where clients send their requests.
+    pub fn replicas(&self) -> Vec<ProcessId> {
+        self.local()
+            .into_iter()
+            .chain(self.remote())
+            .filter(|(t, _)| *t == ProcessType::Replica)
+            .map(|(_, id)| id)
+            .collect()
+    }
+}
+
+pub fn endpoint(s: &str) -> Result<(IpAddr, u32), ConfigErr> {
+    let err = || ConfigErr::Endpoint(s.to_string());
+    let (ip, port) = s.rsplit_once(':').ok_or_else(err)?;
+    let ip = ip.parse().map_err(|_| err())?;
+    let port = port.parse().map_err(|_| err())?;
+    Ok((ip, port))
+}
+
+/// The ids a node at `e` gives to its roles, in registration order.
+pub fn processes(e: &str, roles: &Roles) -> Result<Vec<(ProcessType, ProcessId)>, ConfigErr> {
+    let (ip, port) = endpoint(e)?;
+    let types = [
+        (ProcessType::Acceptor, roles.acceptors),
+        (ProcessType::Leader, roles.leaders),
+        (ProcessType::Replica, roles.replicas),
+        (ProcessType::Witness, roles.witnesses),
+    ];
+
+    let mut res = vec![];
+    for (t, n) in types {
+        for _ in 0..n {
+            let id = ProcessId::new(ip, port, res.len() as u32);
+            res.push((t.clone(), id));
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{env::ProcessType, quorum::QuorumErr};
+
+    use super::{processes, ConfigErr, NodeConfig};
+
+    #[test]
+    fn parse_toml() {
+        let config: NodeConfig = toml::from_str(
+            r#"
+            endpoint = "127.0.0.1:7000"
+            [roles]
+            acceptors = 2
+            replicas = 1
+            [timeouts]
+            deadline_ms = 500
+            [[peers]]
+            endpoint = "127.0.0.1:7001"
+            roles = { leaders = 1 }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.leader_config().deadline.as_millis(), 500);
+        let local = config.local();
+        assert_eq!(local.len(), 3);
+        assert!(local[2].0 == ProcessType::Replica && local[2].1.id == 2);
+        assert!(config.remote()[0].0 == ProcessType::Leader);
+        assert_eq!(config.remote()[0].1.port, 7001);
+    }
+
+    #[test]
+    fn exclusive_modes() {
+        let config = |modes: &str| -> NodeConfig {
+            toml::from_str(&format!("endpoint = \"127.0.0.1:7000\"\n{}", modes)).unwrap()
+        };
+        assert!(config("").modes().is_ok());
+        assert!(config("locks = true").modes().is_ok());
+        assert!(matches!(
+            config("mencius = true\nfast = true").modes(),
+            Err(ConfigErr::Modes("mencius", "fast"))
+        ));
+        let masters = "epaxos = true\n[[masters]]\nendpoint = \"127.0.0.1:7001\"";
+        assert!(matches!(
+            config(masters).modes(),
+            Err(ConfigErr::Modes("masters", "epaxos"))
+        ));
+    }
+
+    #[test]
+    fn mencius_leaders() {
+        let config: NodeConfig = toml::from_str(
+            r#"
+            endpoint = "127.0.0.1:7000"
+            mencius = true
+            roles = { acceptors = 1, leaders = 1 }
+            [[peers]]
+            endpoint = "127.0.0.1:7001"
+            roles = { leaders = 1 }
+            "#,
+        )
+        .unwrap();
+        let leaders = config.leader_config().leaders;
+        assert_eq!(leaders.len(), 2);
+        assert!(leaders[0].id == 1 && leaders[1].port == 7001);
+    }
+
+    #[test]
+    fn quorums() {
+        let config = |roles: &str| -> NodeConfig {
+            toml::from_str(&format!("endpoint = \"127.0.0.1:7000\"\n{}", roles)).unwrap()
+        };
+        assert!(config("").quorum().is_ok());
+        assert!(config("[roles]\nleaders = 1\nwitnesses = 1")
+            .quorum()
+            .is_ok());
+        assert!(matches!(
+            config("[roles]\nleaders = 1").quorum(),
+            Err(ConfigErr::Quorum(QuorumErr::NoAcceptors))
+        ));
+    }
+
+    #[test]
+    fn bad_endpoint() {
+        assert!(processes("localhost", &Default::default()).is_err());
+    }
+}
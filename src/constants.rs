@@ -1,3 +1,22 @@
 pub static SLEEP_TIME: u64 = 1000;
-pub static INCOMING_PORT: u32 = 5555;
-pub static OUTGOING_PORT: u32 = 5556;
+pub static THRIFTY_TIMEOUT_MS: u64 = 100;
+pub static RETRANSMIT_MS: u64 = 200;
+pub static RETRANSMIT_MAX_MS: u64 = 3200;
+pub static DEADLINE_MS: u64 = 10000;
+pub static STEP_DOWN_MS: u64 = 5000;
+pub static CLIENT_RETRY_MS: u64 = 1000;
+pub static CLIENT_TIMEOUT_MS: u64 = 10000;
+pub static LINGER_MS: i32 = 1000;
+pub static SEND_TIMEOUT_MS: i32 = 1000;
+pub static PROTOCOL_VERSION: u32 = 4;
+pub static MIN_PROTOCOL_VERSION: u32 = 1;
+pub static HELLO_INTERVAL_MS: u64 = 5000;
+pub static PEER_IDLE_MS: u64 = 60000;
+pub static P1B_PAGE_SIZE: usize = 1000;
+/// Synod instances take the slots from here on, apart from the replicated log.
+pub static SYNOD_SLOTS: u64 = 1 << 63;
+pub static HEARTBEAT_MS: u64 = 100;
+pub static LEADER_TIMEOUT_MS: u64 = 1000;
+pub static PROPOSE_TIMEOUT_MS: u64 = 1000;
+pub static FAST_PATH_TIMEOUT_MS: u64 = 100;
+pub static MAX_CLOCK_SKEW_MS: u64 = 1000;
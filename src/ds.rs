@@ -1,55 +1,343 @@
-use std::collections::{hash_map::Iter, HashMap};
+use std::{
+    cmp::Ordering,
+    collections::{btree_map::Iter, BTreeMap, HashMap, HashSet},
+    ops::Range,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
-use super::pval::{PValue, SlotNumber};
+use super::{
+    env::ProcessId,
+    pval::{PValue, SlotNumber},
+};
 
-#[derive(Debug)]
+/// The highest ballot PValue per slot. Values are reference counted, so
+/// cloning the whole set for a P1B or an Adopt does not copy any of them.
+#[derive(Clone, Debug)]
 pub struct Accepted {
-    m: HashMap<SlotNumber, Box<PValue>>,
+    m: BTreeMap<SlotNumber, Arc<PValue>>,
 }
 
 impl Accepted {
     pub fn insert(&mut self, k: SlotNumber, v: PValue) {
+        self.insert_shared(k, Arc::new(v));
+    }
+
+    fn insert_shared(&mut self, k: SlotNumber, v: Arc<PValue>) {
         match self.m.get_mut(&k) {
             Some(e) => {
                 if e.ballot < v.ballot {
-                    **e = v;
+                    *e = v;
                 }
             }
             None => {
-                self.m.insert(k, Box::new(v));
+                self.m.insert(k, v);
             }
         }
     }
 
     pub fn new() -> Accepted {
-        Accepted { m: HashMap::new() }
+        Accepted { m: BTreeMap::new() }
     }
 
     pub(crate) fn extend(&mut self, accepted: Accepted) -> () {
         for (k, v) in accepted.m {
-            self.insert(k, *v);
+            self.insert_shared(k, v);
         }
     }
 
-    pub fn iter(&self) -> Iter<'_, u64, Box<PValue>> {
+    pub fn get(&self, slot: &SlotNumber) -> Option<&PValue> {
+        self.m.get(slot).map(|v| &**v)
+    }
+
+    pub fn remove(&mut self, slot: &SlotNumber) {
+        self.m.remove(slot);
+    }
+
+    pub fn len(&self) -> usize {
+        self.m.len()
+    }
+
+    /// lowest and highest slot with an accepted value
+    pub fn range(&self) -> Option<(SlotNumber, SlotNumber)> {
+        let (lo, _) = self.m.first_key_value()?;
+        let (hi, _) = self.m.last_key_value()?;
+        Some((*lo, *hi))
+    }
+
+    pub fn iter(&self) -> Iter<'_, u64, Arc<PValue>> {
         self.m.iter()
     }
 
+    /// The values in `slots`, in pages of at most `size` slots in slot
+    /// order. There is always a page, empty if there are no values.
+    pub fn pages(&self, slots: Range<SlotNumber>, size: usize) -> Vec<Accepted> {
+        let mut res = vec![Accepted::new()];
+        for (k, v) in self.m.range(slots) {
+            if res.last().unwrap().len() >= size {
+                res.push(Accepted::new());
+            }
+            res.last_mut().unwrap().m.insert(*k, v.clone());
+        }
+        res
+    }
+
     pub fn map<T>(self, f: fn(PValue) -> T) -> HashMap<u64, T> {
         let mut res = HashMap::new();
-        for (k, v) in self.m.into_iter().map(|(k, v)| (k, *v)) {
+        let values = self.m.into_iter();
+        for (k, v) in values.map(|(k, v)| (k, Arc::unwrap_or_clone(v))) {
             res.insert(k, f(v));
         }
         res
     }
 }
 
-impl Clone for Accepted {
-    fn clone(&self) -> Accepted {
-        let mut res = HashMap::new();
-        for (k, v) in self.m.iter() {
-            res.insert(*k, v.clone());
+/// pmax from the paper: for every slot, the PValue with the highest ballot
+/// across all the given P1B responses.
+pub fn pmax<'a, I>(responses: I) -> HashMap<SlotNumber, PValue>
+where
+    I: IntoIterator<Item = &'a Accepted>,
+{
+    let mut res: HashMap<SlotNumber, PValue> = HashMap::new();
+    for accepted in responses {
+        for (s, pv) in accepted.iter() {
+            match res.get(s) {
+                Some(p) if p.ballot >= pv.ballot => {}
+                _ => {
+                    res.insert(*s, (**pv).clone());
+                }
+            }
+        }
+    }
+    res
+}
+
+/// The P1B responses merged for an Adopt: for every slot, the value with the
+/// highest ballot. Acceptors can accept different values in the same fast
+/// ballot, the one most of them accepted is then the only one that may have
+/// been chosen, given fast quorums sized by `QuorumSystem::fast`.
+pub fn pick<'a, I>(responses: I) -> Accepted
+where
+    I: IntoIterator<Item = &'a Accepted>,
+{
+    let mut votes: HashMap<SlotNumber, Vec<(Arc<PValue>, usize)>> = HashMap::new();
+    for accepted in responses {
+        for (s, pv) in accepted.iter() {
+            let v = votes.entry(*s).or_insert(Vec::new());
+            match v.first().map(|(p, _)| p.ballot.cmp(&pv.ballot)) {
+                Some(Ordering::Greater) => continue,
+                Some(Ordering::Less) => v.clear(),
+                _ => {}
+            }
+            match v.iter_mut().find(|(p, _)| p.command == pv.command) {
+                Some((_, n)) => *n += 1,
+                None => v.push((pv.clone(), 1)),
+            }
+        }
+    }
+    let mut res = Accepted::new();
+    for (s, v) in votes {
+        // the first of the most voted, in the order they were seen
+        let mut best = &v[0];
+        for e in v.iter() {
+            if e.1 > best.1 {
+                best = e;
+            }
+        }
+        res.insert_shared(s, best.0.clone());
+    }
+    res
+}
+
+/// `x ◁ y` from the paper: every entry of `y`, plus the entries of `x` for the
+/// slots `y` does not have.
+pub fn triangle<V>(x: &mut HashMap<SlotNumber, V>, y: HashMap<SlotNumber, V>) {
+    for (k, v) in y.into_iter() {
+        x.insert(k, v);
+    }
+}
+
+/// When each process was last heard from, shared between a leader and the
+/// scouts and commanders it spawns, and the ones suspected to be down since.
+pub struct LastSeen {
+    m: Mutex<HashMap<ProcessId, Instant>>,
+    down: Mutex<HashSet<ProcessId>>,
+}
+
+impl LastSeen {
+    pub fn new() -> LastSeen {
+        LastSeen {
+            m: Mutex::new(HashMap::new()),
+            down: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn seen(&self, id: &ProcessId) {
+        self.m.lock().unwrap().insert(id.clone(), Instant::now());
+        self.down.lock().unwrap().remove(id);
+    }
+
+    /// `id` did not answer in time, it is down until heard from again.
+    pub fn suspect(&self, id: &ProcessId) {
+        self.down.lock().unwrap().insert(id.clone());
+    }
+
+    pub fn any_down(&self, ids: &[ProcessId]) -> bool {
+        let down = self.down.lock().unwrap();
+        ids.iter().any(|id| down.contains(id))
+    }
+
+    /// `ids` ordered from the most recently heard from, the ones never heard
+    /// from keep their relative order at the end.
+    pub fn order(&self, ids: &[ProcessId]) -> Vec<ProcessId> {
+        let m = self.m.lock().unwrap();
+        let mut res = ids.to_vec();
+        res.sort_by(|a, b| m.get(b).cmp(&m.get(a)));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::{IpAddr, Ipv4Addr},
+    };
+
+    use proptest::{collection::vec, prelude::*};
+
+    use crate::{
+        env::ProcessId,
+        pval::{BallotNumber, Command, PValue, SlotNumber},
+    };
+
+    use super::{pick, pmax, triangle, Accepted};
+
+    fn pid(id: u32) -> ProcessId {
+        ProcessId::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0, id)
+    }
+
+    // a leader only ever proposes one command per slot in a ballot, so the
+    // command is derived from the ballot and the slot
+    fn pvalue() -> impl Strategy<Value = PValue> {
+        (0u64..4, 0u32..3, 1u64..8).prop_map(|(round, leader, slot)| {
+            PValue::new(
+                BallotNumber::new(round, pid(leader)),
+                slot,
+                Command::new_from_str(
+                    pid(leader),
+                    format!("{}:{}:{}", round, leader, slot),
+                    format!("Op:{}", slot),
+                ),
+            )
+        })
+    }
+
+    // what a single acceptor would report in its P1B
+    fn p1b() -> impl Strategy<Value = Accepted> {
+        vec(pvalue(), 0..10).prop_map(|pvs| {
+            let mut a = Accepted::new();
+            for pv in pvs {
+                a.insert(pv.slot, pv);
+            }
+            a
+        })
+    }
+
+    #[test]
+    fn pick_most_voted_in_fast_ballot() {
+        let ballot = BallotNumber::new(1, pid(0));
+        let response = |op: &str| {
+            let mut a = Accepted::new();
+            let c = Command::new_from_str(pid(1), op.to_string(), op.to_string());
+            a.insert(1, PValue::new(ballot.clone(), 1, c));
+            a
+        };
+        let responses = [response("b"), response("a"), response("a")];
+        let picked = pick(responses.iter());
+        assert_eq!(picked.get(&1).unwrap().command.op_str(), "a");
+    }
+
+    proptest! {
+        #[test]
+        fn pmax_picks_highest_ballot(responses in vec(p1b(), 1..5)) {
+            let max = pmax(responses.iter());
+
+            for a in responses.iter() {
+                for (s, pv) in a.iter() {
+                    let m = max.get(s).expect("every reported slot is present");
+                    prop_assert!(m.ballot >= pv.ballot);
+                    if m.ballot == pv.ballot {
+                        prop_assert_eq!(&m.command, &pv.command);
+                    }
+                }
+            }
+            for (s, m) in max.iter() {
+                prop_assert!(responses.iter().any(|a| a.iter().any(|(k, pv)| k == s && **pv == *m)));
+            }
+        }
+
+        #[test]
+        fn pmax_ignores_response_order(mut responses in vec(p1b(), 1..5)) {
+            let max = pmax(responses.iter());
+            responses.reverse();
+            prop_assert_eq!(max, pmax(responses.iter()));
+        }
+
+        #[test]
+        fn pmax_matches_scout_merge(responses in vec(p1b(), 1..5)) {
+            let mut values = Accepted::new();
+            for a in responses.iter() {
+                values.extend(a.clone());
+            }
+            prop_assert_eq!(pmax([&values]), pmax(responses.iter()));
+        }
+
+        #[test]
+        fn triangle_reproposes_pmax(
+            responses in vec(p1b(), 1..5),
+            own in vec((1u64..12, "[a-z]{1,4}"), 0..10),
+        ) {
+            let mut proposals: HashMap<SlotNumber, Command> = own
+                .into_iter()
+                .map(|(s, op)| (s, Command::new_from_str(pid(9), op.clone(), op)))
+                .collect();
+            let before = proposals.clone();
+            let max = pmax(responses.iter());
+
+            triangle(
+                &mut proposals,
+                max.iter().map(|(s, pv)| (*s, pv.command.clone())).collect(),
+            );
+
+            for (s, c) in proposals.iter() {
+                match max.get(s) {
+                    Some(pv) => prop_assert_eq!(c, &pv.command),
+                    None => prop_assert_eq!(Some(c), before.get(s)),
+                }
+            }
+            prop_assert!(max.keys().all(|s| proposals.contains_key(s)));
+        }
+
+        #[test]
+        fn pick_matches_pmax_on_classic_ballots(responses in vec(p1b(), 1..5)) {
+            let picked: HashMap<_, _> = pick(responses.iter()).map(|pv| pv);
+            prop_assert_eq!(picked, pmax(responses.iter()));
+        }
+
+        #[test]
+        fn pages_cover_range(values in p1b(), from in 0u64..9, n in 0u64..9, size in 1usize..4) {
+            let to = from + n;
+            let pages = values.pages(from..to, size);
+            prop_assert!(!pages.is_empty());
+            prop_assert!(pages.iter().all(|p| p.len() <= size));
+
+            let mut merged = Accepted::new();
+            for p in pages {
+                merged.extend(p);
+            }
+            let expected: Vec<_> = values.iter().filter(|(s, _)| (from..to).contains(*s)).collect();
+            prop_assert_eq!(merged.iter().collect::<Vec<_>>(), expected);
         }
-        return Accepted { m: res };
     }
 }
@@ -1,7 +1,15 @@
 use chashmap::CHashMap;
 
 use super::message::Message;
-use std::{collections::HashMap, fmt::Display, net::IpAddr, slice::Iter, sync::Mutex};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    net::IpAddr,
+    slice::Iter,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
 
 #[derive(Eq, Ord, PartialEq, PartialOrd, Hash, Clone, Debug)]
 pub struct ProcessId {
@@ -29,13 +37,18 @@ impl Display for ProcessId {
     }
 }
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum ProcessType {
     Acceptor,
     Replica,
     Leader,
     Scout,
     Commander,
+    Client,
+    // an auxiliary acceptor, see `witness::Witness`
+    Witness,
+    // proposes a single value, see `synod::Proposer`
+    Proposer,
 }
 
 pub struct Cluster {
@@ -58,7 +71,10 @@ impl Cluster {
     }
 
     fn get(&self, t: ProcessType) -> Vec<ProcessId> {
-        return Self::copy_vec(self.map.get(&t).unwrap().iter());
+        match self.map.get(&t) {
+            Some(v) => Self::copy_vec(v.iter()),
+            None => vec![],
+        }
     }
 
     pub fn acceptors(&self) -> Vec<ProcessId> {
@@ -73,6 +89,10 @@ impl Cluster {
         return self.get(ProcessType::Leader);
     }
 
+    pub fn witnesses(&self) -> Vec<ProcessId> {
+        return self.get(ProcessType::Witness);
+    }
+
     pub fn add(&self, t: ProcessType, id: ProcessId) {
         let o = self.map.get_mut(&t);
         if o.is_none() {
@@ -99,6 +119,22 @@ pub enum GetErr {
 pub trait Receiver {
     fn try_get(&self) -> Result<Message, GetErr>;
     fn get(&self, sleep: u64) -> Message;
+
+    /// Same as `get`, but gives up once `timeout` has passed without a message.
+    fn get_timeout(&self, sleep: u64, timeout: Duration) -> Result<Message, GetErr> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.try_get() {
+                Ok(m) => return Ok(m),
+                Err(e) => {
+                    if Instant::now() >= deadline {
+                        return Err(e);
+                    }
+                    thread::sleep(Duration::from_nanos(sleep))
+                }
+            }
+        }
+    }
 }
 
 pub trait Executor {
@@ -0,0 +1,692 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use log::{info, warn};
+
+use super::{
+    constants::{FAST_PATH_TIMEOUT_MS, RETRANSMIT_MS, SLEEP_TIME},
+    env::{Env, Executor, ProcessId, Receiver, Router},
+    message::Message,
+    pval::{Command, Deps, Instance, SlotNumber},
+    state::{KvStore, Op, StateMachine},
+    trace::{self, Stage},
+};
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+enum Status {
+    PreAccepted,
+    Accepted,
+    Committed,
+    Executed,
+}
+
+struct Entry {
+    command: Command,
+    seq: u64,
+    deps: Deps,
+    status: Status,
+}
+
+/// The key a command reads or writes, commands on different keys commute.
+/// Operations the state machine cannot parse change nothing and commute with
+/// everything.
+fn key(c: &Command) -> Option<String> {
+    match Op::parse(&c.operation)? {
+        Op::Get(k) | Op::Put(k, _) | Op::Del(k) => Some(k),
+    }
+}
+
+/// Per key, the latest instance of every replica that touches it and the
+/// highest seq among them. Depending on the latest instance of a replica is
+/// enough, it depends on the earlier ones itself.
+struct Conflicts {
+    m: HashMap<String, (HashMap<ProcessId, u64>, u64)>,
+}
+
+impl Conflicts {
+    fn new() -> Conflicts {
+        Conflicts { m: HashMap::new() }
+    }
+
+    /// The seq and deps of a command on `key` in instance `inst`, as far as
+    /// this replica knows.
+    fn attrs(&self, key: &str, inst: &Instance) -> (u64, Deps) {
+        match self.m.get(key) {
+            Some((latest, seq)) => {
+                let deps = latest
+                    .iter()
+                    .map(|(r, n)| Instance::new(r.clone(), *n))
+                    .filter(|i| i != inst)
+                    .collect();
+                (seq + 1, deps)
+            }
+            None => (1, Deps::new()),
+        }
+    }
+
+    fn add(&mut self, key: String, inst: &Instance, seq: u64) {
+        let (latest, max) = self.m.entry(key).or_insert((HashMap::new(), 0));
+        let n = latest.entry(inst.replica.clone()).or_insert(0);
+        *n = (*n).max(inst.number);
+        *max = (*max).max(seq);
+    }
+}
+
+/// The replicas, command leader included, that have to answer on the fast
+/// path and on the slow path in a cluster of `n`, which tolerates
+/// `(n - 1) / 2` failures.
+pub fn quorums(n: usize) -> (usize, usize) {
+    let f = n.saturating_sub(1) / 2;
+    ((f + (f + 1) / 2).max(f + 1), f + 1)
+}
+
+#[derive(PartialEq, Eq)]
+enum Phase {
+    PreAccept,
+    Accept,
+}
+
+/// What the command leader of an instance collects from the other replicas.
+struct Round {
+    phase: Phase,
+    command: Command,
+    // the attributes sent in the PreAccept, merged with the replies for the
+    // Accept
+    seq: u64,
+    deps: Deps,
+    replies: HashMap<ProcessId, (u64, Deps)>,
+    acks: HashSet<ProcessId>,
+    started: Instant,
+    sent: Instant,
+}
+
+/// The order committed instances are executed in, starting from `root`:
+/// Tarjan's strongly connected components of the dependency graph, each
+/// component after the ones it depends on and sorted by seq within. `None`
+/// while an instance it depends on is not committed yet.
+fn execution_order(instances: &HashMap<Instance, Entry>, root: &Instance) -> Option<Vec<Instance>> {
+    let mut t = Tarjan {
+        instances,
+        index: HashMap::new(),
+        low: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: HashSet::new(),
+        order: Vec::new(),
+    };
+    match t.visit(root) {
+        true => Some(t.order),
+        false => None,
+    }
+}
+
+struct Tarjan<'a> {
+    instances: &'a HashMap<Instance, Entry>,
+    index: HashMap<Instance, usize>,
+    low: HashMap<Instance, usize>,
+    stack: Vec<Instance>,
+    on_stack: HashSet<Instance>,
+    order: Vec<Instance>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, v: &Instance) -> bool {
+        let i = self.index.len();
+        self.index.insert(v.clone(), i);
+        self.low.insert(v.clone(), i);
+        self.stack.push(v.clone());
+        self.on_stack.insert(v.clone());
+
+        for w in self.instances[v].deps.iter() {
+            match self.instances.get(w).map(|e| e.status) {
+                Some(Status::Executed) => continue,
+                Some(Status::Committed) => {}
+                _ => return false,
+            }
+            if !self.index.contains_key(w) {
+                if !self.visit(w) {
+                    return false;
+                }
+                let low = self.low[v].min(self.low[w]);
+                self.low.insert(v.clone(), low);
+            } else if self.on_stack.contains(w) {
+                let low = self.low[v].min(self.index[w]);
+                self.low.insert(v.clone(), low);
+            }
+        }
+
+        if self.low[v] == self.index[v] {
+            let mut scc = Vec::new();
+            loop {
+                let w = self.stack.pop().unwrap();
+                self.on_stack.remove(&w);
+                let done = w == *v;
+                scc.push(w);
+                if done {
+                    break;
+                }
+            }
+            scc.sort_by_key(|i| (self.instances[i].seq, i.clone()));
+            self.order.extend(scc);
+        }
+        true
+    }
+}
+
+/// A leaderless Egalitarian Paxos replica. Every replica leads the instances
+/// of the requests it receives: it pre-accepts the command with the
+/// instances it conflicts with, and commits it at once if a fast quorum
+/// agrees on them. Otherwise it merges what the replicas answered and commits
+/// after an Accept round on a majority. Committed commands are executed in
+/// dependency order, so commands on different keys never wait on each other.
+///
+/// Experimental: an instance whose leader fails before committing it is not
+/// recovered, and the instances depending on it are not executed.
+pub struct Replica {
+    me: ProcessId,
+    next: u64,
+    instances: HashMap<Instance, Entry>,
+    // the committed instances not executed yet
+    pending: BTreeSet<Instance>,
+    conflicts: Conflicts,
+    rounds: HashMap<Instance, Round>,
+    state: Box<dyn StateMachine + Send>,
+    // commands executed so far, given to the state machine as the slot
+    executed: SlotNumber,
+    // the highest instance of each replica stored, and the one up to which
+    // every instance of that replica is executed
+    highest: HashMap<ProcessId, u64>,
+    done: HashMap<ProcessId, u64>,
+    // every request applied, and the last response sent to each client,
+    // resent when it retries the request
+    applied: HashSet<(ProcessId, Bytes)>,
+    replies: HashMap<ProcessId, (Bytes, SlotNumber, Bytes)>,
+    // the requests applied in order, each with the highest instances stored
+    // then: one is forgotten once all of those are executed
+    horizons: VecDeque<(HashMap<ProcessId, u64>, (ProcessId, Bytes))>,
+}
+
+impl Replica {
+    pub fn new(id: ProcessId) -> Replica {
+        Replica::with_state(id, Box::new(KvStore::new()))
+    }
+
+    pub fn with_state(id: ProcessId, state: Box<dyn StateMachine + Send>) -> Replica {
+        Replica {
+            me: id,
+            next: 0,
+            instances: HashMap::new(),
+            pending: BTreeSet::new(),
+            conflicts: Conflicts::new(),
+            rounds: HashMap::new(),
+            state,
+            executed: 0,
+            highest: HashMap::new(),
+            done: HashMap::new(),
+            applied: HashSet::new(),
+            replies: HashMap::new(),
+            horizons: VecDeque::new(),
+        }
+    }
+
+    fn broadcast<T: Router, E: Env<T>>(&self, m: Message, env: &E) {
+        for r in env.cluster().replicas().iter().filter(|r| **r != self.me) {
+            env.router().send(r, m.clone());
+        }
+    }
+
+    fn attrs(&self, c: &Command, inst: &Instance) -> (u64, Deps) {
+        match key(c) {
+            Some(k) => self.conflicts.attrs(&k, inst),
+            None => (1, Deps::new()),
+        }
+    }
+
+    /// Records an instance, a committed one is never changed again.
+    fn store(&mut self, inst: &Instance, command: Command, seq: u64, deps: Deps, status: Status) {
+        if let Some(e) = self.instances.get(inst) {
+            if e.status > status || e.status >= Status::Committed {
+                return;
+            }
+        }
+        if let Some(k) = key(&command) {
+            self.conflicts.add(k, inst, seq);
+        }
+        if status == Status::Committed {
+            self.pending.insert(inst.clone());
+        }
+        let highest = self.highest.entry(inst.replica.clone()).or_insert(0);
+        *highest = (*highest).max(inst.number);
+        let e = Entry {
+            command,
+            seq,
+            deps,
+            status,
+        };
+        self.instances.insert(inst.clone(), e);
+    }
+
+    fn request<T: Router, E: Env<T>>(&mut self, c: Command, env: &E) {
+        self.next += 1;
+        let inst = Instance::new(self.me.clone(), self.next);
+        let (seq, deps) = self.attrs(&c, &inst);
+        self.store(&inst, c.clone(), seq, deps.clone(), Status::PreAccepted);
+        let m = Message::PreAccept(self.me.clone(), inst.clone(), c.clone(), seq, deps.clone());
+        self.broadcast(m, env);
+        let now = Instant::now();
+        let round = Round {
+            phase: Phase::PreAccept,
+            command: c,
+            seq,
+            deps,
+            replies: HashMap::new(),
+            acks: HashSet::new(),
+            started: now,
+            sent: now,
+        };
+        self.rounds.insert(inst.clone(), round);
+        self.check(&inst, env);
+    }
+
+    /// Moves the round of `inst` forward once enough replicas answered: to a
+    /// commit when a fast quorum agrees with the PreAccept or a majority
+    /// accepted, to an Accept when they disagree or the fast quorum is slow.
+    fn check<T: Router, E: Env<T>>(&mut self, inst: &Instance, env: &E) {
+        let (fast, slow) = quorums(env.cluster().replicas().len());
+        let r = match self.rounds.get_mut(inst) {
+            Some(r) => r,
+            None => return,
+        };
+        match r.phase {
+            Phase::PreAccept => {
+                let agreed = r
+                    .replies
+                    .values()
+                    .all(|(seq, deps)| *seq == r.seq && *deps == r.deps);
+                let answered = r.replies.len() + 1;
+                if agreed && answered >= fast {
+                    return self.commit(inst, env);
+                }
+                let late = r.started.elapsed() >= Duration::from_millis(FAST_PATH_TIMEOUT_MS);
+                if answered >= slow && (!agreed || late) {
+                    for (seq, deps) in r.replies.values() {
+                        r.seq = r.seq.max(*seq);
+                        r.deps.extend(deps.iter().cloned());
+                    }
+                    r.phase = Phase::Accept;
+                    r.sent = Instant::now();
+                    let (c, seq, deps) = (r.command.clone(), r.seq, r.deps.clone());
+                    self.store(inst, c.clone(), seq, deps.clone(), Status::Accepted);
+                    self.broadcast(
+                        Message::Accept(self.me.clone(), inst.clone(), c, seq, deps),
+                        env,
+                    );
+                    self.check(inst, env);
+                }
+            }
+            Phase::Accept => {
+                if r.acks.len() + 1 >= slow {
+                    self.commit(inst, env);
+                }
+            }
+        }
+    }
+
+    fn commit<T: Router, E: Env<T>>(&mut self, inst: &Instance, env: &E) {
+        let r = self.rounds.remove(inst).unwrap();
+        self.store(
+            inst,
+            r.command.clone(),
+            r.seq,
+            r.deps.clone(),
+            Status::Committed,
+        );
+        let m = Message::Commit(self.me.clone(), inst.clone(), r.command, r.seq, r.deps);
+        self.broadcast(m, env);
+        self.execute(env);
+    }
+
+    /// Resends what the replicas that have not answered are missing, and
+    /// takes the slow path when the fast quorum does not answer in time.
+    fn tick<T: Router, E: Env<T>>(&mut self, env: &E) {
+        let retransmit = Duration::from_millis(RETRANSMIT_MS);
+        let insts: Vec<Instance> = self.rounds.keys().cloned().collect();
+        for inst in insts {
+            self.check(&inst, env);
+            let r = match self.rounds.get_mut(&inst) {
+                Some(r) if r.sent.elapsed() >= retransmit => r,
+                _ => continue,
+            };
+            r.sent = Instant::now();
+            let (c, seq, deps) = (r.command.clone(), r.seq, r.deps.clone());
+            let (m, answered) = match r.phase {
+                Phase::PreAccept => (
+                    Message::PreAccept(self.me.clone(), inst.clone(), c, seq, deps),
+                    r.replies.keys().cloned().collect::<HashSet<_>>(),
+                ),
+                Phase::Accept => (
+                    Message::Accept(self.me.clone(), inst.clone(), c, seq, deps),
+                    r.acks.clone(),
+                ),
+            };
+            for p in env.cluster().replicas().iter() {
+                if *p != self.me && !answered.contains(p) {
+                    env.router().send(p, m.clone());
+                }
+            }
+        }
+    }
+
+    /// Executes every committed instance whose dependencies are committed.
+    fn execute<T: Router, E: Env<T>>(&mut self, env: &E) {
+        let committed: Vec<Instance> = self.pending.iter().cloned().collect();
+        for inst in committed {
+            if !self.pending.contains(&inst) {
+                continue;
+            }
+            if let Some(order) = execution_order(&self.instances, &inst) {
+                for i in order {
+                    self.perform(&i, env);
+                }
+            }
+        }
+    }
+
+    fn perform<T: Router, E: Env<T>>(&mut self, inst: &Instance, env: &E) {
+        let e = self.instances.get_mut(inst).unwrap();
+        e.status = Status::Executed;
+        let c = e.command.clone();
+        self.pending.remove(inst);
+        self.executed += 1;
+
+        // a command retried on another replica runs in two instances, and
+        // is only applied the first time
+        let key = (c.client.clone(), c.req_id.clone());
+        let result = match self.replies.get(&c.client) {
+            Some((req_id, _, result)) if *req_id == c.req_id => result.clone(),
+            // the client has moved on to later requests since
+            _ if self.applied.contains(&key) => Bytes::new(),
+            _ => {
+                info!("Replica {} performed {}", self.me, c);
+                trace::record(Stage::Perform, &self.me, &c, Some(self.executed));
+                let result = self.state.apply(self.executed, &c);
+                self.applied.insert(key.clone());
+                self.horizons.push_back((self.highest.clone(), key));
+                self.replies.insert(
+                    c.client.clone(),
+                    (c.req_id.clone(), self.executed, result.clone()),
+                );
+                result
+            }
+        };
+        if inst.replica == self.me {
+            let client = c.client.clone();
+            let m = Message::Response(self.me.clone(), self.executed, c, result);
+            env.router().send(&client, m);
+        }
+        self.prune(&inst.replica);
+    }
+
+    /// Moves the executed watermark of `replica` past its instances executed
+    /// since, and forgets the requests applied before every instance stored
+    /// at the time was executed: a copy of one in an instance stored since is
+    /// no longer told apart, the client is expected to have moved on.
+    fn prune(&mut self, replica: &ProcessId) {
+        let done = self.done.entry(replica.clone()).or_insert(0);
+        loop {
+            let next = Instance::new(replica.clone(), *done + 1);
+            match self.instances.get(&next) {
+                Some(e) if e.status == Status::Executed => *done += 1,
+                _ => break,
+            }
+        }
+        while let Some((horizon, key)) = self.horizons.front() {
+            let executed = horizon
+                .iter()
+                .all(|(r, n)| self.done.get(r).is_some_and(|d| d >= n));
+            if !executed {
+                break;
+            }
+            self.applied.remove(key);
+            self.horizons.pop_front();
+        }
+    }
+}
+
+impl Executor for Replica {
+    fn exec<R: Receiver, T: Router, E: Env<T>>(mut self, reciever: R, env: &E) {
+        loop {
+            self.tick(env);
+            let wait = Duration::from_millis(FAST_PATH_TIMEOUT_MS);
+            let msg = match reciever.get_timeout(SLEEP_TIME, wait) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+
+            match msg {
+                Message::Request(_, command) => {
+                    trace::record(Stage::Request, &self.me, &command, None);
+                    match self.replies.get(&command.client) {
+                        Some((req_id, slot, result)) if *req_id == command.req_id => {
+                            let m = Message::Response(
+                                self.me.clone(),
+                                *slot,
+                                command.clone(),
+                                result.clone(),
+                            );
+                            env.router().send(&command.client, m);
+                        }
+                        _ => self.request(command, env),
+                    }
+                }
+                Message::PreAccept(src, inst, command, seq, mut deps) => {
+                    let (s, d) = self.attrs(&command, &inst);
+                    let seq = seq.max(s);
+                    deps.extend(d);
+                    self.store(&inst, command, seq, deps.clone(), Status::PreAccepted);
+                    // once committed, the attributes it was committed with
+                    let e = &self.instances[&inst];
+                    let m = Message::PreAcceptOk(self.me.clone(), inst, e.seq, e.deps.clone());
+                    env.router().send(&src, m);
+                }
+                Message::PreAcceptOk(src, inst, seq, deps) => {
+                    if let Some(r) = self.rounds.get_mut(&inst) {
+                        if r.phase == Phase::PreAccept {
+                            r.replies.insert(src, (seq, deps));
+                            self.check(&inst, env);
+                        }
+                    }
+                }
+                Message::Accept(src, inst, command, seq, deps) => {
+                    self.store(&inst, command, seq, deps, Status::Accepted);
+                    env.router()
+                        .send(&src, Message::AcceptOk(self.me.clone(), inst));
+                }
+                Message::AcceptOk(src, inst) => {
+                    if let Some(r) = self.rounds.get_mut(&inst) {
+                        if r.phase == Phase::Accept {
+                            r.acks.insert(src);
+                            self.check(&inst, env);
+                        }
+                    }
+                }
+                Message::Commit(_, inst, command, seq, deps) => {
+                    self.store(&inst, command, seq, deps, Status::Committed);
+                    self.execute(env);
+                }
+                m => warn!("Replica {} ignored {}", self.me, m),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::{IpAddr, Ipv4Addr},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use bytes::Bytes;
+
+    use crate::{
+        env::{Env, ProcessId, ProcessType, Router},
+        fixture,
+        message::Message,
+        pval::{Command, Deps, Instance, SlotNumber},
+        state::{KvStore, Op, StateMachine},
+    };
+
+    use super::{execution_order, quorums, Entry, Replica, Status};
+
+    fn pid(id: u32) -> ProcessId {
+        ProcessId::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0, id)
+    }
+
+    #[test]
+    fn order() {
+        assert_eq!(quorums(3), (2, 2));
+        assert_eq!(quorums(5), (3, 3));
+        assert_eq!(quorums(7), (5, 4));
+
+        // 1 and 2 depend on each other and on 3, which is executed first
+        let inst = |n| Instance::new(pid(n), 1);
+        let entry = |seq, deps: &[u32]| Entry {
+            command: Command::noop(pid(0)),
+            seq,
+            deps: deps.iter().map(|d| inst(*d)).collect::<Deps>(),
+            status: Status::Committed,
+        };
+        let mut instances = HashMap::new();
+        instances.insert(inst(1), entry(3, &[2, 3]));
+        instances.insert(inst(2), entry(2, &[1]));
+        instances.insert(inst(3), entry(1, &[]));
+        assert_eq!(
+            execution_order(&instances, &inst(1)),
+            Some(vec![inst(3), inst(2), inst(1)])
+        );
+
+        instances.get_mut(&inst(3)).unwrap().status = Status::Accepted;
+        assert_eq!(execution_order(&instances, &inst(1)), None);
+    }
+
+    /// A key-value store that also reports what it applies.
+    struct Log(KvStore, Arc<Mutex<Vec<Bytes>>>);
+
+    impl StateMachine for Log {
+        fn apply(&mut self, slot: SlotNumber, c: &Command) -> Bytes {
+            self.1.lock().unwrap().push(c.operation.clone());
+            self.0.apply(slot, c)
+        }
+    }
+
+    #[test]
+    fn conflicting_commands_execute_in_the_same_order() {
+        let env = fixture::env();
+        let mut logs = Vec::new();
+        for _ in 0..3 {
+            let id = fixture::new_id(env);
+            let log = Arc::new(Mutex::new(Vec::new()));
+            let replica =
+                Replica::with_state(id.clone(), Box::new(Log(KvStore::new(), log.clone())));
+            env.register(id, ProcessType::Replica, replica);
+            logs.push(log);
+        }
+        let (client, r) = fixture::probe(env, ProcessType::Client);
+
+        // every replica leads some of the commands, half of them on one key
+        let n = 60;
+        let replicas = env.cluster().replicas();
+        for i in 0..n {
+            let key = match i % 2 {
+                0 => format!("hot"),
+                _ => format!("k{}", i),
+            };
+            let c = Command::new_from_str(
+                client.clone(),
+                format!("{}", i),
+                format!("PUT {} {}", key, i),
+            );
+            env.router()
+                .send(&replicas[i % 3], Message::Request(client.clone(), c));
+        }
+        for _ in 0..n {
+            match r.recv_timeout(fixture::WAIT).unwrap() {
+                Message::Response(_, _, _, result) => assert_eq!(result, "OK"),
+                m => panic!("unexpected {}", m),
+            }
+        }
+
+        // the others may still be executing the last commits
+        let on_hot = |log: &Arc<Mutex<Vec<Bytes>>>| -> Vec<Bytes> {
+            let log = log.lock().unwrap();
+            let hot = log.iter().filter(|op| match Op::parse(op) {
+                Some(Op::Put(k, _)) => k == "hot",
+                _ => false,
+            });
+            hot.cloned().collect()
+        };
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while logs.iter().any(|l| l.lock().unwrap().len() < n)
+            && std::time::Instant::now() < deadline
+        {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let first = on_hot(&logs[0]);
+        assert_eq!(first.len(), n / 2);
+        for log in logs.iter() {
+            assert_eq!(log.lock().unwrap().len(), n);
+            assert_eq!(on_hot(log), first);
+        }
+    }
+
+    #[test]
+    fn applied_is_pruned() {
+        let env = fixture::env();
+        let (client, r) = fixture::probe(env, ProcessType::Client);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (a, b) = (pid(1), pid(2));
+        let mut replica =
+            Replica::with_state(a.clone(), Box::new(Log(KvStore::new(), log.clone())));
+        let put = |req: &str, v: &str| {
+            Command::new_from_str(
+                client.clone(),
+                req.to_string(),
+                format!("PUT k{} {}", req, v),
+            )
+        };
+        let commit = |replica: &mut Replica, inst: &Instance, c: Command| {
+            replica.store(inst, c, 1, Deps::new(), Status::Committed);
+            replica.execute(env);
+        };
+
+        // the client retried its first request on b, which started an
+        // instance for it before the one of a was executed
+        let (a1, a2, b1) = (
+            Instance::new(a.clone(), 1),
+            Instance::new(a.clone(), 2),
+            Instance::new(b.clone(), 1),
+        );
+        replica.store(&b1, put("1", "x"), 1, Deps::new(), Status::PreAccepted);
+        commit(&mut replica, &a1, put("1", "x"));
+        commit(&mut replica, &a2, put("2", "y"));
+        assert_eq!(replica.applied.len(), 2);
+        commit(&mut replica, &b1, put("1", "x"));
+        assert_eq!(log.lock().unwrap().len(), 2);
+
+        // every instance stored when they were applied is executed
+        assert!(replica.applied.is_empty() && replica.horizons.is_empty());
+        assert_eq!(replica.done[&a], 2);
+        assert_eq!(replica.done[&b], 1);
+        for req in ["1", "2"] {
+            match r.recv_timeout(fixture::WAIT).unwrap() {
+                Message::Response(_, _, c, _) => assert_eq!(c.req_id, req),
+                m => panic!("unexpected {}", m),
+            }
+        }
+    }
+}
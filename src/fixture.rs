@@ -0,0 +1,113 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel;
+
+use super::{
+    acceptor::Acceptor,
+    constants::SLEEP_TIME,
+    env::{Env, Executor, GetErr, ProcessId, ProcessType, Receiver, Router},
+    local::InMemEnv,
+    message::Message,
+    pval::{BallotNumber, Command, SlotNumber},
+};
+
+/// The env the tests run their roles in.
+pub type TestEnv = InMemEnv<channel::Receiver<Message>, channel::Sender<Message>>;
+
+/// How long a test waits for something that should happen quickly.
+pub static WAIT: Duration = Duration::from_secs(10);
+
+pub fn env() -> &'static TestEnv {
+    Box::leak(Box::new(InMemEnv::new(|| {
+        let (s, r) = channel::unbounded();
+        return (r, s);
+    })))
+}
+
+/// A process id not used yet in `env`, or in its siblings.
+pub fn new_id(env: &TestEnv) -> ProcessId {
+    let local_host = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    ProcessId::new(local_host, 0, env.new_id())
+}
+
+/// Stands in for any role, and hands over what it receives.
+pub struct Probe(pub channel::Sender<Message>);
+
+impl Executor for Probe {
+    fn exec<R: Receiver, T: Router, E: Env<T>>(self, reciever: R, _: &'static E) {
+        loop {
+            let _ = self.0.send(reciever.get(SLEEP_TIME));
+        }
+    }
+}
+
+/// Registers a `Probe` as a `t`, and returns its id and what it receives.
+pub fn probe(env: &'static TestEnv, t: ProcessType) -> (ProcessId, channel::Receiver<Message>) {
+    let (s, r) = channel::unbounded();
+    let id = new_id(env);
+    env.register(id.clone(), t, Probe(s));
+    (id, r)
+}
+
+/// Runs an executor that loses every message while `down` is set.
+pub struct Flaky<X>(pub X, pub Arc<AtomicBool>);
+
+struct Gate<R>(R, Arc<AtomicBool>);
+
+impl<R: Receiver> Receiver for Gate<R> {
+    fn try_get(&self) -> Result<Message, GetErr> {
+        match self.0.try_get() {
+            Ok(_) if self.1.load(Ordering::SeqCst) => Err(GetErr::None),
+            r => r,
+        }
+    }
+
+    fn get(&self, sleep: u64) -> Message {
+        loop {
+            match self.try_get() {
+                Ok(m) => return m,
+                Err(_) => thread::sleep(Duration::from_nanos(sleep)),
+            }
+        }
+    }
+}
+
+impl<X: Executor> Executor for Flaky<X> {
+    fn exec<R: Receiver, T: Router, E: Env<T>>(self, reciever: R, env: &'static E) {
+        self.0.exec(Gate(reciever, self.1), env)
+    }
+}
+
+pub fn acceptors(env: &'static TestEnv, n: usize) -> Vec<ProcessId> {
+    (0..n)
+        .map(|_| {
+            let id = new_id(env);
+            env.register(id.clone(), ProcessType::Acceptor, Acceptor::new(id.clone()));
+            id
+        })
+        .collect()
+}
+
+/// Waits for the decision of `slot` among the messages of a probe, and
+/// returns the command and the ballot it was decided with.
+pub fn wait_decision(
+    r: &channel::Receiver<Message>,
+    slot: SlotNumber,
+) -> (Command, Option<BallotNumber>) {
+    let deadline = Instant::now() + WAIT;
+    loop {
+        match r.recv_deadline(deadline) {
+            Ok(Message::Decision(_, s, c, b)) if s == slot => return (c, b),
+            Ok(_) => {}
+            Err(_) => panic!("slot {} was not decided in time", slot),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use log::debug;
+
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok(body: String) -> Response {
+        Response { status: 200, body }
+    }
+
+    pub fn not_found() -> Response {
+        Response {
+            status: 404,
+            body: String::from("not found\n"),
+        }
+    }
+}
+
+pub fn bind(addr: &str) -> io::Result<TcpListener> {
+    TcpListener::bind(addr)
+}
+
+/// Minimal HTTP/1.0 server for the text endpoints, handles one request per
+/// connection on the calling thread. `handler` gets the method and the path.
+pub fn serve<F>(listener: TcpListener, handler: F)
+where
+    F: Fn(&str, &str) -> Response,
+{
+    for stream in listener.incoming() {
+        match stream {
+            Ok(s) => handle(s, &handler),
+            Err(e) => debug!("http accept failed {}", e),
+        }
+    }
+}
+
+fn handle<F>(mut stream: TcpStream, handler: &F)
+where
+    F: Fn(&str, &str) -> Response,
+{
+    let mut line = String::new();
+    if BufReader::new(&stream).read_line(&mut line).is_err() {
+        return;
+    }
+
+    let mut parts = line.split_whitespace();
+    let res = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => handler(method, path),
+        _ => Response {
+            status: 400,
+            body: String::from("bad request\n"),
+        },
+    };
+
+    let reason = match res.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        _ => "Error",
+    };
+    let _ = write!(
+        stream,
+        "HTTP/1.0 {} {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+        res.status,
+        reason,
+        res.body.len(),
+        res.body
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        thread,
+    };
+
+    use super::{bind, serve, Response};
+
+    fn get(addr: &str, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+        res
+    }
+
+    #[test]
+    fn requests() {
+        let listener = bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            serve(listener, |method, path| match path {
+                "/missing" => Response::not_found(),
+                _ => Response::ok(format!("{} {}\n", method, path)),
+            })
+        });
+
+        assert_eq!(
+            get(&addr, "POST /a/b HTTP/1.0\r\n\r\n"),
+            "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: 10\r\n\r\nPOST /a/b\n"
+        );
+        let res = get(&addr, "GET /missing HTTP/1.0\r\n\r\n");
+        assert!(res.starts_with("HTTP/1.0 404 Not Found\r\n"));
+        assert!(res.ends_with("\r\n\r\nnot found\n"));
+        // a request line without a path
+        let res = get(&addr, "GET\r\n\r\n");
+        assert!(res.starts_with("HTTP/1.0 400 Bad Request\r\n"));
+        assert!(res.ends_with("\r\n\r\nbad request\n"));
+    }
+
+    #[test]
+    fn bind_in_use() {
+        let listener = bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        assert!(bind(&addr).is_err());
+    }
+}
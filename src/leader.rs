@@ -1,12 +1,29 @@
 use core::panic;
-use std::collections::{hash_map::Iter, HashMap, HashSet};
+use std::{
+    collections::{hash_map::Iter, BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use log::{info, warn};
 
 use super::{
-    constants::SLEEP_TIME,
-    ds::Accepted,
+    admin,
+    constants::{
+        DEADLINE_MS, FAST_PATH_TIMEOUT_MS, HEARTBEAT_MS, LEADER_TIMEOUT_MS, RETRANSMIT_MAX_MS,
+        RETRANSMIT_MS, SLEEP_TIME, STEP_DOWN_MS, THRIFTY_TIMEOUT_MS,
+    },
+    ds::{pick, pmax, triangle, Accepted, LastSeen},
     env::{Env, Executor, ProcessId, ProcessType, Receiver, Router},
     message::Message,
+    metrics::{process, REGISTRY},
     pval::{BallotNumber, Command, SlotNumber},
+    quorum::QuorumSystem,
+    state::OK,
+    trace::{self, Stage},
+    vertical::{Assignment, MasterOp},
 };
 
 #[derive(PartialEq, Eq)]
@@ -20,55 +37,595 @@ struct Proposal {
     status: Status,
 }
 
+impl Proposal {
+    fn pending(command: Command) -> Proposal {
+        Proposal {
+            command,
+            status: Status::PENDING,
+        }
+    }
+}
+
 struct Proposals {
     m: HashMap<SlotNumber, Proposal>,
+    // the slots of the pending proposals, in order
+    pending: BTreeSet<SlotNumber>,
+    // every slot up to this one is decided, and forgotten unless still
+    // needed, see `prune`
+    floor: SlotNumber,
 }
 impl Proposals {
+    fn new() -> Proposals {
+        Proposals {
+            m: HashMap::new(),
+            pending: BTreeSet::new(),
+            floor: 0,
+        }
+    }
+
     fn has(&self, slot: &u64) -> bool {
-        self.m.contains_key(slot)
+        *slot <= self.floor || self.m.contains_key(slot)
     }
 
     fn insert(&mut self, slot: u64, command: Command) {
-        self.m.insert(
-            slot,
-            Proposal {
-                command: command,
-                status: Status::PENDING,
-            },
+        self.m.insert(slot, Proposal::pending(command));
+        self.pending.insert(slot);
+    }
+
+    /// Proposes the values phase 1 found in place of ours.
+    fn adopt(&mut self, values: HashMap<SlotNumber, Command>) {
+        self.pending.extend(values.keys());
+        triangle(
+            &mut self.m,
+            values
+                .into_iter()
+                .map(|(s, c)| (s, Proposal::pending(c)))
+                .collect(),
         );
     }
 
     fn pending(&self) -> impl Iterator<Item = (&SlotNumber, &Proposal)> {
-        self.m.iter().filter(|i| i.1.status == Status::PENDING)
+        self.pending.iter().map(move |s| (s, &self.m[s]))
+    }
+
+    fn pending_command(&self, slot: &u64) -> Option<Command> {
+        self.m
+            .get(slot)
+            .filter(|p| p.status == Status::PENDING)
+            .map(|p| p.command.clone())
+    }
+
+    fn done(&mut self, slot: &u64, command: Command) {
+        let x = self.m.entry(*slot).or_insert(Proposal::pending(command));
+        x.status = Status::DONE;
+        self.pending.remove(slot);
     }
 
-    fn done(&mut self, slot: &u64) {
-        let x = self.m.get_mut(slot).expect("should always be present");
-        x.status = Status::DONE
+    fn is_done(&self, slot: &u64) -> bool {
+        match self.m.get(slot) {
+            Some(p) => p.status == Status::DONE,
+            None => *slot <= self.floor,
+        }
+    }
+
+    /// Forgets the slots up to `decided` but the ones `kept`.
+    fn prune(&mut self, decided: SlotNumber, kept: impl Fn(&SlotNumber) -> bool) {
+        while self.floor < decided {
+            self.floor += 1;
+            if !kept(&self.floor) {
+                self.m.remove(&self.floor);
+            }
+        }
+    }
+
+    /// Forgets a decided slot kept by `prune` until now.
+    fn forget(&mut self, slot: &u64) {
+        if *slot <= self.floor && self.is_done(slot) {
+            self.m.remove(slot);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LeaderConfig {
+    pub quorum: QuorumSystem,
+    /// Send P2A only to a phase 2 quorum of the most recently responsive
+    /// acceptors, and to the rest only if they do not answer within
+    /// `thrifty_timeout`.
+    pub thrifty: bool,
+    pub thrifty_timeout: Duration,
+    /// Scouts and commanders resend to the acceptors that have not answered,
+    /// starting after `retransmit` and doubling up to `retransmit_max`, and
+    /// report `Message::Failed` to the leader once `deadline` has passed.
+    pub retransmit: Duration,
+    pub retransmit_max: Duration,
+    pub deadline: Duration,
+    /// Distinguished leader mode: once adopted, a leader keeps its ballot and
+    /// sends a heartbeat to the other leaders every `heartbeat`. A preempted
+    /// leader does not scout again but forwards proposals to the leader that
+    /// preempted it, until it has not heard from it for `leader_timeout`.
+    pub stable: bool,
+    pub heartbeat: Duration,
+    pub leader_timeout: Duration,
+    /// Mencius mode: slots are owned round-robin by the leaders, see `owner`.
+    /// Each leader proposes in its own slots with a ballot shared by all of
+    /// them and set up front, so there is no phase 1, and fills the slots it
+    /// has nothing for with no-ops once a later slot is decided. A slot left
+    /// undecided for `leader_timeout` while later ones are is taken over with
+    /// a regular phase 1, and the leaders fall back to the classical protocol.
+    pub mencius: bool,
+    /// Every leader of the cluster in Mencius mode, the same on all of them.
+    /// When empty, a leader waits until it is registered and takes the
+    /// leaders registered by then, which are all of them only if they are
+    /// registered before any of them starts.
+    pub leaders: Vec<ProcessId>,
+    /// Fast Paxos: once adopted, a leader makes its ballot fast for the slots
+    /// above its proposals, replicas send their commands straight to the
+    /// acceptors and a command is decided when a fast quorum accepts it (see
+    /// `QuorumSystem::fast`). When acceptors accept different commands for a
+    /// slot, or a fast quorum does not answer within `fast_timeout`, the
+    /// leader recovers with a classic round on a higher ballot.
+    pub fast: bool,
+    pub fast_timeout: Duration,
+    /// Vertical Paxos, when not empty: the replicas of a configuration master
+    /// (`vertical::ConfigMaster`) that hands out the rounds. The leader asks
+    /// it for a round on the current acceptors, runs phase 1 on the acceptors
+    /// of the last complete round and phase 2 on its own, and only proposes
+    /// new commands once it has written what it read and the master has
+    /// marked the round complete. Not combined with Mencius or fast rounds.
+    pub masters: Vec<ProcessId>,
+}
+
+impl Default for LeaderConfig {
+    fn default() -> Self {
+        Self {
+            quorum: QuorumSystem::Majority,
+            thrifty: false,
+            thrifty_timeout: Duration::from_millis(THRIFTY_TIMEOUT_MS),
+            retransmit: Duration::from_millis(RETRANSMIT_MS),
+            retransmit_max: Duration::from_millis(RETRANSMIT_MAX_MS),
+            deadline: Duration::from_millis(DEADLINE_MS),
+            stable: false,
+            heartbeat: Duration::from_millis(HEARTBEAT_MS),
+            leader_timeout: Duration::from_millis(LEADER_TIMEOUT_MS),
+            mencius: false,
+            leaders: vec![],
+            fast: false,
+            fast_timeout: Duration::from_millis(FAST_PATH_TIMEOUT_MS),
+            masters: vec![],
+        }
     }
 }
 
+/// The leader owning `slot` in Mencius mode, slots are dealt round-robin to
+/// the leaders in id order starting from slot 1.
+pub fn owner(mut leaders: Vec<ProcessId>, slot: SlotNumber) -> Option<ProcessId> {
+    if leaders.is_empty() {
+        return None;
+    }
+    leaders.sort();
+    let i = (slot.saturating_sub(1) % leaders.len() as u64) as usize;
+    Some(leaders.swap_remove(i))
+}
+
 pub struct Leader {
     me: ProcessId,
     ballot: BallotNumber,
     active: bool,
     proposals: Proposals,
+    // every slot up to this one is decided, scouts do not ask for them
+    decided: SlotNumber,
+    config: LeaderConfig,
+    seen: Arc<LastSeen>,
+    // set when told to step down, no scouting until then
+    passive_until: Option<Instant>,
+    // in stable mode, the ballot of the active leader and when it was last
+    // heard from
+    following: Option<(BallotNumber, Instant)>,
+    next_heartbeat: Instant,
+    // in Mencius mode, the ballot all the leaders start with, and the leaders
+    // in id order with the index of this one, see `owner`
+    shared: Option<BallotNumber>,
+    rotation: Vec<ProcessId>,
+    index: usize,
+    // highest decided slot, and since when the one after `decided` is missing
+    highest: SlotNumber,
+    stalled: Option<(SlotNumber, Instant)>,
+    // the ballot is fast from this slot on, and what each acceptor accepted
+    // in the slots not decided yet
+    fast: Option<SlotNumber>,
+    votes: HashMap<SlotNumber, (Instant, HashMap<ProcessId, Command>)>,
+    // in Vertical Paxos, the configuration of the ballot, the request to the
+    // master waiting for an answer and the master it was sent to, and, until
+    // the round is complete, the highest slot read in phase 1 and the slots
+    // read not yet written to the new configuration
+    assignment: Option<Assignment>,
+    asked: Option<(Command, Instant, usize)>,
+    completing: Option<(SlotNumber, HashSet<SlotNumber>)>,
+    // slots decided while an acceptor was down, with when they were last
+    // proposed to the acceptors again, the witnesses drop them after that
+    witnessed: BTreeMap<SlotNumber, Option<Instant>>,
 }
 
 impl Leader {
     pub fn new(me: ProcessId) -> Leader {
+        Leader::with_config(me, LeaderConfig::default())
+    }
+
+    pub fn with_config(me: ProcessId, config: LeaderConfig) -> Leader {
         Leader {
             me: me.clone(),
             ballot: BallotNumber::first(me),
             active: false,
-            proposals: Proposals { m: HashMap::new() },
+            proposals: Proposals::new(),
+            decided: 0,
+            config,
+            seen: Arc::new(LastSeen::new()),
+            passive_until: None,
+            following: None,
+            next_heartbeat: Instant::now(),
+            shared: None,
+            rotation: vec![],
+            index: 0,
+            highest: 0,
+            stalled: None,
+            fast: None,
+            votes: HashMap::new(),
+            assignment: None,
+            asked: None,
+            completing: None,
+            witnessed: BTreeMap::new(),
+        }
+    }
+
+    /// Makes the ballot fast from slot `from` on.
+    fn open<T: Router, E: Env<T>>(&mut self, from: SlotNumber, env: &'static E) {
+        self.fast = Some(from);
+        self.votes.clear();
+        let m = Message::FastAny(self.me.clone(), self.ballot.clone(), from);
+        for a in env.cluster().acceptors().iter() {
+            env.router().send(a, m.clone());
+        }
+    }
+
+    /// A classic round on a higher ballot, its phase 1 finds the command that
+    /// may have been chosen in every slot the fast round did not decide.
+    fn recover<T: Router, E: Env<T>>(&mut self, env: &'static E) {
+        info!("fast round of {} did not decide, recovering", self.me);
+        self.fast = None;
+        self.votes.clear();
+        self.active = false;
+        self.ballot = BallotNumber::new(self.ballot.round + 1, self.me.clone());
+        self.scout(self.ballot.clone(), env);
+    }
+
+    /// Counts the command an acceptor accepted in a fast slot, and decides it
+    /// once a fast quorum has, or recovers when no command can get there.
+    fn vote<T: Router, E: Env<T>>(
+        &mut self,
+        acceptor: ProcessId,
+        slot: SlotNumber,
+        command: Command,
+        env: &'static E,
+    ) {
+        let acceptors = env.cluster().acceptors();
+        let size = self.config.quorum.fast(&acceptors).unwrap();
+        let (_, votes) = self
+            .votes
+            .entry(slot)
+            .or_insert((Instant::now(), HashMap::new()));
+        votes.insert(acceptor, command);
+
+        let mut best: Option<(&Command, usize)> = None;
+        for c in votes.values() {
+            let n = votes.values().filter(|v| *v == c).count();
+            if best.map_or(true, |(_, m)| n > m) {
+                best = Some((c, n));
+            }
+        }
+        let (c, n) = best.unwrap();
+        if n >= size {
+            let c = c.clone();
+            self.votes.remove(&slot);
+            trace::record(Stage::Decision, &self.me, &c, Some(slot));
+            let decision = Message::Decision(self.me.clone(), slot, c, Some(self.ballot.clone()));
+            for r in env.cluster().replicas().iter() {
+                env.router().send(r, decision.clone());
+            }
+            env.router().send(&self.me, decision);
+        } else if n + acceptors.len() - votes.len() < size {
+            self.recover(env);
+        }
+    }
+
+    /// Sorts the leaders for `owner` and `owns`, and tells whether this
+    /// leader is one of them.
+    fn deal<T: Router, E: Env<T>>(&mut self, env: &'static E) -> bool {
+        self.rotation = self.config.leaders.clone();
+        while self.rotation.is_empty() {
+            let leaders = env.cluster().leaders();
+            if leaders.contains(&self.me) {
+                self.rotation = leaders;
+            } else {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        self.rotation.sort();
+        match self.rotation.iter().position(|l| *l == self.me) {
+            Some(i) => {
+                self.index = i;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The leader owning `slot` in Mencius mode.
+    fn owner(&self, slot: SlotNumber) -> &ProcessId {
+        &self.rotation[(slot.saturating_sub(1) % self.rotation.len() as u64) as usize]
+    }
+
+    fn owns(&self, slot: SlotNumber) -> bool {
+        slot.saturating_sub(1) % self.rotation.len() as u64 == self.index as u64
+    }
+
+    /// Still proposing in its own slots with the shared ballot.
+    fn rotating(&self) -> bool {
+        self.active && self.shared.as_ref() == Some(&self.ballot)
+    }
+
+    /// Fills the slots this leader owns below `slot` with no-ops, nobody else
+    /// proposes in them.
+    fn skip<T: Router, E: Env<T>>(&mut self, slot: SlotNumber, env: &'static E) {
+        for s in (self.decided + 1)..slot {
+            if self.proposals.has(&s) || !self.owns(s) {
+                continue;
+            }
+            let noop = Command::noop(self.me.clone());
+            self.proposals.insert(s, noop.clone());
+            self.commander(self.ballot.clone(), s, noop, env);
+        }
+    }
+
+    /// Takes over with a phase 1 once the first undecided slot has been
+    /// missing for `leader_timeout` while later ones are decided, its owner
+    /// is likely gone.
+    fn revoke<T: Router, E: Env<T>>(&mut self, env: &'static E) {
+        let next = self.decided + 1;
+        if self.highest <= next {
+            self.stalled = None;
+            return;
+        }
+        match self.stalled {
+            Some((s, since)) if s == next => {
+                if since.elapsed() >= self.config.leader_timeout {
+                    info!("slot {} is stalled, {} takes over", s, self.me);
+                    self.stalled = None;
+                    self.active = false;
+                    self.ballot = BallotNumber::new(self.ballot.round + 1, self.me.clone());
+                    self.scout(self.ballot.clone(), env);
+                }
+            }
+            _ => self.stalled = Some((next, Instant::now())),
+        }
+    }
+
+    fn vertical(&self) -> bool {
+        !self.config.masters.is_empty()
+    }
+
+    /// Sends `op` to the configuration master, `tick` resends it to the next
+    /// master until one answers.
+    fn ask<T: Router, E: Env<T>>(&mut self, op: MasterOp, env: &'static E) {
+        let c = Command {
+            client: self.me.clone(),
+            req_id: Bytes::from(env.new_id().to_string()),
+            operation: op.encode(),
+        };
+        let m = Message::Request(self.me.clone(), c.clone());
+        env.router().send(&self.config.masters[0], m);
+        self.asked = Some((c, Instant::now(), 0));
+    }
+
+    /// Handles what the master answered to `op`.
+    fn answered<T: Router, E: Env<T>>(&mut self, op: MasterOp, result: Bytes, env: &'static E) {
+        match op {
+            MasterOp::New(ref acceptors) => match Assignment::parse(&result) {
+                Some(a) => {
+                    info!(
+                        "{} got round {} on configuration {}",
+                        self.me, a.round, a.config.id
+                    );
+                    self.ballot = BallotNumber::new(a.round, self.me.clone());
+                    self.active = false;
+                    self.scout_on(Some(a.readers().clone()), env);
+                    self.assignment = Some(a);
+                }
+                None => {
+                    warn!("{} got a bad round from the master: {:?}", self.me, result);
+                    self.ask(MasterOp::New(acceptors.clone()), env);
+                }
+            },
+            MasterOp::Complete(_) if result == OK => {
+                let (highest, _) = self.completing.take().unwrap();
+                self.activate(highest, env);
+            }
+            // a later round has been handed out, take another one in turn
+            MasterOp::Complete(_) => self.scout(self.ballot.clone(), env),
+        }
+    }
+
+    /// Asks the master to mark the round complete, once every value phase 1
+    /// read has been written to the acceptors of the round.
+    fn complete<T: Router, E: Env<T>>(&mut self, env: &'static E) {
+        let written = self
+            .completing
+            .as_ref()
+            .map_or(false, |(_, s)| s.is_empty());
+        if written && self.asked.is_none() {
+            self.ask(MasterOp::Complete(self.ballot.round), env);
+        }
+    }
+
+    /// Proposes every pending command with the adopted ballot, after filling
+    /// the holes below `highest`, the highest slot phase 1 found a value for.
+    fn activate<T: Router, E: Env<T>>(&mut self, highest: SlotNumber, env: &'static E) {
+        // a slot below the highest accepted one that no acceptor has a value
+        // for will never be proposed by anyone, fill it with a no-op so the
+        // replicas are not stuck on it
+        for s in (self.decided + 1)..highest {
+            if !self.proposals.has(&s) {
+                self.proposals.insert(s, Command::noop(self.me.clone()));
+            }
+        }
+
+        for (sn, c) in self.proposals.pending() {
+            self.commander(self.ballot.clone(), *sn, (*c).command.clone(), env);
+        }
+        self.active = true;
+        self.following = None;
+    }
+
+    /// The active leader to forward proposals to, while it is alive.
+    fn leader(&self) -> Option<&ProcessId> {
+        match &self.following {
+            Some((b, seen)) if seen.elapsed() < self.config.leader_timeout => Some(&b.process_id),
+            _ => None,
+        }
+    }
+
+    /// Follows the leader of `ballot` unless a newer one is known, and hands
+    /// it the proposals that came in while there was no leader to send them to.
+    fn follow<T: Router, E: Env<T>>(&mut self, ballot: BallotNumber, env: &'static E) {
+        let known = self.following.as_ref().map(|(b, _)| b.clone());
+        if known.as_ref().map_or(false, |b| *b > ballot) {
+            return;
+        }
+        if known.as_ref() != Some(&ballot) {
+            for (slot, p) in self.proposals.pending() {
+                let m = Message::Propose(self.me.clone(), *slot, p.command.clone());
+                env.router().send(&ballot.process_id, m);
+            }
+        }
+        self.following = Some((ballot, Instant::now()));
+    }
+
+    /// Heartbeats when leading and failure detection when following, in
+    /// stable mode only.
+    fn tick<T: Router, E: Env<T>>(&mut self, env: &'static E) {
+        if let Some((c, sent, i)) = self.asked.as_mut() {
+            if sent.elapsed() >= self.config.leader_timeout {
+                *i = (*i + 1) % self.config.masters.len();
+                *sent = Instant::now();
+                let m = Message::Request(self.me.clone(), c.clone());
+                env.router().send(&self.config.masters[*i], m);
+            }
+        }
+        if self.active {
+            self.rewrite(env);
+        }
+        if self.rotating() {
+            return self.revoke(env);
+        }
+        let timeout = self.config.fast_timeout;
+        if self.active && self.votes.values().any(|(t, _)| t.elapsed() >= timeout) {
+            return self.recover(env);
+        }
+        if !self.config.stable {
+            return;
+        }
+        if self.active {
+            if Instant::now() >= self.next_heartbeat {
+                let m = Message::Heartbeat(self.me.clone(), self.ballot.clone());
+                for l in env.cluster().leaders().iter().filter(|l| **l != self.me) {
+                    env.router().send(l, m.clone());
+                }
+                self.next_heartbeat = Instant::now() + self.config.heartbeat;
+            }
+        } else if self.following.is_some() && self.leader().is_none() {
+            let (b, _) = self.following.take().unwrap();
+            info!("leader {} timed out, {} takes over", b.process_id, self.me);
+            if self.passive_until.is_none() {
+                self.scout(self.ballot.clone(), env);
+            }
+        }
+    }
+
+    /// Proposes the slots decided with the votes of witnesses to the acceptors
+    /// again, so that the acceptors alone hold them once they are all up.
+    fn rewrite<T: Router, E: Env<T>>(&mut self, env: &'static E) {
+        let retry = self.config.leader_timeout;
+        let due: Vec<SlotNumber> = self
+            .witnessed
+            .iter()
+            .filter(|(_, sent)| sent.map_or(true, |t| t.elapsed() >= retry))
+            .map(|(s, _)| *s)
+            .collect();
+        for s in due {
+            self.witnessed.insert(s, Some(Instant::now()));
+            let c = self.proposals.m[&s].command.clone();
+            self.commander(self.ballot.clone(), s, c, env);
+        }
+    }
+
+    /// Takes note of a slot decided with this ballot: the witnesses may have
+    /// voted for it while an acceptor was down, and can drop it once it is
+    /// decided with the acceptors alone.
+    fn witness<T: Router, E: Env<T>>(&mut self, slot: SlotNumber, command: &Command, env: &E) {
+        let witnesses = env.cluster().witnesses();
+        if witnesses.is_empty() || self.vertical() {
+            return;
+        }
+        if self.seen.any_down(&env.cluster().acceptors()) {
+            self.witnessed.entry(slot).or_insert(None);
+        } else if self.witnessed.remove(&slot).is_some() {
+            let m = Message::Decision(self.me.clone(), slot, command.clone(), None);
+            for w in witnesses.iter() {
+                env.router().send(w, m.clone());
+            }
+        }
+    }
+
+    /// How long the loop may wait for a message before it has to `tick`.
+    fn wait(&self) -> Duration {
+        if self.rotating() {
+            return self.config.heartbeat;
+        }
+        match (self.config.stable, self.active) {
+            (false, _) => self.config.heartbeat,
+            (true, true) => self
+                .next_heartbeat
+                .saturating_duration_since(Instant::now()),
+            (true, false) => self.config.leader_timeout / 4,
+        }
+    }
+
+    fn scout<T: Router, E: Env<T>>(&mut self, ballot: BallotNumber, env: &'static E) {
+        if self.vertical() {
+            // the master picks the round, the scout starts once it answers
+            self.assignment = None;
+            self.completing = None;
+            return self.ask(MasterOp::New(env.cluster().acceptors()), env);
+        }
+        self.scout_on(None, env);
+    }
+
+    fn round(&self, ballot: BallotNumber, group: Option<Vec<ProcessId>>) -> Round {
+        Round {
+            leader: self.me.clone(),
+            ballot,
+            group,
+            seen: self.seen.clone(),
+            log: true,
         }
     }
 
-    fn scout<T: Router, E: Env<T>>(&self, ballot: BallotNumber, env: &'static E) {
+    /// Starts a scout for the ballot, on the acceptors of `group` if any.
+    fn scout_on<T: Router, E: Env<T>>(&self, group: Option<Vec<ProcessId>>, env: &'static E) {
         let sid = ProcessId::new(self.me.ip, self.me.port, env.new_id());
-        let scout = Scout::new(sid.clone(), self.me.clone(), self.ballot.clone());
-        env.register(scout.me.clone(), ProcessType::Scout, scout);
+        let round = self.round(self.ballot.clone(), group);
+        let scout = Scout::new(sid.clone(), round, self.decided + 1, self.config.clone());
+        env.register(sid, ProcessType::Scout, scout);
     }
 
     fn commander<T: Router, E: Env<T>>(
@@ -79,176 +636,1082 @@ impl Leader {
         env: &'static E,
     ) {
         let cid = ProcessId::new(self.me.ip, self.me.port, env.new_id());
-        let commander = Commander::new(&cid, &self.me, ballot, slot, command);
-        env.register(commander.me.clone(), ProcessType::Commander, commander);
+        let group = self.assignment.as_ref().map(|a| a.config.acceptors.clone());
+        let round = self.round(ballot, group);
+        let commander = Commander::new(cid.clone(), round, slot, command, self.config.clone());
+        env.register(cid, ProcessType::Commander, commander);
+    }
+
+    fn report(&self) {
+        let me = process(&self.me);
+        let l = [("process", me.as_str())];
+        REGISTRY.set("paxos_leader_active", &l, self.active as u64 as f64);
+        REGISTRY.set("paxos_leader_ballot_round", &l, self.ballot.round as f64);
+
+        let pending = &self.proposals.pending;
+        admin::publish(
+            &self.me,
+            admin::Status::Leader {
+                ballot: self.ballot.clone(),
+                active: self.active,
+                pending: pending.len(),
+                oldest: pending.iter().take(16).copied().collect(),
+            },
+        );
     }
 }
 
 impl Executor for Leader {
     fn exec<R: Receiver, T: Router, E: Env<T>>(mut self, reciever: R, env: &'static E) {
-        self.scout(self.ballot.clone(), env);
+        if self.config.mencius && self.deal(env) {
+            // above the ballot every acceptor starts with, and the same for
+            // every leader as they never propose in the same slot
+            self.ballot = BallotNumber::new(1, self.rotation[0].clone());
+            self.shared = Some(self.ballot.clone());
+            self.active = true;
+        } else {
+            if self.config.mencius {
+                warn!("{} is not one of the Mencius leaders", self.me);
+            }
+            self.scout(self.ballot.clone(), env);
+        }
         loop {
-            let msg = reciever.get(SLEEP_TIME);
+            self.tick(env);
+            let msg = match reciever.get_timeout(SLEEP_TIME, self.wait()) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
 
             match msg {
                 Message::Propose(_, slot, command) => {
+                    trace::record(Stage::Propose, &self.me, &command, Some(slot));
                     if !self.proposals.has(&slot) {
                         self.proposals.insert(slot, command.clone());
-                        if self.active {
+                        if self.rotating() && !self.owns(slot) {
+                            // kept too, in case its owner has to be taken over
+                            let m = Message::Propose(self.me.clone(), slot, command);
+                            env.router().send(self.owner(slot), m);
+                        } else if self.active && self.fast.map_or(false, |from| slot >= from) {
+                            // the replica gave up on the acceptors, try once more
+                            // for it, a commander cannot use a fast ballot
+                            let m = Message::FastRequest(self.me.clone(), slot, command);
+                            for a in env.cluster().acceptors().iter() {
+                                env.router().send(a, m.clone());
+                            }
+                        } else if self.active {
                             self.commander(self.ballot.clone(), slot, command, env);
+                        } else if let Some(l) = self.leader() {
+                            // kept as well, re-proposed if this leader takes over
+                            env.router()
+                                .send(l, Message::Propose(self.me.clone(), slot, command));
+                        } else if self.passive_until.map_or(false, |t| Instant::now() >= t) {
+                            // done stepping down, try to lead again
+                            self.passive_until = None;
+                            self.ballot = BallotNumber::new(self.ballot.round + 1, self.me.clone());
+                            self.scout(self.ballot.clone(), env);
                         }
                     }
                 }
                 Message::Adopt(_, ballot, values) => {
-                    if self.ballot == ballot {
-                        let mut max: HashMap<SlotNumber, BallotNumber> = HashMap::new();
-                        for (s, pv) in values.iter() {
-                            let bn = max.get(s);
+                    if self.ballot == ballot && self.passive_until.is_none() {
+                        let max = pmax([&values]);
+                        let highest = max.keys().max().copied().unwrap_or(0);
+                        let read: HashSet<SlotNumber> = max.keys().copied().collect();
+                        self.proposals
+                            .adopt(max.into_iter().map(|(s, pv)| (s, pv.command)).collect());
 
-                            if bn.map_or(true, |p| p < &self.ballot) {
-                                max.insert(pv.slot, pv.ballot.clone());
-                                self.proposals.insert(pv.slot, pv.command.clone());
+                        if self.vertical() {
+                            // the new configuration gets what was read from the
+                            // old one before anything else is proposed
+                            for s in read.iter() {
+                                let c = self.proposals.m[s].command.clone();
+                                self.commander(self.ballot.clone(), *s, c, env);
                             }
+                            self.completing = Some((highest, read));
+                            self.complete(env);
+                            self.report();
+                            continue;
                         }
-
-                        for (sn, c) in self.proposals.pending() {
-                            self.commander(self.ballot.clone(), *sn, (*c).command.clone(), env);
+                        self.activate(highest, env);
+                        // witnesses take no part in fast rounds
+                        let acceptors = env.cluster().acceptors();
+                        let sized = self.config.quorum.fast(&acceptors).is_some();
+                        if self.config.fast && sized && env.cluster().witnesses().is_empty() {
+                            let last = self.proposals.m.keys().max().copied();
+                            self.open(last.unwrap_or(self.decided).max(self.decided) + 1, env);
                         }
-                        self.active = true;
                     }
                 }
                 Message::Preempt(_, ballot) => {
                     if self.ballot < ballot {
                         self.ballot = BallotNumber::new(ballot.round + 1, self.me.clone());
-                        self.scout(ballot, env);
+                        if self.config.stable {
+                            // let the leader that preempted us be until it goes quiet
+                            self.follow(ballot, env);
+                        } else if self.passive_until.is_none() {
+                            self.scout(ballot, env);
+                        }
+                        self.active = false;
+                    }
+                }
+                Message::Heartbeat(_, ballot) => {
+                    if self.config.stable && (!self.active || self.ballot < ballot) {
+                        if self.ballot < ballot {
+                            self.ballot = BallotNumber::new(ballot.round + 1, self.me.clone());
+                        }
                         self.active = false;
+                        self.follow(ballot, env);
+                    }
+                }
+                Message::Decision(_, slot, command, ballot) => {
+                    // only written to the new configuration when decided with
+                    // this ballot
+                    if ballot.as_ref() == Some(&self.ballot) {
+                        if let Some((_, read)) = self.completing.as_mut() {
+                            read.remove(&slot);
+                        }
+                        self.complete(env);
+                        self.witness(slot, &command, env);
+                    }
+                    self.proposals.done(&slot, command);
+                    self.highest = self.highest.max(slot);
+                    while self.proposals.is_done(&(self.decided + 1)) {
+                        self.decided += 1;
+                    }
+                    // the command of a slot still to be written again is needed
+                    let witnessed = &self.witnessed;
+                    let completing = &self.completing;
+                    let kept = |s: &SlotNumber| {
+                        witnessed.contains_key(s)
+                            || completing.as_ref().map_or(false, |(_, r)| r.contains(s))
+                    };
+                    self.proposals.prune(self.decided, kept);
+                    if !kept(&slot) {
+                        self.proposals.forget(&slot);
+                    }
+                    if self.rotating() {
+                        self.skip(slot, env);
+                    }
+                }
+                Message::Failed(_, ballot, slot) => {
+                    if self.ballot == ballot {
+                        match slot {
+                            // the acceptors did not answer the commander in time,
+                            // try the slot again while we are still active
+                            Some(s) => match self.proposals.pending_command(&s) {
+                                Some(c) if self.active => {
+                                    self.commander(self.ballot.clone(), s, c, env)
+                                }
+                                _ if self
+                                    .completing
+                                    .as_ref()
+                                    .map_or(false, |(_, r)| r.contains(&s)) =>
+                                {
+                                    let c = self.proposals.m[&s].command.clone();
+                                    self.commander(self.ballot.clone(), s, c, env)
+                                }
+                                _ => {}
+                            },
+                            None => {
+                                let idle = self.passive_until.is_none() && self.leader().is_none();
+                                if !self.active && idle {
+                                    self.scout(self.ballot.clone(), env);
+                                }
+                            }
+                        }
+                    }
+                }
+                Message::FastAccepted(acceptor, ballot, slot, command) => {
+                    let open = self.active && self.fast.is_some();
+                    if open && self.ballot == ballot && !self.proposals.is_done(&slot) {
+                        self.vote(acceptor, slot, command, env);
+                    }
+                }
+                Message::Response(_, _, command, result) => {
+                    let asked = self.asked.as_ref().map_or(false, |(c, _, _)| *c == command);
+                    if asked {
+                        self.asked = None;
+                        if let Some(op) = MasterOp::parse(&command.operation) {
+                            self.answered(op, result, env);
+                        }
                     }
                 }
-                Message::Decision(id, slot, command) => {
-                    self.proposals.done(&slot);
+                Message::StepDown(_) => {
+                    self.active = false;
+                    self.passive_until = Some(Instant::now() + Duration::from_millis(STEP_DOWN_MS));
                 }
                 _ => panic!("unexpected"),
             }
+            self.report();
         }
     }
 }
 
-struct Scout {
+/// Retransmission schedule of a scout or a commander, exponential between
+/// retries and bounded by an overall deadline.
+struct Backoff {
+    interval: Duration,
+    max: Duration,
+    next: Instant,
+    deadline: Instant,
+}
+
+impl Backoff {
+    fn new(config: &LeaderConfig) -> Backoff {
+        let now = Instant::now();
+        Backoff {
+            interval: config.retransmit,
+            max: config.retransmit_max,
+            next: now + config.retransmit,
+            deadline: now + config.deadline,
+        }
+    }
+
+    /// how long to wait for a message before doing anything else
+    fn wait(&self) -> Duration {
+        self.next
+            .min(self.deadline)
+            .saturating_duration_since(Instant::now())
+    }
+
+    fn expired(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// true when it is time to retransmit, and schedules the next one
+    fn due(&mut self) -> bool {
+        let now = Instant::now();
+        if now < self.next {
+            return false;
+        }
+        self.interval = (self.interval * 2).min(self.max);
+        self.next = now + self.interval;
+        true
+    }
+}
+
+/// Every process that votes in phase 1 and 2, the witnesses after the
+/// acceptors, and the ones to ask first: the acceptors alone, unless one of
+/// them is suspected to be down and the witnesses have to stand in for it.
+/// In Vertical Paxos, the acceptors of the configuration, `group`, alone.
+fn voters<T: Router, E: Env<T>>(
+    env: &E,
+    seen: &LastSeen,
+    group: &Option<Vec<ProcessId>>,
+) -> (Vec<ProcessId>, Vec<ProcessId>) {
+    if let Some(g) = group {
+        return (g.clone(), g.clone());
+    }
+    let acceptors = env.cluster().acceptors();
+    let mut all = acceptors.clone();
+    all.extend(env.cluster().witnesses());
+    match seen.any_down(&acceptors) {
+        true => (all.clone(), all),
+        false => (all, acceptors),
+    }
+}
+
+/// Suspects the acceptors asked that did not answer in time.
+fn suspect<T: Router, E: Env<T>>(
+    env: &E,
+    seen: &LastSeen,
+    sent: &HashSet<ProcessId>,
+    acked: &HashSet<ProcessId>,
+) {
+    for a in env.cluster().acceptors().iter() {
+        if sent.contains(a) && !acked.contains(a) {
+            seen.suspect(a);
+        }
+    }
+}
+
+/// What the scouts and commanders of a leader share for one ballot.
+pub(crate) struct Round {
+    pub(crate) leader: ProcessId,
+    pub(crate) ballot: BallotNumber,
+    // the acceptors of the configuration of the ballot, in Vertical Paxos
+    pub(crate) group: Option<Vec<ProcessId>>,
+    pub(crate) seen: Arc<LastSeen>,
+    // whether the slots are those of the replicated log, whose replicas are
+    // told the decisions
+    pub(crate) log: bool,
+}
+
+pub(crate) struct Scout {
     me: ProcessId,
-    leader: ProcessId,
-    ballot: BallotNumber,
+    round: Round,
+    // the first slot the leader does not know to be decided
+    from: SlotNumber,
+    config: LeaderConfig,
 }
 
 impl Scout {
-    fn new(id: ProcessId, leader: ProcessId, ballot: BallotNumber) -> Scout {
+    pub(crate) fn new(
+        me: ProcessId,
+        round: Round,
+        from: SlotNumber,
+        config: LeaderConfig,
+    ) -> Scout {
         Scout {
-            me: id,
-            leader: leader,
-            ballot: ballot,
+            me,
+            round,
+            from,
+            config,
         }
     }
 }
 
 impl Executor for Scout {
     fn exec<R: Receiver, T: Router, E: Env<T>>(self, reciever: R, env: &E) {
-        let msg = Message::P1A(self.me.clone(), self.ballot.clone());
-        let mut wait: HashSet<ProcessId> = HashSet::new();
-        for a in env.cluster().acceptors().iter() {
+        let (acceptors, first) = voters(env, &self.round.seen, &self.round.group);
+
+        let msg = Message::P1A(self.me.clone(), self.round.ballot.clone(), self.from);
+        let mut sent: HashSet<ProcessId> = first.into_iter().collect();
+        for a in sent.iter() {
             env.router().send(a, msg.clone());
-            wait.insert(a.clone());
         }
 
-        let mut values: Accepted = Accepted::new();
-        while 2 * wait.len() >= env.cluster().acceptors().len() {
-            let msg = reciever.get(SLEEP_TIME);
+        let fallback = Instant::now() + self.config.thrifty_timeout;
+        let mut backoff = Backoff::new(&self.config);
+        let mut acked: HashSet<ProcessId> = HashSet::new();
+        let mut responses: HashMap<ProcessId, Accepted> = HashMap::new();
+        // the number of pages of the reply of each acceptor, and the ones in
+        let mut received: HashMap<ProcessId, (u64, HashSet<u64>)> = HashMap::new();
+        while !self.config.quorum.is_phase1(&acceptors, &acked) {
+            let mut wait = backoff.wait();
+            if sent.len() < acceptors.len() {
+                wait = wait.min(fallback.saturating_duration_since(Instant::now()));
+            }
 
-            match msg {
-                Message::P1B(pid, ballot, accepted) => {
-                    if ballot != self.ballot {
-                        env.router()
-                            .send(&self.leader, Message::Preempt(self.me.clone(), ballot));
+            match reciever.get_timeout(SLEEP_TIME, wait) {
+                Err(_) => {
+                    if backoff.expired() {
+                        env.router().send(
+                            &self.round.leader,
+                            Message::Failed(self.me.clone(), self.round.ballot.clone(), None),
+                        );
+                        return;
+                    }
+                    if Instant::now() >= fallback && sent.len() < acceptors.len() {
+                        // an acceptor is slow, the witnesses stand in for it
+                        suspect(env, &self.round.seen, &sent, &acked);
+                        for a in acceptors.iter() {
+                            if sent.insert(a.clone()) {
+                                env.router().send(a, msg.clone());
+                            }
+                        }
+                    }
+                    if backoff.due() {
+                        for a in sent.iter().filter(|a| !acked.contains(a)) {
+                            env.router().send(a, msg.clone());
+                        }
+                    }
+                }
+                Ok(Message::P1B(pid, ballot, accepted, (page, pages))) => {
+                    self.round.seen.seen(&pid);
+                    if ballot != self.round.ballot {
+                        env.router().send(
+                            &self.round.leader,
+                            Message::Preempt(self.me.clone(), ballot),
+                        );
                         return;
                     }
-                    if wait.contains(&pid) {
-                        wait.remove(&pid);
+                    // an acceptor counts once every page of a reply is in, the
+                    // missing ones come again with the P1A retransmissions,
+                    // and pages resent merge harmlessly
+                    if acceptors.contains(&pid) && !acked.contains(&pid) {
+                        let values = responses.entry(pid.clone()).or_insert(Accepted::new());
                         values.extend(accepted);
+                        let got = received
+                            .entry(pid.clone())
+                            .or_insert((pages, HashSet::new()));
+                        if got.0 != pages {
+                            *got = (pages, HashSet::new());
+                        }
+                        if page < pages {
+                            got.1.insert(page);
+                        }
+                        if got.1.len() as u64 == pages {
+                            acked.insert(pid);
+                        }
                     }
                 }
-                _ => panic!("not expected"),
+                Ok(_) => panic!("not expected"),
             }
         }
 
         env.router().send(
-            &self.leader,
-            Message::Adopt(self.me.clone(), self.ballot.clone(), values.clone()),
+            &self.round.leader,
+            Message::Adopt(
+                self.me.clone(),
+                self.round.ballot.clone(),
+                pick(responses.values()),
+            ),
         )
     }
 }
 
-struct Commander {
+pub(crate) struct Commander {
     me: ProcessId,
-    leader: ProcessId,
-    ballot: BallotNumber,
+    round: Round,
     slot: SlotNumber,
     command: Command,
+    config: LeaderConfig,
 }
 
 impl Commander {
-    fn new(
-        id: &ProcessId,
-        leader: &ProcessId,
-        ballot: BallotNumber,
+    pub(crate) fn new(
+        me: ProcessId,
+        round: Round,
         slot: SlotNumber,
         command: Command,
+        config: LeaderConfig,
     ) -> Commander {
         Commander {
-            me: id.clone(),
-            leader: leader.clone(),
-            ballot: ballot,
-            slot: slot,
-            command: command,
+            me,
+            round,
+            slot,
+            command,
+            config,
         }
     }
 }
 
 impl Executor for Commander {
     fn exec<R: Receiver, T: Router, E: Env<T>>(self, reciever: R, env: &E) {
+        let start = Instant::now();
         let msg = Message::P2A(
             self.me.clone(),
-            self.ballot.clone(),
+            self.round.ballot.clone(),
             self.slot,
             self.command.clone(),
         );
-        let mut wait: HashSet<ProcessId> = HashSet::new();
-        for a in env.cluster().acceptors().iter() {
+        let (acceptors, first) = voters(env, &self.round.seen, &self.round.group);
+        let quorum = &self.config.quorum;
+        let mut sent: HashSet<ProcessId> = if self.config.thrifty {
+            quorum.pick_phase2(&acceptors, &self.round.seen.order(&first))
+        } else {
+            first.into_iter().collect()
+        };
+        for a in sent.iter() {
             env.router().send(a, msg.clone());
-            wait.insert(a.clone());
         }
 
-        while 2 * wait.len() >= env.cluster().acceptors().len() {
-            let msg = reciever.get(SLEEP_TIME);
-            match msg {
-                Message::P2B(pid, ballot, slot) => {
-                    if self.ballot == ballot {
-                        if wait.contains(&pid) {
-                            wait.remove(&pid);
+        let fallback = Instant::now() + self.config.thrifty_timeout;
+        let mut backoff = Backoff::new(&self.config);
+        let mut acked: HashSet<ProcessId> = HashSet::new();
+        while !quorum.is_phase2(&acceptors, &acked) {
+            let mut wait = backoff.wait();
+            if sent.len() < acceptors.len() {
+                wait = wait.min(fallback.saturating_duration_since(Instant::now()));
+            }
+
+            match reciever.get_timeout(SLEEP_TIME, wait) {
+                Err(_) => {
+                    if backoff.expired() {
+                        env.router().send(
+                            &self.round.leader,
+                            Message::Failed(
+                                self.me.clone(),
+                                self.round.ballot.clone(),
+                                Some(self.slot),
+                            ),
+                        );
+                        return;
+                    }
+                    if Instant::now() >= fallback {
+                        // the chosen quorum is too slow, fall back to everyone
+                        // else, witnesses included
+                        suspect(env, &self.round.seen, &sent, &acked);
+                        for a in acceptors.iter() {
+                            if sent.insert(a.clone()) {
+                                env.router().send(a, msg.clone());
+                            }
+                        }
+                    }
+                    if backoff.due() {
+                        for a in sent.iter().filter(|a| !acked.contains(a)) {
+                            env.router().send(a, msg.clone());
+                        }
+                    }
+                }
+                Ok(Message::P2B(pid, ballot, slot)) => {
+                    self.round.seen.seen(&pid);
+                    if self.round.ballot == ballot {
+                        if acceptors.contains(&pid) && acked.insert(pid) {
+                            trace::record(Stage::P2B, &self.me, &self.command, Some(self.slot));
                         }
                     } else {
-                        env.router()
-                            .send(&self.leader, Message::Preempt(self.me.clone(), ballot));
+                        env.router().send(
+                            &self.round.leader,
+                            Message::Preempt(self.me.clone(), ballot),
+                        );
                         return;
                     }
                 }
-                _ => panic!("not expected"),
+                Ok(_) => panic!("not expected"),
             }
         }
 
-        let decision = Message::Decision(self.me.clone(), self.slot, self.command.clone());
+        REGISTRY.observe(
+            "paxos_commit_latency_seconds",
+            &[],
+            start.elapsed().as_secs_f64(),
+        );
+        trace::record(Stage::Decision, &self.me, &self.command, Some(self.slot));
+        let decision = Message::Decision(
+            self.me.clone(),
+            self.slot,
+            self.command.clone(),
+            Some(self.round.ballot.clone()),
+        );
+        if !self.round.log {
+            env.router().send(&self.round.leader, decision);
+            return;
+        }
         for r in env.cluster().replicas().iter() {
             env.router().send(r, decision.clone());
         }
 
-        // send it to colocated leader
-        env.router().send(&self.leader, decision);
+        if self.config.mencius {
+            // every leader skips its own slots below this one
+            for l in env.cluster().leaders().iter() {
+                env.router().send(l, decision.clone());
+            }
+        } else {
+            // send it to colocated leader
+            env.router().send(&self.round.leader, decision);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crossbeam::channel;
+
+    use crate::{
+        acceptor::Acceptor,
+        ds::{Accepted, LastSeen},
+        env::{Env, ProcessId, ProcessType, Router},
+        fixture::{self, Flaky},
+        message::Message,
+        pval::{BallotNumber, Command, PValue},
+        quorum::QuorumSystem,
+        replica::Replica,
+        vertical::ConfigMaster,
+        witness::Witness,
+    };
+
+    use super::{owner, Commander, Leader, LeaderConfig, Proposals, Round, Scout};
+
+    fn next_heartbeat(r: &channel::Receiver<Message>) -> BallotNumber {
+        loop {
+            match r.recv_timeout(Duration::from_secs(5)).unwrap() {
+                Message::Heartbeat(_, ballot) => return ballot,
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn stable_failover() {
+        let env = fixture::env();
+        let config = LeaderConfig {
+            stable: true,
+            heartbeat: Duration::from_millis(20),
+            leader_timeout: Duration::from_millis(200),
+            ..LeaderConfig::default()
+        };
+
+        fixture::acceptors(env, 3);
+        let (_, h) = fixture::probe(env, ProcessType::Leader);
+        let (replica, d) = fixture::probe(env, ProcessType::Replica);
+        let leaders = [fixture::new_id(env), fixture::new_id(env)];
+        for id in leaders.iter() {
+            let leader = Leader::with_config(id.clone(), config.clone());
+            env.register(id.clone(), ProcessType::Leader, leader);
+        }
+
+        // the active leader steps down and stops sending heartbeats, the
+        // other one notices and takes over
+        let ballot = next_heartbeat(&h);
+        let active = ballot.process_id.clone();
+        env.router()
+            .send(&active, Message::StepDown(replica.clone()));
+        let other = leaders.iter().find(|l| **l != active).unwrap();
+        // heartbeats sent before the step down may still be queued
+        loop {
+            let b = next_heartbeat(&h);
+            if b.process_id == *other && b > ballot {
+                break;
+            }
+        }
+
+        // the leader that stepped down forwards to the new one
+        let c = Command::new_from_str(replica.clone(), format!("1"), format!("PUT a 1"));
+        env.router()
+            .send(&active, Message::Propose(replica.clone(), 1, c.clone()));
+        assert_eq!(fixture::wait_decision(&d, 1).0, c);
+    }
+
+    #[test]
+    fn fast_round_collision() {
+        let env = fixture::env();
+        // phase 1 hears from every acceptor, so recovery sees all the votes
+        let config = LeaderConfig {
+            fast: true,
+            fast_timeout: Duration::from_secs(5),
+            quorum: QuorumSystem::Flexible {
+                phase1: 4,
+                phase2: 1,
+            },
+            ..LeaderConfig::default()
+        };
+
+        let acceptors = fixture::acceptors(env, 4);
+        let (replica, d) = fixture::probe(env, ProcessType::Replica);
+        let id = fixture::new_id(env);
+        env.register(
+            id.clone(),
+            ProcessType::Leader,
+            Leader::with_config(id, config),
+        );
+
+        let command =
+            |op: &str| Command::new_from_str(replica.clone(), op.to_string(), op.to_string());
+        let fast = |to: &[ProcessId], slot, c: &Command| {
+            for a in to.iter() {
+                env.router()
+                    .send(a, Message::FastRequest(replica.clone(), slot, c.clone()));
+            }
+        };
+
+        // straight to the acceptors, once the ballot is fast
+        let c = command("PUT a 1");
+        let deadline = Instant::now() + fixture::WAIT;
+        'fast: while Instant::now() < deadline {
+            fast(&acceptors, 1, &c);
+            while let Ok(m) = d.recv_timeout(Duration::from_millis(200)) {
+                if let Message::Decision(_, 1, decided, _) = m {
+                    assert_eq!(decided, c);
+                    break 'fast;
+                }
+            }
+        }
+
+        // three commands collide in slot 2, none of them can get a fast
+        // quorum of three and recovery settles on the one most voted
+        let (c1, c2, c3) = (command("PUT a 2"), command("PUT a 3"), command("PUT a 4"));
+        fast(&acceptors[..2], 2, &c1);
+        fast(&acceptors[2..3], 2, &c2);
+        fast(&acceptors[3..], 2, &c3);
+        assert_eq!(fixture::wait_decision(&d, 2).0, c1);
+    }
+
+    #[test]
+    fn witness_stands_in() {
+        let env = fixture::env();
+        let config = LeaderConfig {
+            leader_timeout: Duration::from_millis(200),
+            ..LeaderConfig::default()
+        };
+
+        // one of the two acceptors is down, a phase 1 or 2 quorum is two
+        // votes out of three with the witness
+        fixture::acceptors(env, 1);
+        let down = Arc::new(AtomicBool::new(true));
+        let id = fixture::new_id(env);
+        let flaky = Flaky(Acceptor::new(id.clone()), down.clone());
+        env.register(id, ProcessType::Acceptor, flaky);
+        let witness = fixture::new_id(env);
+        env.register(
+            witness.clone(),
+            ProcessType::Witness,
+            Witness::new(witness.clone()),
+        );
+        let (replica, d) = fixture::probe(env, ProcessType::Replica);
+        let id = fixture::new_id(env);
+        env.register(
+            id.clone(),
+            ProcessType::Leader,
+            Leader::with_config(id.clone(), config),
+        );
+
+        let c = Command::new_from_str(replica.clone(), format!("1"), format!("PUT a 1"));
+        env.router()
+            .send(&id, Message::Propose(replica.clone(), 1, c.clone()));
+        assert_eq!(fixture::wait_decision(&d, 1).0, c);
+
+        // once the acceptor is back and has the value too, the witness
+        // drops it
+        let (scout, p) = fixture::probe(env, ProcessType::Scout);
+        let held = || {
+            let ballot = BallotNumber::first(scout.clone());
+            env.router()
+                .send(&witness, Message::P1A(scout.clone(), ballot, 1));
+            match p.recv_timeout(fixture::WAIT).unwrap() {
+                Message::P1B(_, _, accepted, _) => accepted.len(),
+                m => panic!("unexpected {}", m),
+            }
+        };
+        assert_eq!(held(), 1);
+        down.store(false, Ordering::SeqCst);
+        let deadline = Instant::now() + fixture::WAIT;
+        while held() > 0 {
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn mencius_skips() {
+        let env = fixture::env();
+        let leaders = vec![fixture::new_id(env), fixture::new_id(env)];
+        let config = LeaderConfig {
+            mencius: true,
+            leaders: leaders.clone(),
+            ..LeaderConfig::default()
+        };
+
+        fixture::acceptors(env, 3);
+        let (replica, d) = fixture::probe(env, ProcessType::Replica);
+        // the first one starts before the other is registered
+        for id in leaders {
+            let leader = Leader::with_config(id.clone(), config.clone());
+            env.register(id.clone(), ProcessType::Leader, leader);
+        }
+
+        // only slot 2 gets a proposal, the owner of slot 1 fills it with a
+        // no-op once slot 2 is decided
+        let c = Command::new_from_str(replica.clone(), format!("1"), format!("PUT a 1"));
+        let to = owner(env.cluster().leaders(), 2).unwrap();
+        assert_ne!(Some(&to), owner(env.cluster().leaders(), 1).as_ref());
+        env.router()
+            .send(&to, Message::Propose(replica.clone(), 2, c.clone()));
+
+        assert_eq!(fixture::wait_decision(&d, 2).0, c);
+        assert!(fixture::wait_decision(&d, 1).0.is_noop());
+    }
+
+    #[test]
+    fn vertical_reconfigures() {
+        let env = fixture::env();
+        let master: &'static _ = Box::leak(Box::new(env.sibling()));
+
+        // the master is a cluster of its own
+        fixture::acceptors(master, 3);
+        let id = fixture::new_id(master);
+        master.register(id.clone(), ProcessType::Leader, Leader::new(id));
+        let masters = vec![fixture::new_id(master)];
+        let state = Box::new(ConfigMaster::new());
+        let replica = Replica::with_state(masters[0].clone(), state);
+        master.register(masters[0].clone(), ProcessType::Replica, replica);
+
+        let old = fixture::acceptors(env, 3);
+        let (replica, d) = fixture::probe(env, ProcessType::Replica);
+        let leader = fixture::new_id(env);
+        let config = LeaderConfig {
+            masters,
+            ..LeaderConfig::default()
+        };
+        let l = Leader::with_config(leader.clone(), config);
+        env.register(leader.clone(), ProcessType::Leader, l);
+
+        let command =
+            |op: &str| Command::new_from_str(replica.clone(), op.to_string(), op.to_string());
+        let c = command("PUT a 1");
+        env.router()
+            .send(&leader, Message::Propose(replica.clone(), 1, c.clone()));
+        let (decided, ballot) = fixture::wait_decision(&d, 1);
+        let ballot = ballot.unwrap();
+        assert_eq!((decided, ballot.round), (c, 1));
+
+        // a value accepted by a majority of the old acceptors only, as if the
+        // decision got lost, then two more acceptors join and the leader
+        // moves to a new round
+        let lost = command("PUT a 2");
+        for a in old[..2].iter() {
+            let m = Message::P2A(replica.clone(), ballot.clone(), 2, lost.clone());
+            env.router().send(a, m);
+        }
+        fixture::acceptors(env, 2);
+        thread::sleep(Duration::from_millis(100));
+        let higher = BallotNumber::new(ballot.round, fixture::new_id(env));
+        env.router()
+            .send(&leader, Message::Preempt(replica.clone(), higher));
+
+        // phase 1 of round 2 reads it from the old acceptors and phase 2
+        // writes it to all five
+        let (decided, ballot) = fixture::wait_decision(&d, 2);
+        assert_eq!((decided, ballot.unwrap().round), (lost, 2));
+    }
+
+    #[test]
+    fn proposals_forget_decided() {
+        let env = fixture::env();
+        let c = |i: u64| {
+            Command::new_from_str(
+                fixture::new_id(env),
+                format!("{}", i),
+                format!("PUT k {}", i),
+            )
+        };
+        let mut p = Proposals::new();
+        for s in 1..=4 {
+            p.insert(s, c(s));
+        }
+        p.done(&1, c(1));
+        p.done(&2, c(2));
+        p.done(&4, c(4));
+        assert_eq!(p.pending().map(|(s, _)| *s).collect::<Vec<_>>(), vec![3]);
+
+        // slot 2 is still needed, e.g. to be written to the acceptors again
+        p.prune(2, |s| *s == 2);
+        assert!(!p.m.contains_key(&1) && p.m.contains_key(&2));
+        assert!(p.has(&1) && p.is_done(&1));
+        p.forget(&2);
+        assert!(!p.m.contains_key(&2) && p.is_done(&2));
+        // a pending slot is not forgotten
+        p.forget(&3);
+        assert!(p.pending_command(&3).is_some());
+    }
+
+    #[test]
+    fn adopt_fills_holes() {
+        let env = fixture::env();
+        let acceptors = fixture::acceptors(env, 3);
+        let (replica, d) = fixture::probe(env, ProcessType::Replica);
+
+        // a leader got slot 3 accepted and died before slots 1 and 2
+        let (old, _) = fixture::probe(env, ProcessType::Client);
+        let c = Command::new_from_str(replica.clone(), format!("1"), format!("PUT a 1"));
+        let m = Message::P2A(old.clone(), BallotNumber::new(1, old), 3, c.clone());
+        for a in acceptors.iter() {
+            env.router().send(a, m.clone());
+        }
+
+        let id = fixture::new_id(env);
+        env.register(id.clone(), ProcessType::Leader, Leader::new(id));
+        // decisions may come in any order
+        let mut decided = HashMap::new();
+        while decided.len() < 3 {
+            match d.recv_timeout(fixture::WAIT).unwrap() {
+                Message::Decision(_, s, c, _) => decided.insert(s, c),
+                _ => None,
+            };
+        }
+        assert!(decided[&1].is_noop() && decided[&2].is_noop());
+        assert!(decided[&3] == c && !c.is_noop());
+    }
+
+    #[test]
+    fn thrifty_falls_back() {
+        let env = fixture::env();
+        let config = LeaderConfig {
+            thrifty: true,
+            thrifty_timeout: Duration::from_millis(200),
+            ..LeaderConfig::default()
+        };
+        let probes: Vec<(ProcessId, channel::Receiver<Message>)> = (0..3)
+            .map(|_| fixture::probe(env, ProcessType::Acceptor))
+            .collect();
+        let (leader, l) = fixture::probe(env, ProcessType::Leader);
+        let p2a = |r: &channel::Receiver<Message>, wait| match r.recv_timeout(wait) {
+            Ok(Message::P2A(cid, ballot, _, _)) => Some((cid, ballot)),
+            _ => None,
+        };
+
+        // none heard from yet, the first two acceptors make a majority
+        let c = Command::new_from_str(leader.clone(), format!("1"), format!("PUT a 1"));
+        let ballot = BallotNumber::new(1, leader.clone());
+        let cid = fixture::new_id(env);
+        let round = round(&leader, &ballot);
+        let commander = Commander::new(cid.clone(), round, 1, c.clone(), config);
+        env.register(cid.clone(), ProcessType::Commander, commander);
+        let quick = Duration::from_millis(100);
+        assert!(p2a(&probes[0].1, fixture::WAIT).is_some());
+        assert!(p2a(&probes[1].1, fixture::WAIT).is_some());
+        assert!(p2a(&probes[2].1, quick).is_none());
+
+        // one of them does not answer, the third one is asked
+        let p2b = |a: &ProcessId| Message::P2B(a.clone(), ballot.clone(), 1);
+        env.router().send(&cid, p2b(&probes[0].0));
+        assert!(p2a(&probes[2].1, fixture::WAIT).is_some());
+        env.router().send(&cid, p2b(&probes[2].0));
+        assert_eq!(fixture::wait_decision(&l, 1), (c, Some(ballot)));
+    }
+
+    fn round(leader: &ProcessId, ballot: &BallotNumber) -> Round {
+        Round {
+            leader: leader.clone(),
+            ballot: ballot.clone(),
+            group: None,
+            seen: Arc::new(LastSeen::new()),
+            log: true,
+        }
+    }
+
+    fn quick_retries() -> LeaderConfig {
+        LeaderConfig {
+            retransmit: Duration::from_millis(100),
+            retransmit_max: Duration::from_millis(400),
+            deadline: Duration::from_millis(1000),
+            ..LeaderConfig::default()
+        }
+    }
+
+    #[test]
+    fn lost_requests_are_resent() {
+        let env = fixture::env();
+        let probes: Vec<(ProcessId, channel::Receiver<Message>)> = (0..3)
+            .map(|_| fixture::probe(env, ProcessType::Acceptor))
+            .collect();
+        let (leader, l) = fixture::probe(env, ProcessType::Leader);
+        let ballot = BallotNumber::new(1, leader.clone());
+
+        // the first P1A to an acceptor is lost, it comes again later and
+        // later, and the round completes once it is answered
+        let sid = fixture::new_id(env);
+        let scout = Scout::new(sid.clone(), round(&leader, &ballot), 1, quick_retries());
+        env.register(sid.clone(), ProcessType::Scout, scout);
+        let p1a = |r: &channel::Receiver<Message>| match r.recv_timeout(fixture::WAIT) {
+            Ok(Message::P1A(src, b, 1)) => assert!(src == sid && b == ballot),
+            m => panic!("unexpected {:?}", m),
+        };
+        let p1b = |a: &ProcessId| Message::P1B(a.clone(), ballot.clone(), Accepted::new(), (0, 1));
+        p1a(&probes[1].1);
+        env.router().send(&sid, p1b(&probes[1].0));
+        p1a(&probes[0].1);
+        let first = Instant::now();
+        p1a(&probes[0].1);
+        let second = Instant::now();
+        p1a(&probes[0].1);
+        // the interval doubles between retries
+        assert!(second.elapsed() > (second - first) * 3 / 2);
+        assert!(l.try_recv().is_err());
+        env.router().send(&sid, p1b(&probes[0].0));
+        match l.recv_timeout(fixture::WAIT) {
+            Ok(Message::Adopt(_, b, _)) => assert_eq!(b, ballot),
+            m => panic!("unexpected {:?}", m),
+        }
+
+        // the same for the first P2A
+        let c = Command::new_from_str(leader.clone(), format!("1"), format!("PUT a 1"));
+        let cid = fixture::new_id(env);
+        let round = round(&leader, &ballot);
+        let commander = Commander::new(cid.clone(), round, 1, c.clone(), quick_retries());
+        env.register(cid.clone(), ProcessType::Commander, commander);
+        let p2a = |r: &channel::Receiver<Message>| match r.recv_timeout(fixture::WAIT) {
+            Ok(Message::P2A(src, _, 1, _)) => assert!(src == cid),
+            m => panic!("unexpected {:?}", m),
+        };
+        p2a(&probes[1].1);
+        env.router()
+            .send(&cid, Message::P2B(probes[1].0.clone(), ballot.clone(), 1));
+        p2a(&probes[0].1);
+        p2a(&probes[0].1);
+        env.router()
+            .send(&cid, Message::P2B(probes[0].0.clone(), ballot.clone(), 1));
+        assert_eq!(fixture::wait_decision(&l, 1), (c, Some(ballot)));
+    }
+
+    #[test]
+    fn silent_acceptors_fail() {
+        let env = fixture::env();
+        let probes: Vec<(ProcessId, channel::Receiver<Message>)> = (0..3)
+            .map(|_| fixture::probe(env, ProcessType::Acceptor))
+            .collect();
+        let (leader, l) = fixture::probe(env, ProcessType::Leader);
+        let ballot = BallotNumber::new(1, leader.clone());
+
+        // a scout and a commander give up once the deadline has passed
+        let sid = fixture::new_id(env);
+        let scout = Scout::new(sid.clone(), round(&leader, &ballot), 1, quick_retries());
+        let start = Instant::now();
+        env.register(sid.clone(), ProcessType::Scout, scout);
+        match l.recv_timeout(fixture::WAIT) {
+            Ok(Message::Failed(src, b, None)) => assert!(src == sid && b == ballot),
+            m => panic!("unexpected {:?}", m),
+        }
+        assert!(start.elapsed() >= quick_retries().deadline);
+
+        let c = Command::new_from_str(leader.clone(), format!("1"), format!("PUT a 1"));
+        let cid = fixture::new_id(env);
+        let round = round(&leader, &ballot);
+        let commander = Commander::new(cid.clone(), round, 7, c, quick_retries());
+        env.register(cid.clone(), ProcessType::Commander, commander);
+        match l.recv_timeout(fixture::WAIT) {
+            Ok(Message::Failed(src, b, Some(7))) => assert!(src == cid && b == ballot),
+            m => panic!("unexpected {:?}", m),
+        }
+
+        // a leader told so scouts again, with a new scout for the same ballot
+        probes[2].1.try_iter().count();
+        let id = fixture::new_id(env);
+        env.register(
+            id.clone(),
+            ProcessType::Leader,
+            Leader::with_config(id, quick_retries()),
+        );
+        let mut scouts: Vec<(ProcessId, BallotNumber)> = vec![];
+        let deadline = Instant::now() + fixture::WAIT;
+        while scouts.len() < 2 {
+            assert!(Instant::now() < deadline);
+            if let Ok(Message::P1A(src, b, _)) = probes[2].1.recv_timeout(fixture::WAIT) {
+                if scouts.last().map_or(true, |(s, _)| *s != src) {
+                    scouts.push((src, b));
+                }
+            }
+        }
+        assert_eq!(scouts[0].1, scouts[1].1);
+    }
+
+    #[test]
+    fn scout_waits_for_every_page() {
+        let env = fixture::env();
+        let acceptors: Vec<ProcessId> = (0..3)
+            .map(|_| fixture::probe(env, ProcessType::Acceptor).0)
+            .collect();
+        let (leader, l) = fixture::probe(env, ProcessType::Leader);
+        let ballot = BallotNumber::new(1, leader.clone());
+        let sid = fixture::new_id(env);
+        let round = round(&leader, &ballot);
+        let scout = Scout::new(sid.clone(), round, 1, LeaderConfig::default());
+        env.register(sid.clone(), ProcessType::Scout, scout);
+
+        // a reply in three pages, one value in each
+        let page = |a: &ProcessId, i: u64| {
+            let mut values = Accepted::new();
+            let c = Command::new_from_str(leader.clone(), format!("{}", i), format!("PUT a {}", i));
+            values.insert(i + 1, PValue::new(ballot.clone(), i + 1, c));
+            Message::P1B(a.clone(), ballot.clone(), values, (i, 3))
+        };
+        // the middle page of a majority is lost
+        for a in acceptors[..2].iter() {
+            env.router().send(&sid, page(a, 0));
+            env.router().send(&sid, page(a, 2));
+        }
+        assert!(l.recv_timeout(Duration::from_millis(300)).is_err());
+
+        env.router().send(&sid, page(&acceptors[0], 1));
+        assert!(l.recv_timeout(Duration::from_millis(300)).is_err());
+        env.router().send(&sid, page(&acceptors[1], 1));
+        match l.recv_timeout(fixture::WAIT) {
+            Ok(Message::Adopt(_, b, values)) => {
+                assert_eq!(b, ballot);
+                assert_eq!(values.len(), 3);
+            }
+            m => panic!("unexpected {:?}", m),
+        }
     }
 }
@@ -1,12 +1,31 @@
 pub mod acceptor;
+pub mod admin;
+pub mod auth;
+pub mod bench;
+pub mod client;
+pub mod codec;
+pub mod config;
 mod constants;
-mod ds;
+pub mod ds;
 pub mod env;
+pub mod epaxos;
+#[cfg(test)]
+mod fixture;
+mod http;
 pub mod leader;
 pub mod local;
-mod message;
+pub mod lock;
+pub mod message;
+pub mod metrics;
 mod proto;
-mod pval;
+pub mod pval;
+pub mod quorum;
 pub mod replica;
+pub mod state;
+pub mod synod;
+#[cfg(test)]
 mod test;
-mod zmq;
+pub mod trace;
+pub mod vertical;
+pub mod witness;
+pub mod zmq;
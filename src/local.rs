@@ -13,6 +13,7 @@ use std::{
 use super::{
     env::{Cluster, Env, Executor, GetErr, ProcessId, ProcessType, Receiver, Router, Sender},
     message::Message,
+    metrics::REGISTRY,
 };
 
 impl Sender for channel::Sender<Message> {
@@ -31,7 +32,10 @@ impl Receiver for channel::Receiver<Message> {
     fn try_get(&self) -> Result<Message, GetErr> {
         let g = self.try_recv();
         match g {
-            Ok(m) => return Ok(m),
+            Ok(m) => {
+                REGISTRY.inc("paxos_messages_received_total", &[("type", m.name())]);
+                return Ok(m);
+            }
             Err(_) => Err(GetErr::None),
         }
     }
@@ -73,10 +77,12 @@ impl<S: Sender> RouterMap<S> {
 impl<S: Sender> Router for RouterMap<S> {
     fn send(&self, id: &ProcessId, m: Message) {
         debug!("{} ----> {} ...... message: {}", m.id(), id, m);
+        REGISTRY.inc("paxos_messages_sent_total", &[("type", m.name())]);
         let guard = self.m.lock();
         match guard.unwrap().get_mut(&id) {
             Some(r) => r.send(&m),
-            None => panic!("not possible"),
+            // clients are not registered, their responses go nowhere in memory
+            None => debug!("dropped message for unknown process {}", id),
         }
     }
 }
@@ -104,6 +110,15 @@ impl EnvState {
     pub fn new_id(&self) -> u32 {
         self.id_gen.fetch_add(1, Ordering::SeqCst)
     }
+
+    /// A new cluster drawing its ids from the same generator.
+    fn sibling(&self) -> Self {
+        Self {
+            id_gen: self.id_gen.clone(),
+            cluster: Arc::new(Cluster::new()),
+            join_handles: self.join_handles.clone(),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -158,4 +173,15 @@ impl<R: Receiver, S: Sender> InMemEnv<R, S> {
             state: EnvState::new(),
         }
     }
+
+    /// Another cluster on the same in-memory network: processes of either
+    /// can send to each other, but are not part of each other's cluster.
+    /// Used to run a Vertical Paxos master next to the cluster it serves.
+    pub fn sibling(&self) -> InMemEnv<R, S> {
+        InMemEnv {
+            new_channel_fn: self.new_channel_fn,
+            router: self.router.clone(),
+            state: self.state.sibling(),
+        }
+    }
 }
@@ -0,0 +1,487 @@
+use std::{
+    collections::BTreeMap,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+
+use super::{
+    client::{Client, ClientErr},
+    constants::MAX_CLOCK_SKEW_MS,
+    pval::{Command, SlotNumber},
+    state::{StateMachine, BAD_OP},
+};
+
+/// The answer to an operation sent too far ahead of the clock of the service.
+pub static SKEWED: &[u8] = b"SKEWED";
+
+/// A lock held until `expires`, in milliseconds since the epoch. `token` is
+/// the slot the lock was taken in: every later holder of the lock gets a
+/// higher one, so a resource guarded by the lock can refuse writes carrying a
+/// token lower than the highest it has seen, from a holder whose lease
+/// expired without it noticing.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Lease {
+    pub name: String,
+    pub owner: String,
+    pub token: SlotNumber,
+    pub expires: u64,
+}
+
+/// What `LockService` answers to every operation: the state of the lock
+/// once the operation is applied, written as `FREE` or
+/// `HELD owner token expires`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LockState {
+    Free,
+    Held(Lease),
+}
+
+impl LockState {
+    pub fn encode(&self) -> Bytes {
+        match self {
+            LockState::Free => Bytes::from_static(b"FREE"),
+            LockState::Held(l) => {
+                Bytes::from(format!("HELD {} {} {}", l.owner, l.token, l.expires))
+            }
+        }
+    }
+
+    pub fn parse(name: &str, b: &[u8]) -> Option<LockState> {
+        let s = String::from_utf8_lossy(b);
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        match parts[..] {
+            ["FREE"] => Some(LockState::Free),
+            ["HELD", owner, token, expires] => Some(LockState::Held(Lease {
+                name: name.to_string(),
+                owner: owner.to_string(),
+                token: token.parse().ok()?,
+                expires: expires.parse().ok()?,
+            })),
+            _ => None,
+        }
+    }
+}
+
+/// An operation on `LockService`, written on the wire as
+/// `LOCK name owner ttl`, `RENEW name owner token ttl`,
+/// `UNLOCK name owner token` or `WATCH name`, followed by the time the client
+/// sent it at, in milliseconds since the epoch. Names and owners are single
+/// words, TTLs are in milliseconds.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LockOp {
+    /// Takes the lock for the owner, unless someone else holds it. The owner
+    /// already holding it extends its lease and keeps its token.
+    Lock(String, String, u64),
+    /// Extends the lease taken with the token, if it has not expired.
+    Renew(String, String, SlotNumber, u64),
+    /// Releases the lease taken with the token.
+    Unlock(String, String, SlotNumber),
+    /// Only reads the state of the lock.
+    Watch(String),
+}
+
+impl LockOp {
+    pub fn encode(&self, now: u64) -> Bytes {
+        let s = match self {
+            LockOp::Lock(name, owner, ttl) => format!("LOCK {} {} {}", name, owner, ttl),
+            LockOp::Renew(name, owner, token, ttl) => {
+                format!("RENEW {} {} {} {}", name, owner, token, ttl)
+            }
+            LockOp::Unlock(name, owner, token) => format!("UNLOCK {} {} {}", name, owner, token),
+            LockOp::Watch(name) => format!("WATCH {}", name),
+        };
+        Bytes::from(format!("{} {}", s, now))
+    }
+
+    /// The operation and the time it was sent at.
+    pub fn parse(b: &[u8]) -> Option<(LockOp, u64)> {
+        let s = String::from_utf8_lossy(b);
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let (now, parts) = parts.split_last()?;
+        let s = |i: usize| parts[i].to_string();
+        let op = match parts[..] {
+            ["LOCK", _, _, ttl] => LockOp::Lock(s(1), s(2), ttl.parse().ok()?),
+            ["RENEW", _, _, token, ttl] => {
+                LockOp::Renew(s(1), s(2), token.parse().ok()?, ttl.parse().ok()?)
+            }
+            ["UNLOCK", _, _, token] => LockOp::Unlock(s(1), s(2), token.parse().ok()?),
+            ["WATCH", _] => LockOp::Watch(s(1)),
+            _ => return None,
+        };
+        Some((op, now.parse().ok()?))
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            LockOp::Lock(name, _, _)
+            | LockOp::Renew(name, _, _, _)
+            | LockOp::Unlock(name, _, _)
+            | LockOp::Watch(name) => name,
+        }
+    }
+}
+
+/// A lock service for replicas to run in place of the key-value store.
+///
+/// Leases expire on the clocks of the clients: each operation carries the
+/// time it was sent at and the service's clock is the latest of those, so
+/// every replica expires a lease at the same point of the log. The clocks of
+/// the clients must be roughly in sync: an operation sent more than
+/// `MAX_CLOCK_SKEW_MS` ahead of the service's clock that would make a lease
+/// run out is refused, and only moves the clock forward by that much, so a
+/// client ahead of the others cannot expire leases early at once while a
+/// lease whose holder went quiet still runs out after a few retries.
+pub struct LockService {
+    locks: BTreeMap<String, Lease>,
+    now: u64,
+}
+
+impl LockService {
+    pub fn new() -> LockService {
+        LockService {
+            locks: BTreeMap::new(),
+            now: 0,
+        }
+    }
+
+    fn state(&self, name: &str) -> LockState {
+        match self.locks.get(name) {
+            Some(l) if l.expires > self.now => LockState::Held(l.clone()),
+            _ => LockState::Free,
+        }
+    }
+
+    /// Whether a lease held now would run out by `now`.
+    fn expires_by(&self, now: u64) -> bool {
+        self.locks
+            .values()
+            .any(|l| l.expires > self.now && l.expires <= now)
+    }
+}
+
+impl StateMachine for LockService {
+    fn apply(&mut self, slot: SlotNumber, c: &Command) -> Bytes {
+        let (op, now) = match LockOp::parse(&c.operation) {
+            Some(p) => p,
+            None => return Bytes::from_static(BAD_OP),
+        };
+        let bound = self.now.saturating_add(MAX_CLOCK_SKEW_MS);
+        if now > bound && self.expires_by(now) {
+            self.now = bound;
+            return Bytes::from_static(SKEWED);
+        }
+        self.now = self.now.max(now);
+        let name = op.name().to_string();
+        let held = match self.state(&name) {
+            LockState::Held(l) => Some(l),
+            LockState::Free => None,
+        };
+
+        match op {
+            LockOp::Lock(name, owner, ttl) => match held {
+                Some(l) if l.owner != owner => {}
+                Some(l) => {
+                    let lease = self.locks.get_mut(&name).unwrap();
+                    lease.expires = l.expires.max(self.now.saturating_add(ttl));
+                }
+                None => {
+                    let lease = Lease {
+                        name: name.clone(),
+                        owner,
+                        token: slot,
+                        expires: self.now.saturating_add(ttl),
+                    };
+                    self.locks.insert(name, lease);
+                }
+            },
+            LockOp::Renew(name, owner, token, ttl) => match held {
+                Some(l) if l.owner == owner && l.token == token => {
+                    let lease = self.locks.get_mut(&name).unwrap();
+                    lease.expires = l.expires.max(self.now.saturating_add(ttl));
+                }
+                _ => {}
+            },
+            LockOp::Unlock(name, owner, token) => match held {
+                Some(l) if l.owner == owner && l.token == token => {
+                    self.locks.remove(&name);
+                }
+                _ => {}
+            },
+            LockOp::Watch(_) => {}
+        }
+        self.state(&name).encode()
+    }
+}
+
+#[derive(Debug)]
+pub enum LockErr {
+    /// Someone else holds the lock.
+    Held(Lease),
+    /// The lease has expired or was released.
+    Lost,
+    /// Names and owners are single words.
+    BadName(String),
+    Client(ClientErr),
+    BadReply,
+}
+
+/// Names and owners go on the wire separated by spaces.
+fn word(s: &str) -> Result<(), LockErr> {
+    match s.is_empty() || s.contains(char::is_whitespace) {
+        true => Err(LockErr::BadName(s.to_string())),
+        false => Ok(()),
+    }
+}
+
+/// Takes and releases locks of a cluster whose replicas run `LockService`,
+/// as `owner`.
+pub struct LockClient {
+    client: Client,
+    owner: String,
+}
+
+impl LockClient {
+    pub fn new(client: Client, owner: String) -> LockClient {
+        LockClient { client, owner }
+    }
+
+    fn call(&mut self, op: LockOp) -> Result<LockState, LockErr> {
+        let name = op.name().to_string();
+        loop {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            let reply = self.client.call(op.encode(now)).map_err(LockErr::Client)?;
+            // each refusal moves the clock of the service closer to ours
+            if reply.result != SKEWED {
+                return LockState::parse(&name, &reply.result).ok_or(LockErr::BadReply);
+            }
+        }
+    }
+
+    /// The lease the operation left us with.
+    fn held(&self, state: LockState) -> Result<Lease, LockErr> {
+        match state {
+            LockState::Held(l) if l.owner == self.owner => Ok(l),
+            LockState::Held(l) => Err(LockErr::Held(l)),
+            LockState::Free => Err(LockErr::Lost),
+        }
+    }
+
+    /// Takes `name` for `ttl`, or extends the lease if we already hold it.
+    pub fn lock(&mut self, name: &str, ttl: Duration) -> Result<Lease, LockErr> {
+        word(name)?;
+        word(&self.owner)?;
+        let ttl = ttl.as_millis() as u64;
+        let state = self.call(LockOp::Lock(name.to_string(), self.owner.clone(), ttl))?;
+        self.held(state)
+    }
+
+    /// Extends `lease` to `ttl` from now.
+    pub fn renew(&mut self, lease: &Lease, ttl: Duration) -> Result<Lease, LockErr> {
+        let ttl = ttl.as_millis() as u64;
+        let op = LockOp::Renew(lease.name.clone(), self.owner.clone(), lease.token, ttl);
+        let state = self.call(op)?;
+        match self.held(state) {
+            Ok(l) if l.token == lease.token => Ok(l),
+            _ => Err(LockErr::Lost),
+        }
+    }
+
+    /// Releases `lease`, nothing to do if it has expired already.
+    pub fn unlock(&mut self, lease: &Lease) -> Result<(), LockErr> {
+        let op = LockOp::Unlock(lease.name.clone(), self.owner.clone(), lease.token);
+        self.call(op).map(|_| ())
+    }
+
+    /// Waits until the state of `name` is no longer `seen`, asking again
+    /// every `interval`, and returns the new one. A lease running out counts
+    /// as a change, renewing it does not.
+    pub fn watch(
+        &mut self,
+        name: &str,
+        seen: &LockState,
+        interval: Duration,
+        timeout: Duration,
+    ) -> Result<LockState, LockErr> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let state = self.call(LockOp::Watch(name.to_string()))?;
+            let same = match (&state, seen) {
+                (LockState::Held(a), LockState::Held(b)) => a.token == b.token,
+                (a, b) => a == b,
+            };
+            if !same {
+                return Ok(state);
+            }
+            if Instant::now() + interval > deadline {
+                return Err(LockErr::Client(ClientErr::Timeout));
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::Duration,
+    };
+
+    use bytes::Bytes;
+
+    use crate::{
+        client,
+        constants::MAX_CLOCK_SKEW_MS,
+        env::{Env, ProcessId, ProcessType},
+        fixture,
+        leader::Leader,
+        pval::{Command, SlotNumber},
+        replica::Replica,
+        state::StateMachine,
+    };
+
+    use super::{word, Lease, LockErr, LockOp, LockService, LockState, SKEWED};
+
+    fn held(owner: &str, token: SlotNumber, expires: u64) -> LockState {
+        LockState::Held(Lease {
+            name: "a".to_string(),
+            owner: owner.to_string(),
+            token,
+            expires,
+        })
+    }
+
+    #[test]
+    fn leases() {
+        let client = ProcessId::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0, 0);
+        let mut locks = LockService::new();
+        let mut apply = |slot: SlotNumber, op: LockOp, now| {
+            let c = Command::new_from_str(client.clone(), slot.to_string(), String::new());
+            let c = Command {
+                operation: op.encode(now),
+                ..c
+            };
+            LockState::parse("a", &locks.apply(slot, &c)).unwrap()
+        };
+        let a = || "a".to_string();
+        let (w1, w2) = (|| "w1".to_string(), || "w2".to_string());
+
+        assert_eq!(
+            apply(3, LockOp::Lock(a(), w1(), 100), 0),
+            held("w1", 3, 100)
+        );
+        assert_eq!(
+            apply(4, LockOp::Lock(a(), w2(), 100), 50),
+            held("w1", 3, 100)
+        );
+        assert_eq!(
+            apply(5, LockOp::Renew(a(), w1(), 3, 100), 90),
+            held("w1", 3, 190)
+        );
+
+        // the lease runs out, the next holder gets a higher token and the
+        // old one can neither renew nor release it
+        assert_eq!(apply(6, LockOp::Watch(a()), 190), LockState::Free);
+        assert_eq!(
+            apply(7, LockOp::Lock(a(), w2(), 100), 200),
+            held("w2", 7, 300)
+        );
+        assert_eq!(
+            apply(8, LockOp::Renew(a(), w1(), 3, 100), 210),
+            held("w2", 7, 300)
+        );
+        assert_eq!(
+            apply(9, LockOp::Unlock(a(), w1(), 3), 210),
+            held("w2", 7, 300)
+        );
+        assert_eq!(
+            apply(10, LockOp::Unlock(a(), w2(), 7), 220),
+            LockState::Free
+        );
+        assert_eq!(
+            LockOp::parse(b"LOCK a w1 100 5"),
+            Some((LockOp::Lock(a(), w1(), 100), 5))
+        );
+        assert_eq!(LockOp::parse(b"LOCK a 100 5"), None);
+        assert!(matches!(word("w 1"), Err(LockErr::BadName(_))));
+        assert!(matches!(word(""), Err(LockErr::BadName(_))));
+        assert!(word("w1").is_ok());
+    }
+
+    #[test]
+    fn clock_skew() {
+        let client = ProcessId::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0, 0);
+        let mut locks = LockService::new();
+        let mut apply = |slot: SlotNumber, op: LockOp, now| {
+            let c = Command::new_from_str(client.clone(), slot.to_string(), String::new());
+            let c = Command {
+                operation: op.encode(now),
+                ..c
+            };
+            locks.apply(slot, &c)
+        };
+        let a = || "a".to_string();
+        let state = |b: Bytes| LockState::parse("a", &b).unwrap();
+
+        // a TTL too large for the clock holds the lock for good
+        let lock = LockOp::Lock(a(), "w1".to_string(), u64::MAX);
+        assert_eq!(state(apply(1, lock, 1000)), held("w1", 1, u64::MAX));
+        assert_eq!(
+            state(apply(2, LockOp::Unlock(a(), "w1".to_string(), 1), 1000)),
+            LockState::Free
+        );
+
+        // a client far ahead is refused while a lease would run out early,
+        // every refusal moves the clock by the skew allowed
+        let lock = LockOp::Lock(a(), "w1".to_string(), 1500);
+        assert_eq!(state(apply(3, lock, 1000)), held("w1", 3, 2500));
+        let ahead = 1000 + 3 * MAX_CLOCK_SKEW_MS;
+        assert_eq!(apply(4, LockOp::Watch(a()), ahead), SKEWED);
+        assert_eq!(apply(5, LockOp::Watch(a()), ahead), SKEWED);
+        assert_eq!(state(apply(6, LockOp::Watch(a()), ahead)), LockState::Free);
+    }
+
+    #[test]
+    fn one_holder_at_a_time() {
+        let env = fixture::env();
+        fixture::acceptors(env, 3);
+        for _ in 0..2 {
+            let id = fixture::new_id(env);
+            let replica = Replica::with_state(id.clone(), Box::new(LockService::new()));
+            env.register(id, ProcessType::Replica, replica);
+        }
+        let id = fixture::new_id(env);
+        env.register(id.clone(), ProcessType::Leader, Leader::new(id));
+
+        let (client, r) = fixture::probe(env, ProcessType::Client);
+        let replicas = env.cluster().replicas();
+        let mut n = 0;
+        let mut call = |op: LockOp| {
+            n += 1;
+            let c = Command {
+                client: client.clone(),
+                req_id: Bytes::from(n.to_string()),
+                operation: op.encode(1000),
+            };
+            let (retry, timeout) = (Duration::from_millis(500), fixture::WAIT);
+            let reply = client::call(&replicas, env.router(), &r, c, retry, timeout).unwrap();
+            (reply.slot, LockState::parse("a", &reply.result).unwrap())
+        };
+        let a = || "a".to_string();
+
+        let (slot, state) = call(LockOp::Lock(a(), "w1".to_string(), 60000));
+        assert_eq!(state, held("w1", slot, 61000));
+        let (_, state) = call(LockOp::Lock(a(), "w2".to_string(), 60000));
+        assert_eq!(state, held("w1", slot, 61000));
+        let (_, state) = call(LockOp::Unlock(a(), "w1".to_string(), slot));
+        assert_eq!(state, LockState::Free);
+        let (next, state) = call(LockOp::Lock(a(), "w2".to_string(), 60000));
+        assert!(next > slot);
+        assert_eq!(state, held("w2", next, 61000));
+    }
+}
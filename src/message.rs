@@ -1,5 +1,6 @@
 use std::{collections::HashMap, fmt::Display};
 
+use bytes::Bytes;
 use protobuf::MessageField;
 
 use crate::{
@@ -10,34 +11,144 @@ use crate::{
 use super::{
     ds::Accepted,
     env::ProcessId,
-    pval::{BallotNumber, Command, SlotNumber},
+    pval::{BallotNumber, Command, Deps, Instance, SlotNumber},
 };
 
 #[derive(Clone, Debug)]
 pub enum Message {
-    P1A(ProcessId, BallotNumber),
-    P1B(ProcessId, BallotNumber, Accepted),
+    // the values below the slot are known to be decided and are left out of
+    // the P1B, 0 from peers that predate it asks for all of them in one page
+    P1A(ProcessId, BallotNumber, SlotNumber),
+    // a page of the reply to a P1A, its index and the number of pages
+    P1B(ProcessId, BallotNumber, Accepted, (u64, u64)),
     P2A(ProcessId, BallotNumber, SlotNumber, Command),
     P2B(ProcessId, BallotNumber, SlotNumber),
     Preempt(ProcessId, BallotNumber),
     Adopt(ProcessId, BallotNumber, Accepted),
-    Decision(ProcessId, SlotNumber, Command),
+    // with the ballot of the leader that got it decided, unset from peers
+    // that predate it
+    Decision(ProcessId, SlotNumber, Command, Option<BallotNumber>),
     Request(ProcessId, Command),
     Propose(ProcessId, SlotNumber, Command),
+    // a scout (no slot) or commander (slot) gave up on its ballot
+    Failed(ProcessId, BallotNumber, Option<SlotNumber>),
+    // admin commands, for a leader and a replica respectively
+    StepDown(ProcessId),
+    Snapshot(ProcessId),
+    // a replica telling the client what its command returned
+    Response(ProcessId, SlotNumber, Command, Bytes),
+    // first message on a connection, the protocol version is in the header
+    Hello(ProcessId),
+    // from the active leader to the others, in stable leader mode
+    Heartbeat(ProcessId, BallotNumber),
+    // between EPaxos replicas, with the seq and deps of the command
+    PreAccept(ProcessId, Instance, Command, u64, Deps),
+    PreAcceptOk(ProcessId, Instance, u64, Deps),
+    Accept(ProcessId, Instance, Command, u64, Deps),
+    AcceptOk(ProcessId, Instance),
+    Commit(ProcessId, Instance, Command, u64, Deps),
+    // from a leader to the acceptors, the ballot is fast from the slot on
+    FastAny(ProcessId, BallotNumber, SlotNumber),
+    // from a replica to the acceptors, in a fast ballot
+    FastRequest(ProcessId, SlotNumber, Command),
+    // from an acceptor to the leader of the fast ballot, the value it
+    // accepted in the slot
+    FastAccepted(ProcessId, BallotNumber, SlotNumber, Command),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeErr {
+    // sent by a peer on a newer protocol version
+    UnknownType(i32),
+    Malformed(String),
 }
 
 impl Message {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Message::P1A(_, _, _) => "P1A",
+            Message::P1B(_, _, _, _) => "P1B",
+            Message::P2A(_, _, _, _) => "P2A",
+            Message::P2B(_, _, _) => "P2B",
+            Message::Preempt(_, _) => "Preempt",
+            Message::Adopt(_, _, _) => "Adopt",
+            Message::Decision(_, _, _, _) => "Decision",
+            Message::Request(_, _) => "Request",
+            Message::Propose(_, _, _) => "Propose",
+            Message::Failed(_, _, _) => "Failed",
+            Message::StepDown(_) => "StepDown",
+            Message::Snapshot(_) => "Snapshot",
+            Message::Response(_, _, _, _) => "Response",
+            Message::Hello(_) => "Hello",
+            Message::Heartbeat(_, _) => "Heartbeat",
+            Message::PreAccept(_, _, _, _, _) => "PreAccept",
+            Message::PreAcceptOk(_, _, _, _) => "PreAcceptOk",
+            Message::Accept(_, _, _, _, _) => "Accept",
+            Message::AcceptOk(_, _) => "AcceptOk",
+            Message::Commit(_, _, _, _, _) => "Commit",
+            Message::FastAny(_, _, _) => "FastAny",
+            Message::FastRequest(_, _, _) => "FastRequest",
+            Message::FastAccepted(_, _, _, _) => "FastAccepted",
+        }
+    }
+
+    /// The protocol version that introduced this type of message, peers on
+    /// an older version are not sent it. New types must return the version
+    /// they are added in.
+    pub fn since(&self) -> u32 {
+        match self {
+            Message::Heartbeat(_, _) => 2,
+            Message::PreAccept(_, _, _, _, _)
+            | Message::PreAcceptOk(_, _, _, _)
+            | Message::Accept(_, _, _, _, _)
+            | Message::AcceptOk(_, _)
+            | Message::Commit(_, _, _, _, _) => 3,
+            Message::FastAny(_, _, _)
+            | Message::FastRequest(_, _, _)
+            | Message::FastAccepted(_, _, _, _) => 4,
+            _ => 1,
+        }
+    }
+
+    /// Whether the sender sends this message again until it is answered, so
+    /// it can be dropped when the peer is not keeping up.
+    pub fn retransmitted(&self) -> bool {
+        match self {
+            Message::P1A(_, _, _)
+            | Message::P2A(_, _, _, _)
+            | Message::P2B(_, _, _)
+            | Message::Request(_, _)
+            | Message::Hello(_)
+            | Message::Heartbeat(_, _) => true,
+            _ => false,
+        }
+    }
+
     pub fn id(&self) -> &ProcessId {
         return match self {
-            Message::P1A(id, _) => id,
-            Message::P1B(id, _, _) => id,
+            Message::P1A(id, _, _) => id,
+            Message::P1B(id, _, _, _) => id,
             Message::P2A(id, _, _, _) => id,
             Message::P2B(id, _, _) => id,
             Message::Preempt(id, _) => id,
             Message::Adopt(id, _, _) => id,
-            Message::Decision(id, _, _) => id,
+            Message::Decision(id, _, _, _) => id,
             Message::Request(id, _) => id,
             Message::Propose(id, _, _) => id,
+            Message::Failed(id, _, _) => id,
+            Message::StepDown(id) => id,
+            Message::Snapshot(id) => id,
+            Message::Response(id, _, _, _) => id,
+            Message::Hello(id) => id,
+            Message::Heartbeat(id, _) => id,
+            Message::PreAccept(id, _, _, _, _) => id,
+            Message::PreAcceptOk(id, _, _, _) => id,
+            Message::Accept(id, _, _, _, _) => id,
+            Message::AcceptOk(id, _) => id,
+            Message::Commit(id, _, _, _, _) => id,
+            Message::FastAny(id, _, _) => id,
+            Message::FastRequest(id, _, _) => id,
+            Message::FastAccepted(id, _, _, _) => id,
         };
     }
 }
@@ -47,7 +158,7 @@ impl Into<proto::ProcessId> for ProcessId {
         let mut def = proto::ProcessId::default();
         def.ip = match self.ip {
             std::net::IpAddr::V4(v4) => Option::Some(proto::process_id::Ip::V4(v4.into())),
-            std::net::IpAddr::V6(_) => todo!("ip v6 is not supported"),
+            std::net::IpAddr::V6(v6) => Option::Some(proto::process_id::Ip::V6(v6.to_string())),
         };
         def.port = self.port;
         def.id = self.id;
@@ -55,19 +166,37 @@ impl Into<proto::ProcessId> for ProcessId {
     }
 }
 
-impl From<proto::ProcessId> for ProcessId {
-    fn from(value: proto::ProcessId) -> Self {
-        Self {
+impl TryFrom<proto::ProcessId> for ProcessId {
+    type Error = DecodeErr;
+
+    fn try_from(value: proto::ProcessId) -> Result<Self, Self::Error> {
+        Ok(Self {
             ip: match value.ip {
                 Some(proto::process_id::Ip::V4(v)) => std::net::IpAddr::V4(v.into()),
-                _ => unreachable!("ip v6 is not supported"),
+                Some(proto::process_id::Ip::V6(v)) => std::net::IpAddr::V6(
+                    v.parse()
+                        .map_err(|_| DecodeErr::Malformed(format!("bad address {}", v)))?,
+                ),
+                None => return Err(missing("ip")),
             },
             port: value.port,
             id: value.id,
-        }
+        })
     }
 }
 
+fn missing(field: &str) -> DecodeErr {
+    DecodeErr::Malformed(format!("missing {}", field))
+}
+
+/// A message field the type of the message needs.
+pub(crate) fn required<T, U>(field: MessageField<T>, name: &str) -> Result<U, DecodeErr>
+where
+    U: TryFrom<T, Error = DecodeErr>,
+{
+    U::try_from(field.into_option().ok_or_else(|| missing(name))?)
+}
+
 impl Into<proto::BallotNumber> for BallotNumber {
     fn into(self) -> proto::BallotNumber {
         let mut def = proto::BallotNumber::default();
@@ -77,32 +206,60 @@ impl Into<proto::BallotNumber> for BallotNumber {
     }
 }
 
-impl From<proto::BallotNumber> for BallotNumber {
-    fn from(value: proto::BallotNumber) -> Self {
-        Self {
+impl TryFrom<proto::BallotNumber> for BallotNumber {
+    type Error = DecodeErr;
+
+    fn try_from(value: proto::BallotNumber) -> Result<Self, Self::Error> {
+        Ok(Self {
             round: value.round,
-            process_id: ProcessId::from(value.process_id.unwrap()),
-        }
+            process_id: required(value.process_id, "process_id")?,
+        })
     }
 }
 
+impl Into<proto::Instance> for Instance {
+    fn into(self) -> proto::Instance {
+        let mut def = proto::Instance::default();
+        def.replica = MessageField::some(self.replica.into());
+        def.number = self.number;
+        def
+    }
+}
+
+impl TryFrom<proto::Instance> for Instance {
+    type Error = DecodeErr;
+
+    fn try_from(value: proto::Instance) -> Result<Self, Self::Error> {
+        Ok(Self {
+            replica: required(value.replica, "replica")?,
+            number: value.number,
+        })
+    }
+}
+
+fn deps(value: Vec<proto::Instance>) -> Result<Deps, DecodeErr> {
+    value.into_iter().map(Instance::try_from).collect()
+}
+
 impl Into<proto::Command> for Command {
     fn into(self) -> proto::Command {
         let mut def = proto::Command::default();
         def.client = MessageField::some(self.client.into());
-        def.req_id = self.req_id.clone().into();
-        def.operation = self.operation.clone().into();
+        def.req_id = self.req_id;
+        def.operation = self.operation;
         def
     }
 }
 
-impl From<proto::Command> for Command {
-    fn from(value: proto::Command) -> Self {
-        Command {
-            client: value.client.unwrap().into(),
-            req_id: value.req_id.into(),
-            operation: value.operation.into(),
-        }
+impl TryFrom<proto::Command> for Command {
+    type Error = DecodeErr;
+
+    fn try_from(value: proto::Command) -> Result<Self, Self::Error> {
+        Ok(Command {
+            client: required(value.client, "client")?,
+            req_id: value.req_id,
+            operation: value.operation,
+        })
     }
 }
 
@@ -116,13 +273,15 @@ impl Into<proto::PValue> for PValue {
     }
 }
 
-impl From<proto::PValue> for PValue {
-    fn from(value: proto::PValue) -> Self {
-        Self {
-            ballot: value.ballot.unwrap().into(),
+impl TryFrom<proto::PValue> for PValue {
+    type Error = DecodeErr;
+
+    fn try_from(value: proto::PValue) -> Result<Self, Self::Error> {
+        Ok(Self {
+            ballot: required(value.ballot, "ballot")?,
             slot: value.slot,
-            command: value.command.unwrap().into(),
-        }
+            command: required(value.command, "command")?,
+        })
     }
 }
 
@@ -132,32 +291,38 @@ impl Into<HashMap<u64, proto::PValue>> for Accepted {
     }
 }
 
-impl From<HashMap<u64, proto::PValue>> for Accepted {
-    fn from(value: HashMap<u64, proto::PValue>) -> Self {
+impl TryFrom<HashMap<u64, proto::PValue>> for Accepted {
+    type Error = DecodeErr;
+
+    fn try_from(value: HashMap<u64, proto::PValue>) -> Result<Self, Self::Error> {
         let mut res = Self::new();
         for (k, v) in value.into_iter() {
-            res.insert(k, v.into());
+            res.insert(k, v.try_into()?);
         }
-        res
+        Ok(res)
     }
 }
 
 impl Into<proto::Message> for Message {
     fn into(self) -> proto::Message {
         return match self {
-            Message::P1A(id, ballot) => {
+            Message::P1A(id, ballot, from) => {
                 let mut def = proto::Message::default();
                 def.type_ = proto::MessageType::P1A.into();
                 def.process = MessageField::some(id.into());
                 def.ballot = MessageField::some(ballot.into());
+                def.slot = Option::Some(from);
                 def
             }
-            Message::P1B(id, ballot, accepted) => {
+            Message::P1B(id, ballot, accepted, (page, pages)) => {
                 let mut def = proto::Message::default();
                 def.type_ = proto::MessageType::P1B.into();
                 def.process = MessageField::some(id.into());
                 def.ballot = MessageField::some(ballot.into());
                 def.accepted = accepted.into();
+                def.last = Option::Some(page + 1 == pages);
+                def.page = Option::Some(page);
+                def.pages = Option::Some(pages);
                 def
             }
             Message::P2A(id, ballot, slot, command) => {
@@ -192,12 +357,13 @@ impl Into<proto::Message> for Message {
                 def.accepted = accepted.into();
                 def
             }
-            Message::Decision(id, slot, command) => {
+            Message::Decision(id, slot, command, ballot) => {
                 let mut def = proto::Message::default();
                 def.type_ = proto::MessageType::Decision.into();
                 def.process = MessageField::some(id.into());
                 def.slot = Option::Some(slot);
                 def.command = MessageField::some(command.into());
+                def.ballot = ballot.map(|b| b.into()).into();
                 def
             }
             Message::Request(id, command) => {
@@ -215,78 +381,326 @@ impl Into<proto::Message> for Message {
                 def.command = MessageField::some(command.into());
                 def
             }
+            Message::Failed(id, ballot, slot) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::Failed.into();
+                def.process = MessageField::some(id.into());
+                def.ballot = MessageField::some(ballot.into());
+                def.slot = slot;
+                def
+            }
+            Message::StepDown(id) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::StepDown.into();
+                def.process = MessageField::some(id.into());
+                def
+            }
+            Message::Snapshot(id) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::Snapshot.into();
+                def.process = MessageField::some(id.into());
+                def
+            }
+            Message::Response(id, slot, command, result) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::Response.into();
+                def.process = MessageField::some(id.into());
+                def.slot = Option::Some(slot);
+                def.command = MessageField::some(command.into());
+                def.result = Option::Some(result);
+                def
+            }
+            Message::Hello(id) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::Hello.into();
+                def.process = MessageField::some(id.into());
+                def
+            }
+            Message::Heartbeat(id, ballot) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::Heartbeat.into();
+                def.process = MessageField::some(id.into());
+                def.ballot = MessageField::some(ballot.into());
+                def
+            }
+            Message::PreAccept(id, instance, command, seq, deps) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::PreAccept.into();
+                def.process = MessageField::some(id.into());
+                def.instance = MessageField::some(instance.into());
+                def.command = MessageField::some(command.into());
+                def.seq = Option::Some(seq);
+                def.deps = deps.into_iter().map(|i| i.into()).collect();
+                def
+            }
+            Message::PreAcceptOk(id, instance, seq, deps) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::PreAcceptOk.into();
+                def.process = MessageField::some(id.into());
+                def.instance = MessageField::some(instance.into());
+                def.seq = Option::Some(seq);
+                def.deps = deps.into_iter().map(|i| i.into()).collect();
+                def
+            }
+            Message::Accept(id, instance, command, seq, deps) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::Accept.into();
+                def.process = MessageField::some(id.into());
+                def.instance = MessageField::some(instance.into());
+                def.command = MessageField::some(command.into());
+                def.seq = Option::Some(seq);
+                def.deps = deps.into_iter().map(|i| i.into()).collect();
+                def
+            }
+            Message::AcceptOk(id, instance) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::AcceptOk.into();
+                def.process = MessageField::some(id.into());
+                def.instance = MessageField::some(instance.into());
+                def
+            }
+            Message::Commit(id, instance, command, seq, deps) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::Commit.into();
+                def.process = MessageField::some(id.into());
+                def.instance = MessageField::some(instance.into());
+                def.command = MessageField::some(command.into());
+                def.seq = Option::Some(seq);
+                def.deps = deps.into_iter().map(|i| i.into()).collect();
+                def
+            }
+            Message::FastAny(id, ballot, from) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::FastAny.into();
+                def.process = MessageField::some(id.into());
+                def.ballot = MessageField::some(ballot.into());
+                def.slot = Option::Some(from);
+                def
+            }
+            Message::FastRequest(id, slot, command) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::FastRequest.into();
+                def.process = MessageField::some(id.into());
+                def.slot = Option::Some(slot);
+                def.command = MessageField::some(command.into());
+                def
+            }
+            Message::FastAccepted(id, ballot, slot, command) => {
+                let mut def = proto::Message::default();
+                def.type_ = proto::MessageType::FastAccepted.into();
+                def.process = MessageField::some(id.into());
+                def.ballot = MessageField::some(ballot.into());
+                def.slot = Option::Some(slot);
+                def.command = MessageField::some(command.into());
+                def
+            }
         };
     }
 }
 
-impl From<proto::Message> for Message {
-    fn from(value: proto::Message) -> Self {
-        match value.type_.enum_value() {
+impl TryFrom<proto::Message> for Message {
+    type Error = DecodeErr;
+
+    fn try_from(value: proto::Message) -> Result<Self, Self::Error> {
+        let res = match value.type_.enum_value() {
             Ok(t) => match t {
-                proto::MessageType::P1A => {
-                    Message::P1A(value.process.unwrap().into(), value.ballot.unwrap().into())
-                }
+                proto::MessageType::P1A => Message::P1A(
+                    required(value.process, "process")?,
+                    required(value.ballot, "ballot")?,
+                    value.slot.unwrap_or(0),
+                ),
                 proto::MessageType::P1B => Message::P1B(
-                    value.process.unwrap().into(),
-                    value.ballot.unwrap().into(),
-                    value.accepted.into(),
+                    required(value.process, "process")?,
+                    required(value.ballot, "ballot")?,
+                    value.accepted.try_into()?,
+                    (value.page.unwrap_or(0), value.pages.unwrap_or(1)),
                 ),
                 proto::MessageType::P2A => Message::P2A(
-                    value.process.unwrap().into(),
-                    value.ballot.unwrap().into(),
-                    value.slot.unwrap(),
-                    value.command.unwrap().into(),
+                    required(value.process, "process")?,
+                    required(value.ballot, "ballot")?,
+                    value.slot.ok_or_else(|| missing("slot"))?,
+                    required(value.command, "command")?,
                 ),
                 proto::MessageType::P2B => Message::P2B(
-                    value.process.unwrap().into(),
-                    value.ballot.unwrap().into(),
-                    value.slot.unwrap(),
+                    required(value.process, "process")?,
+                    required(value.ballot, "ballot")?,
+                    value.slot.ok_or_else(|| missing("slot"))?,
+                ),
+                proto::MessageType::Preempt => Message::Preempt(
+                    required(value.process, "process")?,
+                    required(value.ballot, "ballot")?,
                 ),
-                proto::MessageType::Preempt => {
-                    Message::Preempt(value.process.unwrap().into(), value.ballot.unwrap().into())
-                }
                 proto::MessageType::Adopt => Message::Adopt(
-                    value.process.unwrap().into(),
-                    value.ballot.unwrap().into(),
-                    value.accepted.into(),
+                    required(value.process, "process")?,
+                    required(value.ballot, "ballot")?,
+                    value.accepted.try_into()?,
                 ),
                 proto::MessageType::Decision => Message::Decision(
-                    value.process.unwrap().into(),
-                    value.slot.unwrap(),
-                    value.command.unwrap().into(),
+                    required(value.process, "process")?,
+                    value.slot.ok_or_else(|| missing("slot"))?,
+                    required(value.command, "command")?,
+                    value
+                        .ballot
+                        .into_option()
+                        .map(BallotNumber::try_from)
+                        .transpose()?,
+                ),
+                proto::MessageType::Request => Message::Request(
+                    required(value.process, "process")?,
+                    required(value.command, "command")?,
                 ),
-                proto::MessageType::Request => {
-                    Message::Request(value.process.unwrap().into(), value.command.unwrap().into())
-                }
                 proto::MessageType::Propose => Message::Propose(
-                    value.process.unwrap().into(),
-                    value.slot.unwrap(),
-                    value.command.unwrap().into(),
+                    required(value.process, "process")?,
+                    value.slot.ok_or_else(|| missing("slot"))?,
+                    required(value.command, "command")?,
+                ),
+                proto::MessageType::Failed => Message::Failed(
+                    required(value.process, "process")?,
+                    required(value.ballot, "ballot")?,
+                    value.slot,
+                ),
+                proto::MessageType::StepDown => {
+                    Message::StepDown(required(value.process, "process")?)
+                }
+                proto::MessageType::Snapshot => {
+                    Message::Snapshot(required(value.process, "process")?)
+                }
+                proto::MessageType::Response => Message::Response(
+                    required(value.process, "process")?,
+                    value.slot.ok_or_else(|| missing("slot"))?,
+                    required(value.command, "command")?,
+                    value.result.unwrap_or_default(),
+                ),
+                proto::MessageType::Hello => Message::Hello(required(value.process, "process")?),
+                proto::MessageType::Heartbeat => Message::Heartbeat(
+                    required(value.process, "process")?,
+                    required(value.ballot, "ballot")?,
+                ),
+                proto::MessageType::PreAccept => Message::PreAccept(
+                    required(value.process, "process")?,
+                    required(value.instance, "instance")?,
+                    required(value.command, "command")?,
+                    value.seq.ok_or_else(|| missing("seq"))?,
+                    deps(value.deps)?,
+                ),
+                proto::MessageType::PreAcceptOk => Message::PreAcceptOk(
+                    required(value.process, "process")?,
+                    required(value.instance, "instance")?,
+                    value.seq.ok_or_else(|| missing("seq"))?,
+                    deps(value.deps)?,
+                ),
+                proto::MessageType::Accept => Message::Accept(
+                    required(value.process, "process")?,
+                    required(value.instance, "instance")?,
+                    required(value.command, "command")?,
+                    value.seq.ok_or_else(|| missing("seq"))?,
+                    deps(value.deps)?,
+                ),
+                proto::MessageType::AcceptOk => Message::AcceptOk(
+                    required(value.process, "process")?,
+                    required(value.instance, "instance")?,
+                ),
+                proto::MessageType::Commit => Message::Commit(
+                    required(value.process, "process")?,
+                    required(value.instance, "instance")?,
+                    required(value.command, "command")?,
+                    value.seq.ok_or_else(|| missing("seq"))?,
+                    deps(value.deps)?,
+                ),
+                proto::MessageType::FastAny => Message::FastAny(
+                    required(value.process, "process")?,
+                    required(value.ballot, "ballot")?,
+                    value.slot.ok_or_else(|| missing("slot"))?,
+                ),
+                proto::MessageType::FastRequest => Message::FastRequest(
+                    required(value.process, "process")?,
+                    value.slot.ok_or_else(|| missing("slot"))?,
+                    required(value.command, "command")?,
+                ),
+                proto::MessageType::FastAccepted => Message::FastAccepted(
+                    required(value.process, "process")?,
+                    required(value.ballot, "ballot")?,
+                    value.slot.ok_or_else(|| missing("slot"))?,
+                    required(value.command, "command")?,
                 ),
             },
-            Err(_) => unreachable!("should always be present"),
-        }
+            Err(t) => return Err(DecodeErr::UnknownType(t)),
+        };
+        Ok(res)
     }
 }
 
 impl Display for Message {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Message::P1A(id, ballot) => write!(f, "P1A({}, {})", id, ballot),
-            Message::P1B(id, ballot, pval) => write!(f, "P1B({}, {} ,{:#?})", id, ballot, pval),
+            Message::P1A(id, ballot, from) => write!(f, "P1A({}, {}, {})", id, ballot, from),
+            Message::P1B(id, ballot, pval, (page, pages)) => {
+                write!(
+                    f,
+                    "P1B({}, {} ,{:#?}, {}/{})",
+                    id,
+                    ballot,
+                    pval,
+                    page + 1,
+                    pages
+                )
+            }
             Message::P2A(id, ballot, slot, command) => {
                 write!(f, "P2A({}, {}, {}, {})", id, ballot, slot, command)
             }
             Message::P2B(id, ballot, slot) => write!(f, "P2B({}, {}, {})", id, ballot, slot),
             Message::Preempt(id, ballot) => write!(f, "PREEMPT({}, {})", id, ballot),
             Message::Adopt(id, ballot, vals) => write!(f, "ADOPT({}, {}, {:#?})", id, ballot, vals),
-            Message::Decision(id, slot, command) => {
+            Message::Decision(id, slot, command, _) => {
                 write!(f, "DECISION({}, {}, {})", id, slot, command)
             }
             Message::Request(id, command) => write!(f, "REQUEST({}, {})", id, command),
             Message::Propose(id, slot, command) => {
                 write!(f, "PROPOSE({}, {}, {})", id, slot, command)
             }
+            Message::Failed(id, ballot, slot) => {
+                write!(f, "FAILED({}, {}, {:?})", id, ballot, slot)
+            }
+            Message::StepDown(id) => write!(f, "STEPDOWN({})", id),
+            Message::Snapshot(id) => write!(f, "SNAPSHOT({})", id),
+            Message::Hello(id) => write!(f, "HELLO({})", id),
+            Message::Heartbeat(id, ballot) => write!(f, "Heartbeat({}, {})", id, ballot),
+            Message::PreAccept(id, instance, command, seq, deps) => write!(
+                f,
+                "PREACCEPT({}, {}, {}, {}, {:?})",
+                id, instance, command, seq, deps
+            ),
+            Message::PreAcceptOk(id, instance, seq, deps) => {
+                write!(f, "PREACCEPTOK({}, {}, {}, {:?})", id, instance, seq, deps)
+            }
+            Message::Accept(id, instance, command, seq, deps) => write!(
+                f,
+                "ACCEPT({}, {}, {}, {}, {:?})",
+                id, instance, command, seq, deps
+            ),
+            Message::AcceptOk(id, instance) => write!(f, "ACCEPTOK({}, {})", id, instance),
+            Message::Commit(id, instance, command, seq, deps) => write!(
+                f,
+                "COMMIT({}, {}, {}, {}, {:?})",
+                id, instance, command, seq, deps
+            ),
+            Message::FastAny(id, ballot, from) => {
+                write!(f, "FASTANY({}, {}, {})", id, ballot, from)
+            }
+            Message::FastRequest(id, slot, command) => {
+                write!(f, "FASTREQUEST({}, {}, {})", id, slot, command)
+            }
+            Message::FastAccepted(id, ballot, slot, command) => {
+                write!(f, "FASTACCEPTED({}, {}, {}, {})", id, ballot, slot, command)
+            }
+            Message::Response(id, slot, command, result) => write!(
+                f,
+                "RESPONSE({}, {}, {}, {})",
+                id,
+                slot,
+                command,
+                String::from_utf8_lossy(result)
+            ),
         }
     }
 }
@@ -0,0 +1,191 @@
+use std::{collections::BTreeMap, fmt::Write, io, sync::Mutex, thread};
+
+use once_cell::sync::Lazy;
+
+use super::{
+    env::ProcessId,
+    http::{self, Response},
+};
+
+/// Upper bounds of the histogram buckets, in seconds.
+static BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+type Key = (&'static str, String);
+
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Process wide counters, gauges and histograms, rendered in the Prometheus
+/// text exposition format.
+pub struct Registry {
+    counters: Mutex<BTreeMap<Key, u64>>,
+    gauges: Mutex<BTreeMap<Key, f64>>,
+    histograms: Mutex<BTreeMap<Key, Histogram>>,
+}
+
+pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+fn labels(l: &[(&str, &str)]) -> String {
+    let l: Vec<String> = l.iter().map(|(k, v)| format!("{}=\"{}\"", k, v)).collect();
+    l.join(",")
+}
+
+fn series(name: &str, l: &str) -> String {
+    if l.is_empty() {
+        return name.to_string();
+    }
+    format!("{}{{{}}}", name, l)
+}
+
+pub fn process(id: &ProcessId) -> String {
+    format!("{}:{}/{}", id.ip, id.port, id.id)
+}
+
+impl Registry {
+    fn new() -> Registry {
+        Registry {
+            counters: Mutex::new(BTreeMap::new()),
+            gauges: Mutex::new(BTreeMap::new()),
+            histograms: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self, name: &'static str, l: &[(&str, &str)]) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .entry((name, labels(l)))
+            .or_insert(0) += 1;
+    }
+
+    pub fn set(&self, name: &'static str, l: &[(&str, &str)], v: f64) {
+        self.gauges.lock().unwrap().insert((name, labels(l)), v);
+    }
+
+    pub fn observe(&self, name: &'static str, l: &[(&str, &str)], v: f64) {
+        let mut m = self.histograms.lock().unwrap();
+        let h = m.entry((name, labels(l))).or_insert(Histogram {
+            buckets: vec![0; BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        });
+        for (i, b) in BUCKETS.iter().enumerate() {
+            if v <= *b {
+                h.buckets[i] += 1;
+            }
+        }
+        h.sum += v;
+        h.count += 1;
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let mut last = "";
+        for ((name, l), v) in self.counters.lock().unwrap().iter() {
+            if *name != last {
+                let _ = writeln!(out, "# TYPE {} counter", name);
+                last = name;
+            }
+            let _ = writeln!(out, "{} {}", series(name, l), v);
+        }
+
+        last = "";
+        for ((name, l), v) in self.gauges.lock().unwrap().iter() {
+            if *name != last {
+                let _ = writeln!(out, "# TYPE {} gauge", name);
+                last = name;
+            }
+            let _ = writeln!(out, "{} {}", series(name, l), v);
+        }
+
+        last = "";
+        for ((name, l), h) in self.histograms.lock().unwrap().iter() {
+            if *name != last {
+                let _ = writeln!(out, "# TYPE {} histogram", name);
+                last = name;
+            }
+            let sep = if l.is_empty() { "" } else { "," };
+            for (i, b) in BUCKETS.iter().enumerate() {
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{{}{}le=\"{}\"}} {}",
+                    name, l, sep, b, h.buckets[i]
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+                name, l, sep, h.count
+            );
+            let _ = writeln!(out, "{} {}", series(&format!("{}_sum", name), l), h.sum);
+            let _ = writeln!(out, "{} {}", series(&format!("{}_count", name), l), h.count);
+        }
+        out
+    }
+}
+
+/// Serves `GET /metrics` on `addr` from a background thread, once bound.
+pub fn serve(addr: &str) -> io::Result<()> {
+    let listener = http::bind(addr)?;
+    thread::spawn(move || {
+        http::serve(listener, |method, path| match (method, path) {
+            ("GET", "/metrics") => Response::ok(REGISTRY.render()),
+            _ => Response::not_found(),
+        })
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Registry;
+
+    #[test]
+    fn render() {
+        let r = Registry::new();
+        r.inc("sent_total", &[("type", "P1A")]);
+        r.inc("sent_total", &[("type", "P1A")]);
+        r.inc("sent_total", &[("type", "P2A")]);
+        r.set("ballot_round", &[], 3.0);
+        for v in [0.003, 2.0, 10.0] {
+            r.observe("latency_seconds", &[("process", "a")], v);
+        }
+
+        let out = r.render();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[..5],
+            [
+                "# TYPE sent_total counter",
+                "sent_total{type=\"P1A\"} 2",
+                "sent_total{type=\"P2A\"} 1",
+                "# TYPE ballot_round gauge",
+                "ballot_round 3",
+            ]
+        );
+        assert_eq!(lines[5], "# TYPE latency_seconds histogram");
+        // buckets count every value up to their bound
+        for (le, n) in [
+            ("0.0025", 0),
+            ("0.005", 1),
+            ("1", 1),
+            ("2.5", 2),
+            ("+Inf", 3),
+        ] {
+            let line = format!(
+                "latency_seconds_bucket{{process=\"a\",le=\"{}\"}} {}",
+                le, n
+            );
+            assert!(lines.contains(&line.as_str()), "{} missing", line);
+        }
+        assert!(lines.contains(&"latency_seconds_sum{process=\"a\"} 12.003"));
+        assert!(lines.contains(&"latency_seconds_count{process=\"a\"} 3"));
+    }
+}
@@ -33,6 +33,8 @@ pub struct WireMessage {
     pub to: ::protobuf::MessageField<ProcessId>,
     // @@protoc_insertion_point(field:WireMessage.message)
     pub message: ::protobuf::MessageField<Message>,
+    // @@protoc_insertion_point(field:WireMessage.version)
+    pub version: u32,
     // special fields
     // @@protoc_insertion_point(special_field:WireMessage.special_fields)
     pub special_fields: ::protobuf::SpecialFields,
@@ -50,7 +52,7 @@ impl WireMessage {
     }
 
     fn generated_message_descriptor_data() -> ::protobuf::reflect::GeneratedMessageDescriptorData {
-        let mut fields = ::std::vec::Vec::with_capacity(2);
+        let mut fields = ::std::vec::Vec::with_capacity(3);
         let mut oneofs = ::std::vec::Vec::with_capacity(0);
         fields.push(::protobuf::reflect::rt::v2::make_message_field_accessor::<_, ProcessId>(
             "to",
@@ -62,6 +64,11 @@ impl WireMessage {
             |m: &WireMessage| { &m.message },
             |m: &mut WireMessage| { &mut m.message },
         ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "version",
+            |m: &WireMessage| { &m.version },
+            |m: &mut WireMessage| { &mut m.version },
+        ));
         ::protobuf::reflect::GeneratedMessageDescriptorData::new_2::<WireMessage>(
             "WireMessage",
             fields,
@@ -86,6 +93,9 @@ impl ::protobuf::Message for WireMessage {
                 18 => {
                     ::protobuf::rt::read_singular_message_into_field(is, &mut self.message)?;
                 },
+                24 => {
+                    self.version = is.read_uint32()?;
+                },
                 tag => {
                     ::protobuf::rt::read_unknown_or_skip_group(tag, is, self.special_fields.mut_unknown_fields())?;
                 },
@@ -106,6 +116,9 @@ impl ::protobuf::Message for WireMessage {
             let len = v.compute_size();
             my_size += 1 + ::protobuf::rt::compute_raw_varint64_size(len) + len;
         }
+        if self.version != 0 {
+            my_size += ::protobuf::rt::uint32_size(3, self.version);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.special_fields.unknown_fields());
         self.special_fields.cached_size().set(my_size as u32);
         my_size
@@ -118,6 +131,9 @@ impl ::protobuf::Message for WireMessage {
         if let Some(v) = self.message.as_ref() {
             ::protobuf::rt::write_message_field_with_cached_size(2, v, os)?;
         }
+        if self.version != 0 {
+            os.write_uint32(3, self.version)?;
+        }
         os.write_unknown_fields(self.special_fields.unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -137,6 +153,7 @@ impl ::protobuf::Message for WireMessage {
     fn clear(&mut self) {
         self.to.clear();
         self.message.clear();
+        self.version = 0;
         self.special_fields.clear();
     }
 
@@ -144,6 +161,7 @@ impl ::protobuf::Message for WireMessage {
         static instance: WireMessage = WireMessage {
             to: ::protobuf::MessageField::none(),
             message: ::protobuf::MessageField::none(),
+            version: 0,
             special_fields: ::protobuf::SpecialFields::new(),
         };
         &instance
@@ -183,6 +201,20 @@ pub struct Message {
     pub command: ::protobuf::MessageField<Command>,
     // @@protoc_insertion_point(field:Message.accepted)
     pub accepted: ::std::collections::HashMap<u64, PValue>,
+    // @@protoc_insertion_point(field:Message.result)
+    pub result: ::std::option::Option<::bytes::Bytes>,
+    // @@protoc_insertion_point(field:Message.last)
+    pub last: ::std::option::Option<bool>,
+    // @@protoc_insertion_point(field:Message.instance)
+    pub instance: ::protobuf::MessageField<Instance>,
+    // @@protoc_insertion_point(field:Message.seq)
+    pub seq: ::std::option::Option<u64>,
+    // @@protoc_insertion_point(field:Message.deps)
+    pub deps: ::std::vec::Vec<Instance>,
+    // @@protoc_insertion_point(field:Message.page)
+    pub page: ::std::option::Option<u64>,
+    // @@protoc_insertion_point(field:Message.pages)
+    pub pages: ::std::option::Option<u64>,
     // special fields
     // @@protoc_insertion_point(special_field:Message.special_fields)
     pub special_fields: ::protobuf::SpecialFields,
@@ -200,7 +232,7 @@ impl Message {
     }
 
     fn generated_message_descriptor_data() -> ::protobuf::reflect::GeneratedMessageDescriptorData {
-        let mut fields = ::std::vec::Vec::with_capacity(6);
+        let mut fields = ::std::vec::Vec::with_capacity(13);
         let mut oneofs = ::std::vec::Vec::with_capacity(0);
         fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
             "type",
@@ -232,6 +264,41 @@ impl Message {
             |m: &Message| { &m.accepted },
             |m: &mut Message| { &mut m.accepted },
         ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "result",
+            |m: &Message| { &m.result },
+            |m: &mut Message| { &mut m.result },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "last",
+            |m: &Message| { &m.last },
+            |m: &mut Message| { &mut m.last },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_message_field_accessor::<_, Instance>(
+            "instance",
+            |m: &Message| { &m.instance },
+            |m: &mut Message| { &mut m.instance },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "seq",
+            |m: &Message| { &m.seq },
+            |m: &mut Message| { &mut m.seq },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_vec_simpler_accessor::<_, _>(
+            "deps",
+            |m: &Message| { &m.deps },
+            |m: &mut Message| { &mut m.deps },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "page",
+            |m: &Message| { &m.page },
+            |m: &mut Message| { &mut m.page },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_option_accessor::<_, _>(
+            "pages",
+            |m: &Message| { &m.pages },
+            |m: &mut Message| { &mut m.pages },
+        ));
         ::protobuf::reflect::GeneratedMessageDescriptorData::new_2::<Message>(
             "Message",
             fields,
@@ -280,6 +347,27 @@ impl ::protobuf::Message for Message {
                     is.pop_limit(old_limit);
                     self.accepted.insert(key, value);
                 },
+                58 => {
+                    self.result = ::std::option::Option::Some(is.read_tokio_bytes()?);
+                },
+                64 => {
+                    self.last = ::std::option::Option::Some(is.read_bool()?);
+                },
+                74 => {
+                    ::protobuf::rt::read_singular_message_into_field(is, &mut self.instance)?;
+                },
+                80 => {
+                    self.seq = ::std::option::Option::Some(is.read_uint64()?);
+                },
+                90 => {
+                    self.deps.push(is.read_message()?);
+                },
+                96 => {
+                    self.page = ::std::option::Option::Some(is.read_uint64()?);
+                },
+                104 => {
+                    self.pages = ::std::option::Option::Some(is.read_uint64()?);
+                },
                 tag => {
                     ::protobuf::rt::read_unknown_or_skip_group(tag, is, self.special_fields.mut_unknown_fields())?;
                 },
@@ -317,6 +405,29 @@ impl ::protobuf::Message for Message {
             entry_size += 1 + ::protobuf::rt::compute_raw_varint64_size(len) + len;
             my_size += 1 + ::protobuf::rt::compute_raw_varint64_size(entry_size) + entry_size
         };
+        if let Some(v) = self.result.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(7, &v);
+        }
+        if let Some(v) = self.last {
+            my_size += 1 + 1;
+        }
+        if let Some(v) = self.instance.as_ref() {
+            let len = v.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint64_size(len) + len;
+        }
+        if let Some(v) = self.seq {
+            my_size += ::protobuf::rt::uint64_size(10, v);
+        }
+        for value in &self.deps {
+            let len = value.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint64_size(len) + len;
+        };
+        if let Some(v) = self.page {
+            my_size += ::protobuf::rt::uint64_size(12, v);
+        }
+        if let Some(v) = self.pages {
+            my_size += ::protobuf::rt::uint64_size(13, v);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.special_fields.unknown_fields());
         self.special_fields.cached_size().set(my_size as u32);
         my_size
@@ -348,6 +459,27 @@ impl ::protobuf::Message for Message {
             os.write_uint64(1, *k)?;
             ::protobuf::rt::write_message_field_with_cached_size(2, v, os)?;
         };
+        if let Some(v) = self.result.as_ref() {
+            os.write_bytes(7, v)?;
+        }
+        if let Some(v) = self.last {
+            os.write_bool(8, v)?;
+        }
+        if let Some(v) = self.instance.as_ref() {
+            ::protobuf::rt::write_message_field_with_cached_size(9, v, os)?;
+        }
+        if let Some(v) = self.seq {
+            os.write_uint64(10, v)?;
+        }
+        for v in &self.deps {
+            ::protobuf::rt::write_message_field_with_cached_size(11, v, os)?;
+        };
+        if let Some(v) = self.page {
+            os.write_uint64(12, v)?;
+        }
+        if let Some(v) = self.pages {
+            os.write_uint64(13, v)?;
+        }
         os.write_unknown_fields(self.special_fields.unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -371,6 +503,13 @@ impl ::protobuf::Message for Message {
         self.slot = ::std::option::Option::None;
         self.command.clear();
         self.accepted.clear();
+        self.result = ::std::option::Option::None;
+        self.last = ::std::option::Option::None;
+        self.instance.clear();
+        self.seq = ::std::option::Option::None;
+        self.deps.clear();
+        self.page = ::std::option::Option::None;
+        self.pages = ::std::option::Option::None;
         self.special_fields.clear();
     }
 
@@ -685,6 +824,147 @@ pub mod process_id {
     }
 }
 
+#[derive(PartialEq,Clone,Default,Debug)]
+// @@protoc_insertion_point(message:Instance)
+pub struct Instance {
+    // message fields
+    // @@protoc_insertion_point(field:Instance.replica)
+    pub replica: ::protobuf::MessageField<ProcessId>,
+    // @@protoc_insertion_point(field:Instance.number)
+    pub number: u64,
+    // special fields
+    // @@protoc_insertion_point(special_field:Instance.special_fields)
+    pub special_fields: ::protobuf::SpecialFields,
+}
+
+impl<'a> ::std::default::Default for &'a Instance {
+    fn default() -> &'a Instance {
+        <Instance as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl Instance {
+    pub fn new() -> Instance {
+        ::std::default::Default::default()
+    }
+
+    fn generated_message_descriptor_data() -> ::protobuf::reflect::GeneratedMessageDescriptorData {
+        let mut fields = ::std::vec::Vec::with_capacity(2);
+        let mut oneofs = ::std::vec::Vec::with_capacity(0);
+        fields.push(::protobuf::reflect::rt::v2::make_message_field_accessor::<_, ProcessId>(
+            "replica",
+            |m: &Instance| { &m.replica },
+            |m: &mut Instance| { &mut m.replica },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "number",
+            |m: &Instance| { &m.number },
+            |m: &mut Instance| { &mut m.number },
+        ));
+        ::protobuf::reflect::GeneratedMessageDescriptorData::new_2::<Instance>(
+            "Instance",
+            fields,
+            oneofs,
+        )
+    }
+}
+
+impl ::protobuf::Message for Instance {
+    const NAME: &'static str = "Instance";
+
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::Result<()> {
+        while let Some(tag) = is.read_raw_tag_or_eof()? {
+            match tag {
+                10 => {
+                    ::protobuf::rt::read_singular_message_into_field(is, &mut self.replica)?;
+                },
+                16 => {
+                    self.number = is.read_uint64()?;
+                },
+                tag => {
+                    ::protobuf::rt::read_unknown_or_skip_group(tag, is, self.special_fields.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u64 {
+        let mut my_size = 0;
+        if let Some(v) = self.replica.as_ref() {
+            let len = v.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint64_size(len) + len;
+        }
+        if self.number != 0 {
+            my_size += ::protobuf::rt::uint64_size(2, self.number);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.special_fields.unknown_fields());
+        self.special_fields.cached_size().set(my_size as u32);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::Result<()> {
+        if let Some(v) = self.replica.as_ref() {
+            ::protobuf::rt::write_message_field_with_cached_size(1, v, os)?;
+        }
+        if self.number != 0 {
+            os.write_uint64(2, self.number)?;
+        }
+        os.write_unknown_fields(self.special_fields.unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn special_fields(&self) -> &::protobuf::SpecialFields {
+        &self.special_fields
+    }
+
+    fn mut_special_fields(&mut self) -> &mut ::protobuf::SpecialFields {
+        &mut self.special_fields
+    }
+
+    fn new() -> Instance {
+        Instance::new()
+    }
+
+    fn clear(&mut self) {
+        self.replica.clear();
+        self.number = 0;
+        self.special_fields.clear();
+    }
+
+    fn default_instance() -> &'static Instance {
+        static instance: Instance = Instance {
+            replica: ::protobuf::MessageField::none(),
+            number: 0,
+            special_fields: ::protobuf::SpecialFields::new(),
+        };
+        &instance
+    }
+}
+
+impl ::protobuf::MessageFull for Instance {
+    fn descriptor() -> ::protobuf::reflect::MessageDescriptor {
+        static descriptor: ::protobuf::rt::Lazy<::protobuf::reflect::MessageDescriptor> = ::protobuf::rt::Lazy::new();
+        descriptor.get(|| file_descriptor().message_by_package_relative_name("Instance").unwrap()).clone()
+    }
+}
+
+impl ::std::fmt::Display for Instance {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        ::protobuf::text_format::fmt(self, f)
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Instance {
+    type RuntimeType = ::protobuf::reflect::rt::RuntimeTypeMessage<Self>;
+}
+
 #[derive(PartialEq,Clone,Default,Debug)]
 // @@protoc_insertion_point(message:BallotNumber)
 pub struct BallotNumber {
@@ -833,9 +1113,9 @@ pub struct Command {
     // @@protoc_insertion_point(field:Command.client)
     pub client: ::protobuf::MessageField<ProcessId>,
     // @@protoc_insertion_point(field:Command.req_id)
-    pub req_id: ::std::vec::Vec<u8>,
+    pub req_id: ::bytes::Bytes,
     // @@protoc_insertion_point(field:Command.operation)
-    pub operation: ::std::vec::Vec<u8>,
+    pub operation: ::bytes::Bytes,
     // special fields
     // @@protoc_insertion_point(special_field:Command.special_fields)
     pub special_fields: ::protobuf::SpecialFields,
@@ -892,10 +1172,10 @@ impl ::protobuf::Message for Command {
                     ::protobuf::rt::read_singular_message_into_field(is, &mut self.client)?;
                 },
                 18 => {
-                    self.req_id = is.read_bytes()?;
+                    self.req_id = is.read_tokio_bytes()?;
                 },
                 26 => {
-                    self.operation = is.read_bytes()?;
+                    self.operation = is.read_tokio_bytes()?;
                 },
                 tag => {
                     ::protobuf::rt::read_unknown_or_skip_group(tag, is, self.special_fields.mut_unknown_fields())?;
@@ -960,8 +1240,8 @@ impl ::protobuf::Message for Command {
     fn default_instance() -> &'static Command {
         static instance: Command = Command {
             client: ::protobuf::MessageField::none(),
-            req_id: ::std::vec::Vec::new(),
-            operation: ::std::vec::Vec::new(),
+            req_id: ::bytes::Bytes::new(),
+            operation: ::bytes::Bytes::new(),
             special_fields: ::protobuf::SpecialFields::new(),
         };
         &instance
@@ -1166,6 +1446,34 @@ pub enum MessageType {
     Request = 7,
     // @@protoc_insertion_point(enum_value:MessageType.Propose)
     Propose = 8,
+    // @@protoc_insertion_point(enum_value:MessageType.Failed)
+    Failed = 9,
+    // @@protoc_insertion_point(enum_value:MessageType.StepDown)
+    StepDown = 10,
+    // @@protoc_insertion_point(enum_value:MessageType.Snapshot)
+    Snapshot = 11,
+    // @@protoc_insertion_point(enum_value:MessageType.Response)
+    Response = 12,
+    // @@protoc_insertion_point(enum_value:MessageType.Hello)
+    Hello = 13,
+    // @@protoc_insertion_point(enum_value:MessageType.Heartbeat)
+    Heartbeat = 14,
+    // @@protoc_insertion_point(enum_value:MessageType.PreAccept)
+    PreAccept = 15,
+    // @@protoc_insertion_point(enum_value:MessageType.PreAcceptOk)
+    PreAcceptOk = 16,
+    // @@protoc_insertion_point(enum_value:MessageType.Accept)
+    Accept = 17,
+    // @@protoc_insertion_point(enum_value:MessageType.AcceptOk)
+    AcceptOk = 18,
+    // @@protoc_insertion_point(enum_value:MessageType.Commit)
+    Commit = 19,
+    // @@protoc_insertion_point(enum_value:MessageType.FastAny)
+    FastAny = 20,
+    // @@protoc_insertion_point(enum_value:MessageType.FastRequest)
+    FastRequest = 21,
+    // @@protoc_insertion_point(enum_value:MessageType.FastAccepted)
+    FastAccepted = 22,
 }
 
 impl ::protobuf::Enum for MessageType {
@@ -1186,6 +1494,20 @@ impl ::protobuf::Enum for MessageType {
             6 => ::std::option::Option::Some(MessageType::Decision),
             7 => ::std::option::Option::Some(MessageType::Request),
             8 => ::std::option::Option::Some(MessageType::Propose),
+            9 => ::std::option::Option::Some(MessageType::Failed),
+            10 => ::std::option::Option::Some(MessageType::StepDown),
+            11 => ::std::option::Option::Some(MessageType::Snapshot),
+            12 => ::std::option::Option::Some(MessageType::Response),
+            13 => ::std::option::Option::Some(MessageType::Hello),
+            14 => ::std::option::Option::Some(MessageType::Heartbeat),
+            15 => ::std::option::Option::Some(MessageType::PreAccept),
+            16 => ::std::option::Option::Some(MessageType::PreAcceptOk),
+            17 => ::std::option::Option::Some(MessageType::Accept),
+            18 => ::std::option::Option::Some(MessageType::AcceptOk),
+            19 => ::std::option::Option::Some(MessageType::Commit),
+            20 => ::std::option::Option::Some(MessageType::FastAny),
+            21 => ::std::option::Option::Some(MessageType::FastRequest),
+            22 => ::std::option::Option::Some(MessageType::FastAccepted),
             _ => ::std::option::Option::None
         }
     }
@@ -1200,6 +1522,20 @@ impl ::protobuf::Enum for MessageType {
         MessageType::Decision,
         MessageType::Request,
         MessageType::Propose,
+        MessageType::Failed,
+        MessageType::StepDown,
+        MessageType::Snapshot,
+        MessageType::Response,
+        MessageType::Hello,
+        MessageType::Heartbeat,
+        MessageType::PreAccept,
+        MessageType::PreAcceptOk,
+        MessageType::Accept,
+        MessageType::AcceptOk,
+        MessageType::Commit,
+        MessageType::FastAny,
+        MessageType::FastRequest,
+        MessageType::FastAccepted,
     ];
 }
 
@@ -1228,116 +1564,48 @@ impl MessageType {
 }
 
 static file_descriptor_proto_data: &'static [u8] = b"\
-    \n\x0bproto.proto\"M\n\x0bWireMessage\x12\x1a\n\x02to\x18\x01\x20\x01(\
+    \n\x0bproto.proto\"g\n\x0bWireMessage\x12\x1a\n\x02to\x18\x01\x20\x01(\
     \x0b2\n.ProcessIdR\x02to\x12\"\n\x07message\x18\x02\x20\x01(\x0b2\x08.Me\
-    ssageR\x07message\"\xd9\x02\n\x07Message\x12\x20\n\x04type\x18\x01\x20\
-    \x01(\x0e2\x0c.MessageTypeR\x04type\x12$\n\x07process\x18\x02\x20\x01(\
-    \x0b2\n.ProcessIdR\x07process\x12*\n\x06ballot\x18\x03\x20\x01(\x0b2\r.B\
-    allotNumberH\0R\x06ballot\x88\x01\x01\x12\x17\n\x04slot\x18\x04\x20\x01(\
-    \x04H\x01R\x04slot\x88\x01\x01\x12'\n\x07command\x18\x05\x20\x01(\x0b2\
-    \x08.CommandH\x02R\x07command\x88\x01\x01\x122\n\x08accepted\x18\x06\x20\
-    \x03(\x0b2\x16.Message.AcceptedEntryR\x08accepted\x1aD\n\rAcceptedEntry\
-    \x12\x10\n\x03key\x18\x01\x20\x01(\x04R\x03key\x12\x1d\n\x05value\x18\
-    \x02\x20\x01(\x0b2\x07.PValueR\x05value:\x028\x01B\t\n\x07_ballotB\x07\n\
-    \x05_slotB\n\n\x08_command\"Y\n\tProcessId\x12\x10\n\x02v4\x18\x01\x20\
-    \x01(\x07H\0R\x02v4\x12\x10\n\x02v6\x18\x02\x20\x01(\tH\0R\x02v6\x12\x12\
-    \n\x04port\x18\x03\x20\x01(\rR\x04port\x12\x0e\n\x02id\x18\x04\x20\x01(\
-    \rR\x02idB\x04\n\x02ip\"O\n\x0cBallotNumber\x12\x14\n\x05round\x18\x01\
-    \x20\x01(\x04R\x05round\x12)\n\nprocess_id\x18\x02\x20\x01(\x0b2\n.Proce\
-    ssIdR\tprocessId\"b\n\x07Command\x12\"\n\x06client\x18\x01\x20\x01(\x0b2\
-    \n.ProcessIdR\x06client\x12\x15\n\x06req_id\x18\x02\x20\x01(\x0cR\x05req\
-    Id\x12\x1c\n\toperation\x18\x03\x20\x01(\x0cR\toperation\"g\n\x06PValue\
-    \x12%\n\x06ballot\x18\x01\x20\x01(\x0b2\r.BallotNumberR\x06ballot\x12\
-    \x12\n\x04slot\x18\x02\x20\x01(\x04R\x04slot\x12\"\n\x07command\x18\x03\
-    \x20\x01(\x0b2\x08.CommandR\x07command*q\n\x0bMessageType\x12\x07\n\x03P\
-    1A\x10\0\x12\x07\n\x03P1B\x10\x01\x12\x07\n\x03P2A\x10\x02\x12\x07\n\x03\
-    P2B\x10\x03\x12\x0b\n\x07Preempt\x10\x04\x12\t\n\x05Adopt\x10\x05\x12\
-    \x0c\n\x08Decision\x10\x06\x12\x0b\n\x07Request\x10\x07\x12\x0b\n\x07Pro\
-    pose\x10\x08J\xbd\r\n\x06\x12\x04\0\04\x01\n\x08\n\x01\x0c\x12\x03\0\0\
-    \x12\n\n\n\x02\x05\0\x12\x04\x02\0\x0c\x01\n\n\n\x03\x05\0\x01\x12\x03\
-    \x02\x05\x10\n\x0b\n\x04\x05\0\x02\0\x12\x03\x03\x02\n\n\x0c\n\x05\x05\0\
-    \x02\0\x01\x12\x03\x03\x02\x05\n\x0c\n\x05\x05\0\x02\0\x02\x12\x03\x03\
-    \x08\t\n\x0b\n\x04\x05\0\x02\x01\x12\x03\x04\x02\n\n\x0c\n\x05\x05\0\x02\
-    \x01\x01\x12\x03\x04\x02\x05\n\x0c\n\x05\x05\0\x02\x01\x02\x12\x03\x04\
-    \x08\t\n\x0b\n\x04\x05\0\x02\x02\x12\x03\x05\x02\n\n\x0c\n\x05\x05\0\x02\
-    \x02\x01\x12\x03\x05\x02\x05\n\x0c\n\x05\x05\0\x02\x02\x02\x12\x03\x05\
-    \x08\t\n\x0b\n\x04\x05\0\x02\x03\x12\x03\x06\x02\n\n\x0c\n\x05\x05\0\x02\
-    \x03\x01\x12\x03\x06\x02\x05\n\x0c\n\x05\x05\0\x02\x03\x02\x12\x03\x06\
-    \x08\t\n\x0b\n\x04\x05\0\x02\x04\x12\x03\x07\x02\x0e\n\x0c\n\x05\x05\0\
-    \x02\x04\x01\x12\x03\x07\x02\t\n\x0c\n\x05\x05\0\x02\x04\x02\x12\x03\x07\
-    \x0c\r\n\x0b\n\x04\x05\0\x02\x05\x12\x03\x08\x02\x0c\n\x0c\n\x05\x05\0\
-    \x02\x05\x01\x12\x03\x08\x02\x07\n\x0c\n\x05\x05\0\x02\x05\x02\x12\x03\
-    \x08\n\x0b\n\x0b\n\x04\x05\0\x02\x06\x12\x03\t\x02\x0f\n\x0c\n\x05\x05\0\
-    \x02\x06\x01\x12\x03\t\x02\n\n\x0c\n\x05\x05\0\x02\x06\x02\x12\x03\t\r\
-    \x0e\n\x0b\n\x04\x05\0\x02\x07\x12\x03\n\x02\x0e\n\x0c\n\x05\x05\0\x02\
-    \x07\x01\x12\x03\n\x02\t\n\x0c\n\x05\x05\0\x02\x07\x02\x12\x03\n\x0c\r\n\
-    \x0b\n\x04\x05\0\x02\x08\x12\x03\x0b\x02\x0e\n\x0c\n\x05\x05\0\x02\x08\
-    \x01\x12\x03\x0b\x02\t\n\x0c\n\x05\x05\0\x02\x08\x02\x12\x03\x0b\x0c\r\n\
-    \n\n\x02\x04\0\x12\x04\x0e\0\x11\x01\n\n\n\x03\x04\0\x01\x12\x03\x0e\x08\
-    \x13\n\x0b\n\x04\x04\0\x02\0\x12\x03\x0f\x02\x13\n\x0c\n\x05\x04\0\x02\0\
-    \x06\x12\x03\x0f\x02\x0b\n\x0c\n\x05\x04\0\x02\0\x01\x12\x03\x0f\x0c\x0e\
-    \n\x0c\n\x05\x04\0\x02\0\x03\x12\x03\x0f\x11\x12\n\x0b\n\x04\x04\0\x02\
-    \x01\x12\x03\x10\x02\x16\n\x0c\n\x05\x04\0\x02\x01\x06\x12\x03\x10\x02\t\
-    \n\x0c\n\x05\x04\0\x02\x01\x01\x12\x03\x10\n\x11\n\x0c\n\x05\x04\0\x02\
-    \x01\x03\x12\x03\x10\x14\x15\n\n\n\x02\x04\x01\x12\x04\x13\0\x1a\x01\n\n\
-    \n\x03\x04\x01\x01\x12\x03\x13\x08\x0f\n\x0b\n\x04\x04\x01\x02\0\x12\x03\
-    \x14\x02\x17\n\x0c\n\x05\x04\x01\x02\0\x06\x12\x03\x14\x02\r\n\x0c\n\x05\
-    \x04\x01\x02\0\x01\x12\x03\x14\x0e\x12\n\x0c\n\x05\x04\x01\x02\0\x03\x12\
-    \x03\x14\x15\x16\n\x0b\n\x04\x04\x01\x02\x01\x12\x03\x15\x02\x18\n\x0c\n\
-    \x05\x04\x01\x02\x01\x06\x12\x03\x15\x02\x0b\n\x0c\n\x05\x04\x01\x02\x01\
-    \x01\x12\x03\x15\x0c\x13\n\x0c\n\x05\x04\x01\x02\x01\x03\x12\x03\x15\x16\
-    \x17\n\x0b\n\x04\x04\x01\x02\x02\x12\x03\x16\x02#\n\x0c\n\x05\x04\x01\
-    \x02\x02\x04\x12\x03\x16\x02\n\n\x0c\n\x05\x04\x01\x02\x02\x06\x12\x03\
-    \x16\x0b\x17\n\x0c\n\x05\x04\x01\x02\x02\x01\x12\x03\x16\x18\x1e\n\x0c\n\
-    \x05\x04\x01\x02\x02\x03\x12\x03\x16!\"\n\x0b\n\x04\x04\x01\x02\x03\x12\
-    \x03\x17\x02\x1b\n\x0c\n\x05\x04\x01\x02\x03\x04\x12\x03\x17\x02\n\n\x0c\
-    \n\x05\x04\x01\x02\x03\x05\x12\x03\x17\x0b\x11\n\x0c\n\x05\x04\x01\x02\
-    \x03\x01\x12\x03\x17\x12\x16\n\x0c\n\x05\x04\x01\x02\x03\x03\x12\x03\x17\
-    \x19\x1a\n\x0b\n\x04\x04\x01\x02\x04\x12\x03\x18\x02\x1f\n\x0c\n\x05\x04\
-    \x01\x02\x04\x04\x12\x03\x18\x02\n\n\x0c\n\x05\x04\x01\x02\x04\x06\x12\
-    \x03\x18\x0b\x12\n\x0c\n\x05\x04\x01\x02\x04\x01\x12\x03\x18\x13\x1a\n\
-    \x0c\n\x05\x04\x01\x02\x04\x03\x12\x03\x18\x1d\x1e\n\x0b\n\x04\x04\x01\
-    \x02\x05\x12\x03\x19\x02#\n\x0c\n\x05\x04\x01\x02\x05\x06\x12\x03\x19\
-    \x02\x15\n\x0c\n\x05\x04\x01\x02\x05\x01\x12\x03\x19\x16\x1e\n\x0c\n\x05\
-    \x04\x01\x02\x05\x03\x12\x03\x19!\"\n\n\n\x02\x04\x02\x12\x04\x1c\0#\x01\
-    \n\n\n\x03\x04\x02\x01\x12\x03\x1c\x08\x11\n\x0c\n\x04\x04\x02\x08\0\x12\
-    \x04\x1d\x02\x20\x03\n\x0c\n\x05\x04\x02\x08\0\x01\x12\x03\x1d\x08\n\n\
-    \x0b\n\x04\x04\x02\x02\0\x12\x03\x1e\x04\x13\n\x0c\n\x05\x04\x02\x02\0\
-    \x05\x12\x03\x1e\x04\x0b\n\x0c\n\x05\x04\x02\x02\0\x01\x12\x03\x1e\x0c\
-    \x0e\n\x0c\n\x05\x04\x02\x02\0\x03\x12\x03\x1e\x11\x12\n\x0b\n\x04\x04\
-    \x02\x02\x01\x12\x03\x1f\x04\x12\n\x0c\n\x05\x04\x02\x02\x01\x05\x12\x03\
-    \x1f\x04\n\n\x0c\n\x05\x04\x02\x02\x01\x01\x12\x03\x1f\x0b\r\n\x0c\n\x05\
-    \x04\x02\x02\x01\x03\x12\x03\x1f\x10\x11\n\x0b\n\x04\x04\x02\x02\x02\x12\
-    \x03!\x02\x12\n\x0c\n\x05\x04\x02\x02\x02\x05\x12\x03!\x02\x08\n\x0c\n\
-    \x05\x04\x02\x02\x02\x01\x12\x03!\t\r\n\x0c\n\x05\x04\x02\x02\x02\x03\
-    \x12\x03!\x10\x11\n\x0b\n\x04\x04\x02\x02\x03\x12\x03\"\x02\x10\n\x0c\n\
-    \x05\x04\x02\x02\x03\x05\x12\x03\"\x02\x08\n\x0c\n\x05\x04\x02\x02\x03\
-    \x01\x12\x03\"\t\x0b\n\x0c\n\x05\x04\x02\x02\x03\x03\x12\x03\"\x0e\x0f\n\
-    \n\n\x02\x04\x03\x12\x04%\0(\x01\n\n\n\x03\x04\x03\x01\x12\x03%\x08\x14\
-    \n\x0b\n\x04\x04\x03\x02\0\x12\x03&\x02\x13\n\x0c\n\x05\x04\x03\x02\0\
-    \x05\x12\x03&\x02\x08\n\x0c\n\x05\x04\x03\x02\0\x01\x12\x03&\t\x0e\n\x0c\
-    \n\x05\x04\x03\x02\0\x03\x12\x03&\x11\x12\n\x0b\n\x04\x04\x03\x02\x01\
-    \x12\x03'\x02\x1b\n\x0c\n\x05\x04\x03\x02\x01\x06\x12\x03'\x02\x0b\n\x0c\
-    \n\x05\x04\x03\x02\x01\x01\x12\x03'\x0c\x16\n\x0c\n\x05\x04\x03\x02\x01\
-    \x03\x12\x03'\x19\x1a\n\n\n\x02\x04\x04\x12\x04*\0.\x01\n\n\n\x03\x04\
-    \x04\x01\x12\x03*\x08\x0f\n\x0b\n\x04\x04\x04\x02\0\x12\x03+\x02\x17\n\
-    \x0c\n\x05\x04\x04\x02\0\x06\x12\x03+\x02\x0b\n\x0c\n\x05\x04\x04\x02\0\
-    \x01\x12\x03+\x0c\x12\n\x0c\n\x05\x04\x04\x02\0\x03\x12\x03+\x15\x16\n\
-    \x0b\n\x04\x04\x04\x02\x01\x12\x03,\x02\x13\n\x0c\n\x05\x04\x04\x02\x01\
-    \x05\x12\x03,\x02\x07\n\x0c\n\x05\x04\x04\x02\x01\x01\x12\x03,\x08\x0e\n\
-    \x0c\n\x05\x04\x04\x02\x01\x03\x12\x03,\x11\x12\n\x0b\n\x04\x04\x04\x02\
-    \x02\x12\x03-\x02\x16\n\x0c\n\x05\x04\x04\x02\x02\x05\x12\x03-\x02\x07\n\
-    \x0c\n\x05\x04\x04\x02\x02\x01\x12\x03-\x08\x11\n\x0c\n\x05\x04\x04\x02\
-    \x02\x03\x12\x03-\x14\x15\n\n\n\x02\x04\x05\x12\x040\04\x01\n\n\n\x03\
-    \x04\x05\x01\x12\x030\x08\x0e\n\x0b\n\x04\x04\x05\x02\0\x12\x031\x02\x1a\
-    \n\x0c\n\x05\x04\x05\x02\0\x06\x12\x031\x02\x0e\n\x0c\n\x05\x04\x05\x02\
-    \0\x01\x12\x031\x0f\x15\n\x0c\n\x05\x04\x05\x02\0\x03\x12\x031\x18\x19\n\
-    \x0b\n\x04\x04\x05\x02\x01\x12\x032\x02\x12\n\x0c\n\x05\x04\x05\x02\x01\
-    \x05\x12\x032\x02\x08\n\x0c\n\x05\x04\x05\x02\x01\x01\x12\x032\t\r\n\x0c\
-    \n\x05\x04\x05\x02\x01\x03\x12\x032\x10\x11\n\x0b\n\x04\x04\x05\x02\x02\
-    \x12\x033\x02\x16\n\x0c\n\x05\x04\x05\x02\x02\x06\x12\x033\x02\t\n\x0c\n\
-    \x05\x04\x05\x02\x02\x01\x12\x033\n\x11\n\x0c\n\x05\x04\x05\x02\x02\x03\
-    \x12\x033\x14\x15b\x06proto3\
+    ssageR\x07message\x12\x18\n\x07version\x18\x03\x20\x01(\rR\x07version\"\
+    \xe1\x04\n\x07Message\x12\x20\n\x04type\x18\x01\x20\x01(\x0e2\x0c.Messag\
+    eTypeR\x04type\x12$\n\x07process\x18\x02\x20\x01(\x0b2\n.ProcessIdR\x07p\
+    rocess\x12*\n\x06ballot\x18\x03\x20\x01(\x0b2\r.BallotNumberH\0R\x06ball\
+    ot\x88\x01\x01\x12\x17\n\x04slot\x18\x04\x20\x01(\x04H\x01R\x04slot\x88\
+    \x01\x01\x12'\n\x07command\x18\x05\x20\x01(\x0b2\x08.CommandH\x02R\x07co\
+    mmand\x88\x01\x01\x122\n\x08accepted\x18\x06\x20\x03(\x0b2\x16.Message.A\
+    cceptedEntryR\x08accepted\x12\x1b\n\x06result\x18\x07\x20\x01(\x0cH\x03R\
+    \x06result\x88\x01\x01\x12\x17\n\x04last\x18\x08\x20\x01(\x08H\x04R\x04l\
+    ast\x88\x01\x01\x12*\n\x08instance\x18\t\x20\x01(\x0b2\t.InstanceH\x05R\
+    \x08instance\x88\x01\x01\x12\x15\n\x03seq\x18\n\x20\x01(\x04H\x06R\x03se\
+    q\x88\x01\x01\x12\x1d\n\x04deps\x18\x0b\x20\x03(\x0b2\t.InstanceR\x04dep\
+    s\x12\x17\n\x04page\x18\x0c\x20\x01(\x04H\x07R\x04page\x88\x01\x01\x12\
+    \x19\n\x05pages\x18\r\x20\x01(\x04H\x08R\x05pages\x88\x01\x01\x1aD\n\rAc\
+    ceptedEntry\x12\x10\n\x03key\x18\x01\x20\x01(\x04R\x03key\x12\x1d\n\x05v\
+    alue\x18\x02\x20\x01(\x0b2\x07.PValueR\x05value:\x028\x01B\t\n\x07_ballo\
+    tB\x07\n\x05_slotB\n\n\x08_commandB\t\n\x07_resultB\x07\n\x05_lastB\x0b\
+    \n\t_instanceB\x06\n\x04_seqB\x07\n\x05_pageB\x08\n\x06_pages\"Y\n\tProc\
+    essId\x12\x10\n\x02v4\x18\x01\x20\x01(\x07H\0R\x02v4\x12\x10\n\x02v6\x18\
+    \x02\x20\x01(\tH\0R\x02v6\x12\x12\n\x04port\x18\x03\x20\x01(\rR\x04port\
+    \x12\x0e\n\x02id\x18\x04\x20\x01(\rR\x02idB\x04\n\x02ip\"H\n\x08Instance\
+    \x12$\n\x07replica\x18\x01\x20\x01(\x0b2\n.ProcessIdR\x07replica\x12\x16\
+    \n\x06number\x18\x02\x20\x01(\x04R\x06number\"O\n\x0cBallotNumber\x12\
+    \x14\n\x05round\x18\x01\x20\x01(\x04R\x05round\x12)\n\nprocess_id\x18\
+    \x02\x20\x01(\x0b2\n.ProcessIdR\tprocessId\"b\n\x07Command\x12\"\n\x06cl\
+    ient\x18\x01\x20\x01(\x0b2\n.ProcessIdR\x06client\x12\x15\n\x06req_id\
+    \x18\x02\x20\x01(\x0cR\x05reqId\x12\x1c\n\toperation\x18\x03\x20\x01(\
+    \x0cR\toperation\"g\n\x06PValue\x12%\n\x06ballot\x18\x01\x20\x01(\x0b2\r\
+    .BallotNumberR\x06ballot\x12\x12\n\x04slot\x18\x02\x20\x01(\x04R\x04slot\
+    \x12\"\n\x07command\x18\x03\x20\x01(\x0b2\x08.CommandR\x07command*\xb7\
+    \x02\n\x0bMessageType\x12\x07\n\x03P1A\x10\0\x12\x07\n\x03P1B\x10\x01\
+    \x12\x07\n\x03P2A\x10\x02\x12\x07\n\x03P2B\x10\x03\x12\x0b\n\x07Preempt\
+    \x10\x04\x12\t\n\x05Adopt\x10\x05\x12\x0c\n\x08Decision\x10\x06\x12\x0b\
+    \n\x07Request\x10\x07\x12\x0b\n\x07Propose\x10\x08\x12\n\n\x06Failed\x10\
+    \t\x12\x0c\n\x08StepDown\x10\n\x12\x0c\n\x08Snapshot\x10\x0b\x12\x0c\n\
+    \x08Response\x10\x0c\x12\t\n\x05Hello\x10\r\x12\r\n\tHeartbeat\x10\x0e\
+    \x12\r\n\tPreAccept\x10\x0f\x12\x0f\n\x0bPreAcceptOk\x10\x10\x12\n\n\x06\
+    Accept\x10\x11\x12\x0c\n\x08AcceptOk\x10\x12\x12\n\n\x06Commit\x10\x13\
+    \x12\x0b\n\x07FastAny\x10\x14\x12\x0f\n\x0bFastRequest\x10\x15\x12\x10\n\
+    \x0cFastAccepted\x10\x16b\x06proto3\
 ";
 
 /// `FileDescriptorProto` object which was a source for this generated file
@@ -1355,10 +1623,11 @@ pub fn file_descriptor() -> &'static ::protobuf::reflect::FileDescriptor {
     file_descriptor.get(|| {
         let generated_file_descriptor = generated_file_descriptor_lazy.get(|| {
             let mut deps = ::std::vec::Vec::with_capacity(0);
-            let mut messages = ::std::vec::Vec::with_capacity(6);
+            let mut messages = ::std::vec::Vec::with_capacity(7);
             messages.push(WireMessage::generated_message_descriptor_data());
             messages.push(Message::generated_message_descriptor_data());
             messages.push(ProcessId::generated_message_descriptor_data());
+            messages.push(Instance::generated_message_descriptor_data());
             messages.push(BallotNumber::generated_message_descriptor_data());
             messages.push(Command::generated_message_descriptor_data());
             messages.push(PValue::generated_message_descriptor_data());
@@ -1,5 +1,6 @@
 use super::env::ProcessId;
 use bytes::Bytes;
+use std::collections::BTreeSet;
 use std::fmt::Display;
 use std::str;
 
@@ -59,6 +60,9 @@ pub struct Command {
     pub operation: Bytes,
 }
 
+/// The req_id of a no-op, clients never send it, see `Command::noop`.
+pub static NOOP: &[u8] = b"\0noop";
+
 impl Command {
     pub fn new_from_str(id: ProcessId, req_id: String, op: String) -> Command {
         Command {
@@ -68,6 +72,20 @@ impl Command {
         }
     }
 
+    /// A command that only occupies a slot. Leaders use it to fill holes in the
+    /// log after a leader change, replicas skip it without applying anything.
+    pub fn noop(client: ProcessId) -> Command {
+        Command {
+            client,
+            req_id: Bytes::from_static(NOOP),
+            operation: Bytes::new(),
+        }
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.req_id == NOOP
+    }
+
     pub fn req_id_str(&self) -> &str {
         match str::from_utf8(&self.req_id) {
             Ok(v) => v,
@@ -94,3 +112,26 @@ impl Display for Command {
         )
     }
 }
+
+/// An EPaxos instance, numbered by the replica that leads it.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+pub struct Instance {
+    pub replica: ProcessId,
+    pub number: u64,
+}
+
+impl Instance {
+    pub fn new(replica: ProcessId, number: u64) -> Instance {
+        Instance { replica, number }
+    }
+}
+
+impl Display for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Instance({}, {})", self.replica, self.number)
+    }
+}
+
+/// The instances a command executes after. Instances that depend on each
+/// other are executed in `seq` order.
+pub type Deps = BTreeSet<Instance>;
@@ -0,0 +1,319 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use super::env::ProcessId;
+
+#[derive(Debug, PartialEq)]
+pub enum QuorumErr {
+    NoAcceptors,
+    Empty,
+    TooLarge,
+    GridShape,
+    NoIntersection,
+}
+
+impl Display for QuorumErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuorumErr::NoAcceptors => write!(f, "no acceptors"),
+            QuorumErr::Empty => write!(f, "a quorum is empty"),
+            QuorumErr::TooLarge => write!(f, "a quorum is larger than the acceptors"),
+            QuorumErr::GridShape => write!(f, "the grid does not fit the acceptors"),
+            QuorumErr::NoIntersection => write!(f, "phase 1 and phase 2 quorums do not intersect"),
+        }
+    }
+}
+
+/// Decides which sets of acceptors form a quorum in phase 1 (Scout) and in
+/// phase 2 (Commander). Following Flexible Paxos, the two phases can use
+/// different quorums as long as every phase 1 quorum intersects every phase 2
+/// quorum, which is what `validate` checks.
+///
+/// The acceptors a leader counts on are the ones of the cluster followed by
+/// its witnesses, a `Grid` or `Weighted` system is laid out over both.
+#[derive(Clone, Debug)]
+pub enum QuorumSystem {
+    /// More than half of the acceptors in both phases.
+    Majority,
+    /// Explicit number of acceptors needed in each phase.
+    Flexible { phase1: usize, phase2: usize },
+    /// Acceptors laid out row by row, phase 1 needs a full row and phase 2 a
+    /// full column.
+    Grid { rows: usize, cols: usize },
+    /// Votes per acceptor (1 when not listed) and the votes needed in each phase.
+    Weighted {
+        weights: HashMap<ProcessId, u64>,
+        phase1: u64,
+        phase2: u64,
+    },
+}
+
+impl QuorumSystem {
+    pub fn validate(&self, acceptors: &[ProcessId]) -> Result<(), QuorumErr> {
+        let n = acceptors.len();
+        if n == 0 {
+            return Err(QuorumErr::NoAcceptors);
+        }
+
+        match self {
+            QuorumSystem::Majority => Ok(()),
+            QuorumSystem::Flexible { phase1, phase2 } => {
+                Self::check_sizes(*phase1 as u64, *phase2 as u64, n as u64)
+            }
+            QuorumSystem::Grid { rows, cols } => {
+                // any row and any column share exactly one acceptor
+                if rows * cols != n {
+                    return Err(QuorumErr::GridShape);
+                }
+                Ok(())
+            }
+            QuorumSystem::Weighted {
+                weights: _,
+                phase1,
+                phase2,
+            } => Self::check_sizes(*phase1, *phase2, self.votes(acceptors.iter())),
+        }
+    }
+
+    fn check_sizes(phase1: u64, phase2: u64, total: u64) -> Result<(), QuorumErr> {
+        if phase1 == 0 || phase2 == 0 {
+            return Err(QuorumErr::Empty);
+        }
+        if phase1 > total || phase2 > total {
+            return Err(QuorumErr::TooLarge);
+        }
+        if phase1 + phase2 <= total {
+            return Err(QuorumErr::NoIntersection);
+        }
+        Ok(())
+    }
+
+    pub fn is_phase1(&self, acceptors: &[ProcessId], acked: &HashSet<ProcessId>) -> bool {
+        match self {
+            QuorumSystem::Majority => 2 * Self::count(acceptors, acked) > acceptors.len(),
+            QuorumSystem::Flexible { phase1, phase2: _ } => {
+                Self::count(acceptors, acked) >= *phase1
+            }
+            QuorumSystem::Grid { rows, cols } => {
+                let acked = |r, c| {
+                    acceptors
+                        .get(r * cols + c)
+                        .map_or(false, |a| acked.contains(a))
+                };
+                (0..*rows).any(|r| (0..*cols).all(|c| acked(r, c)))
+            }
+            QuorumSystem::Weighted {
+                weights: _,
+                phase1,
+                phase2: _,
+            } => self.acked_votes(acceptors, acked) >= *phase1,
+        }
+    }
+
+    pub fn is_phase2(&self, acceptors: &[ProcessId], acked: &HashSet<ProcessId>) -> bool {
+        match self {
+            QuorumSystem::Majority => 2 * Self::count(acceptors, acked) > acceptors.len(),
+            QuorumSystem::Flexible { phase1: _, phase2 } => {
+                Self::count(acceptors, acked) >= *phase2
+            }
+            QuorumSystem::Grid { rows, cols } => {
+                let acked = |r, c| {
+                    acceptors
+                        .get(r * cols + c)
+                        .map_or(false, |a| acked.contains(a))
+                };
+                (0..*cols).any(|c| (0..*rows).all(|r| acked(r, c)))
+            }
+            QuorumSystem::Weighted {
+                weights: _,
+                phase1: _,
+                phase2,
+            } => self.acked_votes(acceptors, acked) >= *phase2,
+        }
+    }
+
+    /// The smallest prefix of `preferred` that forms a phase 2 quorum, or all
+    /// of `preferred` if it never does.
+    pub fn pick_phase2(
+        &self,
+        acceptors: &[ProcessId],
+        preferred: &[ProcessId],
+    ) -> HashSet<ProcessId> {
+        let mut res = HashSet::new();
+        for a in preferred.iter() {
+            if self.is_phase2(acceptors, &res) {
+                break;
+            }
+            res.insert(a.clone());
+        }
+        res
+    }
+
+    /// How many acceptors must accept the same value in a fast round for it
+    /// to be chosen: enough that any two fast quorums and a phase 1 quorum
+    /// intersect, so a phase 1 can tell which value may have been chosen.
+    /// `None` for the quorum systems that do not count acceptors.
+    pub fn fast(&self, acceptors: &[ProcessId]) -> Option<usize> {
+        let n = acceptors.len();
+        let phase1 = match self {
+            QuorumSystem::Majority => n / 2 + 1,
+            QuorumSystem::Flexible { phase1, phase2: _ } => *phase1,
+            _ => return None,
+        };
+        Some((2 * n - phase1.min(2 * n)) / 2 + 1)
+    }
+
+    fn count(acceptors: &[ProcessId], acked: &HashSet<ProcessId>) -> usize {
+        acceptors.iter().filter(|a| acked.contains(a)).count()
+    }
+
+    fn acked_votes(&self, acceptors: &[ProcessId], acked: &HashSet<ProcessId>) -> u64 {
+        self.votes(acceptors.iter().filter(|a| acked.contains(a)))
+    }
+
+    fn votes<'a, I: Iterator<Item = &'a ProcessId>>(&self, ids: I) -> u64 {
+        match self {
+            QuorumSystem::Weighted {
+                weights,
+                phase1: _,
+                phase2: _,
+            } => ids.map(|a| *weights.get(a).unwrap_or(&1)).sum(),
+            _ => ids.count() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        net::{IpAddr, Ipv4Addr},
+    };
+
+    use crate::env::ProcessId;
+
+    use super::{QuorumErr, QuorumSystem};
+
+    fn acceptors(n: u32) -> Vec<ProcessId> {
+        let local_host = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        (0..n).map(|i| ProcessId::new(local_host, 0, i)).collect()
+    }
+
+    fn acked(a: &[ProcessId], idx: &[usize]) -> HashSet<ProcessId> {
+        idx.iter().map(|i| a[*i].clone()).collect()
+    }
+
+    #[test]
+    fn majority() {
+        let a = acceptors(5);
+        let q = QuorumSystem::Majority;
+        assert_eq!(q.validate(&a), Ok(()));
+        assert!(!q.is_phase1(&a, &acked(&a, &[0, 1])));
+        assert!(q.is_phase1(&a, &acked(&a, &[0, 1, 4])));
+        assert!(q.is_phase2(&a, &acked(&a, &[2, 3, 4])));
+        assert_eq!(q.fast(&a), Some(4));
+        assert_eq!(q.fast(&acceptors(3)), Some(3));
+    }
+
+    #[test]
+    fn flexible() {
+        let a = acceptors(6);
+        assert_eq!(
+            QuorumSystem::Flexible {
+                phase1: 3,
+                phase2: 3
+            }
+            .validate(&a),
+            Err(QuorumErr::NoIntersection)
+        );
+        assert_eq!(
+            QuorumSystem::Flexible {
+                phase1: 7,
+                phase2: 1
+            }
+            .validate(&a),
+            Err(QuorumErr::TooLarge)
+        );
+
+        let q = QuorumSystem::Flexible {
+            phase1: 5,
+            phase2: 2,
+        };
+        assert_eq!(q.validate(&a), Ok(()));
+        assert!(q.is_phase2(&a, &acked(&a, &[0, 5])));
+        assert!(!q.is_phase1(&a, &acked(&a, &[0, 1, 2, 3])));
+        assert!(q.is_phase1(&a, &acked(&a, &[0, 1, 2, 3, 4])));
+    }
+
+    #[test]
+    fn grid() {
+        let a = acceptors(6);
+        assert_eq!(
+            QuorumSystem::Grid { rows: 2, cols: 2 }.validate(&a),
+            Err(QuorumErr::GridShape)
+        );
+
+        // 0 1 2
+        // 3 4 5
+        let q = QuorumSystem::Grid { rows: 2, cols: 3 };
+        assert_eq!(q.validate(&a), Ok(()));
+        assert!(q.is_phase1(&a, &acked(&a, &[3, 4, 5])));
+        assert!(!q.is_phase1(&a, &acked(&a, &[0, 1, 5])));
+        assert!(q.is_phase2(&a, &acked(&a, &[1, 4])));
+        assert!(!q.is_phase2(&a, &acked(&a, &[1, 5])));
+
+        // a grid sized for fewer acceptors than there are never forms a
+        // quorum past them, rather than panic
+        let few = &a[..4];
+        assert!(!q.is_phase1(few, &acked(&a, &[3, 4, 5])));
+        assert!(!q.is_phase2(few, &acked(&a, &[2, 5])));
+    }
+
+    #[test]
+    fn weighted() {
+        let a = acceptors(3);
+        let mut weights = HashMap::new();
+        weights.insert(a[0].clone(), 3);
+
+        // total of 5 votes
+        assert_eq!(
+            QuorumSystem::Weighted {
+                weights: weights.clone(),
+                phase1: 2,
+                phase2: 3
+            }
+            .validate(&a),
+            Err(QuorumErr::NoIntersection)
+        );
+
+        let q = QuorumSystem::Weighted {
+            weights,
+            phase1: 3,
+            phase2: 3,
+        };
+        assert_eq!(q.validate(&a), Ok(()));
+        assert!(q.is_phase1(&a, &acked(&a, &[0])));
+        assert!(!q.is_phase2(&a, &acked(&a, &[1, 2])));
+    }
+
+    #[test]
+    fn pick_phase2() {
+        let a = acceptors(5);
+        let preferred: Vec<ProcessId> = [4, 2, 0, 1, 3].iter().map(|i| a[*i].clone()).collect();
+        assert_eq!(
+            QuorumSystem::Majority.pick_phase2(&a, &preferred),
+            acked(&a, &[4, 2, 0])
+        );
+
+        // 0 1 2
+        // 3 4 5
+        let a = acceptors(6);
+        let q = QuorumSystem::Grid { rows: 2, cols: 3 };
+        let preferred: Vec<ProcessId> = [1, 3, 4, 0].iter().map(|i| a[*i].clone()).collect();
+        assert_eq!(q.pick_phase2(&a, &preferred), acked(&a, &[1, 3, 4]));
+        // no column among the preferred, all of them
+        assert_eq!(q.pick_phase2(&a, &preferred[..2]), acked(&a, &[1, 3]));
+    }
+}
@@ -1,13 +1,22 @@
 use core::panic;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
 
-use log::{info, trace};
+use bytes::Bytes;
+use log::{info, trace, warn};
 
 use super::{
-    constants::SLEEP_TIME,
+    admin::{self, Snapshot, Status},
+    constants::{PROPOSE_TIMEOUT_MS, SLEEP_TIME},
     env::{Env, Executor, ProcessId, Receiver, Router},
+    leader,
     message::Message,
-    pval::{Command, SlotNumber},
+    metrics::{process, REGISTRY},
+    pval::{BallotNumber, Command, SlotNumber},
+    state::{KvStore, StateMachine},
+    trace::{self, Stage},
 };
 
 pub struct Replica {
@@ -15,27 +24,120 @@ pub struct Replica {
     slot: SlotNumber,
     proposals: HashMap<SlotNumber, Command>,
     decisions: HashMap<SlotNumber, Command>,
+    // the client and req_id of every command decided, and of those performed
+    decided: HashSet<(ProcessId, Bytes)>,
+    performed: HashSet<(ProcessId, Bytes)>,
+    state: Box<dyn StateMachine + Send>,
+    // last response sent to each client, resent when it retries the request
+    replies: HashMap<ProcessId, (Bytes, SlotNumber, Bytes)>,
+    // highest ballot seen in a decision, its leader is the one proposed to
+    leader: Option<BallotNumber>,
+    // the ballot of the last leader that did not decide a proposal in time,
+    // decisions it sends afterwards do not make it the leader again
+    timed_out: Option<BallotNumber>,
+    // proposals sent to that leader alone and not decided yet
+    sent: HashMap<SlotNumber, Instant>,
+    // propose to the owner of the slot, see `LeaderConfig::mencius`
+    mencius: bool,
+    // propose to the acceptors in the fast ballots, see `LeaderConfig::fast`
+    fast: bool,
 }
 
 impl Replica {
     pub fn new(id: ProcessId) -> Replica {
+        Replica::with_state(id, Box::new(KvStore::new()))
+    }
+
+    pub fn with_state(id: ProcessId, state: Box<dyn StateMachine + Send>) -> Replica {
         Replica {
             me: id,
             slot: 1,
             proposals: HashMap::new(),
             decisions: HashMap::new(),
+            decided: HashSet::new(),
+            performed: HashSet::new(),
+            state,
+            replies: HashMap::new(),
+            leader: None,
+            timed_out: None,
+            sent: HashMap::new(),
+            mencius: false,
+            fast: false,
+        }
+    }
+
+    /// Sends each proposal straight to the acceptors, for leaders running
+    /// fast rounds. Proposals that are not decided in time go to the leaders.
+    pub fn fast(mut self) -> Replica {
+        self.fast = true;
+        self
+    }
+
+    /// Sends each proposal to the leader owning its slot, for leaders in
+    /// Mencius mode.
+    pub fn mencius(mut self) -> Replica {
+        self.mencius = true;
+        self
+    }
+
+    fn broadcast<T: Router, E: Env<T>>(&self, m: Message, env: &E) {
+        for l in env.cluster().leaders().iter() {
+            env.router().send(l, m.clone());
+        }
+    }
+
+    /// Proposals the known leader did not get decided in time go to every
+    /// leader, and the next ones too until a decision names a leader again.
+    fn tick<T: Router, E: Env<T>>(&mut self, env: &E) {
+        let timeout = Duration::from_millis(PROPOSE_TIMEOUT_MS);
+        let late: Vec<SlotNumber> = self
+            .sent
+            .iter()
+            .filter(|(_, t)| t.elapsed() >= timeout)
+            .map(|(s, _)| *s)
+            .collect();
+        if late.is_empty() {
+            return;
+        }
+        if let Some(b) = self.leader.take() {
+            self.timed_out = Some(b);
+        }
+        for s in late {
+            self.sent.remove(&s);
+            if let Some(c) = self.proposals.get(&s) {
+                if !self.decisions.contains_key(&s) {
+                    self.broadcast(Message::Propose(self.me.clone(), s, c.clone()), env);
+                }
+            }
         }
     }
 
     pub fn propose<T: Router, E: Env<T>>(&mut self, c: Command, env: &E) {
-        if self.decisions.values().all(|com: &Command| c != *com) {
-            let mut i = 1;
+        if !self.decided.contains(&(c.client.clone(), c.req_id.clone())) {
+            // every slot below `slot` is decided
+            let mut i = self.slot;
             loop {
                 if !self.proposals.contains_key(&i) && !self.decisions.contains_key(&i) {
                     self.proposals.insert(i, c.clone());
-                    for l in env.cluster().leaders().iter() {
-                        env.router()
-                            .send(l, Message::Propose(self.me.clone(), i, c.clone()));
+                    if self.fast {
+                        let m = Message::FastRequest(self.me.clone(), i, c.clone());
+                        for a in env.cluster().acceptors().iter() {
+                            env.router().send(a, m.clone());
+                        }
+                        self.sent.insert(i, Instant::now());
+                        break;
+                    }
+                    let m = Message::Propose(self.me.clone(), i, c.clone());
+                    let to = match self.mencius {
+                        true => leader::owner(env.cluster().leaders(), i),
+                        false => self.leader.as_ref().map(|b| b.process_id.clone()),
+                    };
+                    match to {
+                        Some(l) => {
+                            env.router().send(&l, m);
+                            self.sent.insert(i, Instant::now());
+                        }
+                        None => self.broadcast(m, env),
                     }
                     break;
                 }
@@ -44,24 +146,67 @@ impl Replica {
         }
     }
 
-    pub fn perform(&mut self, c: Command) {
-        info!("Replica {} performed {}", self.me, c);
-        if self.decisions.values().any(|com: &Command| c == *com) {
-            self.slot += 1
+    pub fn perform<T: Router, E: Env<T>>(&mut self, c: Command, env: &E) {
+        // a command decided in more than one slot is only applied the first time
+        if c.is_noop() || !self.performed.insert((c.client.clone(), c.req_id.clone())) {
+            self.slot += 1;
+            return;
         }
+        info!("Replica {} performed {}", self.me, c);
+        trace::record(Stage::Perform, &self.me, &c, Some(self.slot));
+        let result = self.state.apply(self.slot, &c);
+        self.replies.insert(
+            c.client.clone(),
+            (c.req_id.clone(), self.slot, result.clone()),
+        );
+        let client = c.client.clone();
+        env.router().send(
+            &client,
+            Message::Response(self.me.clone(), self.slot, c, result),
+        );
+        self.slot += 1;
     }
 }
 
 impl Executor for Replica {
     fn exec<R: Receiver, T: Router, E: Env<T>>(mut self, reciever: R, env: &E) {
         loop {
-            let msg = reciever.get(SLEEP_TIME);
+            self.tick(env);
+            let wait = Duration::from_millis(PROPOSE_TIMEOUT_MS / 4);
+            let msg = match reciever.get_timeout(SLEEP_TIME, wait) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
 
             match msg {
+                Message::Request(_, command) if command.is_noop() => {
+                    warn!(
+                        "Replica {} ignored a request with the no-op req_id",
+                        self.me
+                    );
+                }
                 Message::Request(_, command) => {
-                    self.propose(command, env);
+                    trace::record(Stage::Request, &self.me, &command, None);
+                    match self.replies.get(&command.client) {
+                        Some((req_id, slot, result)) if *req_id == command.req_id => {
+                            let m = Message::Response(
+                                self.me.clone(),
+                                *slot,
+                                command.clone(),
+                                result.clone(),
+                            );
+                            env.router().send(&command.client, m);
+                        }
+                        _ => self.propose(command, env),
+                    }
                 }
-                Message::Decision(_, slot, command) => {
+                Message::Decision(_, slot, command, ballot) => {
+                    if ballot > self.leader && ballot > self.timed_out {
+                        self.leader = ballot;
+                    }
+                    self.sent.remove(&slot);
+                    self.decided
+                        .insert((command.client.clone(), command.req_id.clone()));
                     self.decisions.insert(slot, command);
                     loop {
                         match self.decisions.get(&self.slot) {
@@ -75,14 +220,110 @@ impl Executor for Replica {
                                     }
                                     None => {}
                                 }
-                                self.perform(cclone);
+                                self.perform(cclone, env);
                             }
                             None => break,
                         }
                     }
+                    REGISTRY.set(
+                        "paxos_replica_slot",
+                        &[("process", &process(&self.me))],
+                        self.slot as f64,
+                    );
+                }
+                Message::Snapshot(_) => {
+                    let log = (1..self.slot)
+                        .map(|s| self.decisions.get(&s).unwrap().clone())
+                        .collect();
+                    admin::store_snapshot(
+                        &self.me,
+                        Snapshot {
+                            slot: self.slot,
+                            log,
+                        },
+                    );
                 }
                 _ => panic!("unexpected"),
             }
+            admin::publish(
+                &self.me,
+                Status::Replica {
+                    slot: self.slot,
+                    // every slot below `slot` is decided
+                    backlog: self.decisions.len() + 1 - self.slot as usize,
+                },
+            );
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crossbeam::channel;
+
+    use crate::{
+        constants::PROPOSE_TIMEOUT_MS,
+        env::{Env, ProcessType, Router},
+        fixture,
+        message::Message,
+        pval::{BallotNumber, Command, SlotNumber},
+    };
+
+    use super::Replica;
+
+    /// Waits up to `wait` for a proposal in `slot`.
+    fn proposed(r: &channel::Receiver<Message>, slot: SlotNumber, wait: Duration) -> bool {
+        while let Ok(m) = r.recv_timeout(wait) {
+            if let Message::Propose(_, s, _) = m {
+                if s == slot {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn silent_leader_times_out() {
+        let env = fixture::env();
+        let (a, ra) = fixture::probe(env, ProcessType::Leader);
+        let (b, rb) = fixture::probe(env, ProcessType::Leader);
+        let me = fixture::new_id(env);
+        env.register(me.clone(), ProcessType::Replica, Replica::new(me.clone()));
+        let client = fixture::new_id(env);
+        let c = |i: u64| {
+            Command::new_from_str(client.clone(), format!("{}", i), format!("PUT k {}", i))
+        };
+        let request = |i: u64| {
+            env.router()
+                .send(&me, Message::Request(client.clone(), c(i)))
+        };
+        let decide = |from, slot, ballot| {
+            let m = Message::Decision(from, slot, c(slot), Some(ballot));
+            env.router().send(&me, m)
+        };
+        let quick = Duration::from_millis(PROPOSE_TIMEOUT_MS / 4);
+        let late = Duration::from_millis(PROPOSE_TIMEOUT_MS * 2);
+
+        // a decision names `a` as the leader, which then stays silent
+        let old = BallotNumber::new(1, a.clone());
+        decide(a.clone(), 1, old.clone());
+        request(2);
+        assert!(proposed(&ra, 2, quick));
+        assert!(!proposed(&rb, 2, quick));
+        assert!(proposed(&rb, 2, late));
+
+        // a late decision from `a` does not make it the leader again
+        decide(a.clone(), 2, old);
+        request(3);
+        assert!(proposed(&rb, 3, quick));
+
+        // a higher ballot does
+        decide(b.clone(), 3, BallotNumber::new(2, b.clone()));
+        request(4);
+        assert!(proposed(&rb, 4, quick));
+        assert!(!proposed(&ra, 4, quick));
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+
+use super::pval::{Command, SlotNumber};
+
+/// What a replica applies decided commands to, in slot order and exactly once
+/// per command. The returned bytes are sent back to the client.
+pub trait StateMachine {
+    fn apply(&mut self, slot: SlotNumber, c: &Command) -> Bytes;
+}
+
+/// An operation on `KvStore`, written on the wire as `GET key`, `PUT key value`
+/// or `DEL key`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Op {
+    Get(String),
+    Put(String, Bytes),
+    Del(String),
+}
+
+impl Op {
+    pub fn parse(b: &[u8]) -> Option<Op> {
+        let s = String::from_utf8_lossy(b);
+        let mut parts = s.trim().splitn(3, ' ');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(op), Some(k), None) if op.eq_ignore_ascii_case("GET") => {
+                Some(Op::Get(k.to_string()))
+            }
+            (Some(op), Some(k), None) if op.eq_ignore_ascii_case("DEL") => {
+                Some(Op::Del(k.to_string()))
+            }
+            (Some(op), Some(k), Some(v)) if op.eq_ignore_ascii_case("PUT") => {
+                Some(Op::Put(k.to_string(), Bytes::from(v.to_string())))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self) -> Bytes {
+        match self {
+            Op::Get(k) => Bytes::from(format!("GET {}", k)),
+            Op::Put(k, v) => Bytes::from(format!("PUT {} {}", k, String::from_utf8_lossy(v))),
+            Op::Del(k) => Bytes::from(format!("DEL {}", k)),
+        }
+    }
+}
+
+pub static OK: &[u8] = b"OK";
+pub static NOT_FOUND: &[u8] = b"NOT_FOUND";
+pub static BAD_OP: &[u8] = b"ERR bad operation";
+
+/// In memory key-value store, the state machine replicas run by default.
+pub struct KvStore {
+    m: BTreeMap<String, Bytes>,
+}
+
+impl KvStore {
+    pub fn new() -> KvStore {
+        KvStore { m: BTreeMap::new() }
+    }
+}
+
+impl StateMachine for KvStore {
+    fn apply(&mut self, _: SlotNumber, c: &Command) -> Bytes {
+        match Op::parse(&c.operation) {
+            Some(Op::Get(k)) => self
+                .m
+                .get(&k)
+                .cloned()
+                .unwrap_or(Bytes::from_static(NOT_FOUND)),
+            Some(Op::Put(k, v)) => {
+                self.m.insert(k, v);
+                Bytes::from_static(OK)
+            }
+            Some(Op::Del(k)) => match self.m.remove(&k) {
+                Some(_) => Bytes::from_static(OK),
+                None => Bytes::from_static(NOT_FOUND),
+            },
+            None => Bytes::from_static(BAD_OP),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use bytes::Bytes;
+
+    use crate::{env::ProcessId, pval::Command};
+
+    use super::{KvStore, Op, StateMachine, NOT_FOUND, OK};
+
+    #[test]
+    fn kv() {
+        let client = ProcessId::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0, 0);
+        let mut kv = KvStore::new();
+        let mut apply = |slot, op: &str| {
+            kv.apply(
+                slot,
+                &Command::new_from_str(client.clone(), slot.to_string(), op.to_string()),
+            )
+        };
+
+        assert_eq!(apply(1, "GET a"), NOT_FOUND);
+        assert_eq!(apply(2, "PUT a hello world"), OK);
+        assert_eq!(apply(3, "get a"), "hello world");
+        assert_eq!(apply(4, "DEL a"), OK);
+        assert_eq!(apply(5, "GET a"), NOT_FOUND);
+        assert_eq!(
+            Op::parse(b"PUT k v v"),
+            Some(Op::Put("k".to_string(), Bytes::from("v v")))
+        );
+        assert_eq!(Op::parse(b"GET"), None);
+    }
+}
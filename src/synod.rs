@@ -0,0 +1,233 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel;
+use log::warn;
+
+use super::{
+    constants::{SLEEP_TIME, SYNOD_SLOTS},
+    ds::LastSeen,
+    env::{Env, Executor, ProcessId, ProcessType, Receiver, Router},
+    leader::{Commander, LeaderConfig, Round, Scout},
+    message::Message,
+    pval::{BallotNumber, Command, SlotNumber},
+};
+
+/// Single-decree Paxos: proposers agree on one value per `instance`, for
+/// one-off decisions such as electing a leader or bootstrapping a
+/// configuration. It runs on the acceptors of the cluster with the scouts and
+/// commanders of `leader::Leader`, each instance taking its own slot from
+/// `SYNOD_SLOTS` on, which the replicated log never reaches and which the
+/// scouts of its leaders are not sent. Decisions go to the proposer only.
+///
+/// A proposer proposes its own value unless phase 1 finds one that may have
+/// been chosen already, and hands over the value chosen, whoever proposed it.
+/// A preempted proposer waits longer each time before it tries again, so
+/// that competing proposers let one of them finish.
+pub struct Proposer {
+    me: ProcessId,
+    instance: SlotNumber,
+    value: Command,
+    ballot: BallotNumber,
+    config: LeaderConfig,
+    seen: Arc<LastSeen>,
+    chosen: channel::Sender<Command>,
+}
+
+impl Proposer {
+    pub fn new(
+        me: ProcessId,
+        instance: SlotNumber,
+        value: Command,
+    ) -> (Proposer, channel::Receiver<Command>) {
+        Proposer::with_config(me, instance, value, LeaderConfig::default())
+    }
+
+    pub fn with_config(
+        me: ProcessId,
+        instance: SlotNumber,
+        value: Command,
+        config: LeaderConfig,
+    ) -> (Proposer, channel::Receiver<Command>) {
+        let (s, r) = channel::bounded(1);
+        let proposer = Proposer {
+            me: me.clone(),
+            instance,
+            value,
+            ballot: BallotNumber::new(1, me),
+            config,
+            seen: Arc::new(LastSeen::new()),
+            chosen: s,
+        };
+        (proposer, r)
+    }
+
+    fn round(&self) -> Round {
+        Round {
+            leader: self.me.clone(),
+            ballot: self.ballot.clone(),
+            group: None,
+            seen: self.seen.clone(),
+            log: false,
+        }
+    }
+
+    /// The slot of the instance on the acceptors.
+    fn slot(&self) -> SlotNumber {
+        SYNOD_SLOTS + self.instance
+    }
+
+    fn scout<T: Router, E: Env<T>>(&self, env: &'static E) {
+        let sid = ProcessId::new(self.me.ip, self.me.port, env.new_id());
+        let scout = Scout::new(sid.clone(), self.round(), self.slot(), self.config.clone());
+        env.register(sid, ProcessType::Scout, scout);
+    }
+
+    fn commander<T: Router, E: Env<T>>(&self, value: Command, env: &'static E) {
+        let cid = ProcessId::new(self.me.ip, self.me.port, env.new_id());
+        let round = self.round();
+        let commander = Commander::new(cid.clone(), round, self.slot(), value, self.config.clone());
+        env.register(cid, ProcessType::Commander, commander);
+    }
+}
+
+impl Executor for Proposer {
+    fn exec<R: Receiver, T: Router, E: Env<T>>(mut self, reciever: R, env: &'static E) {
+        // proposers preempted at the same time retry at different times
+        let stagger = self.config.retransmit / 8 * (self.me.id % 8);
+        let mut delay = self.config.retransmit;
+        // when to try again after a preemption
+        let mut retry: Option<Instant> = None;
+        self.scout(env);
+        loop {
+            if retry.map_or(false, |t| Instant::now() >= t) {
+                retry = None;
+                self.scout(env);
+            }
+            let wait = match retry {
+                Some(t) => t.saturating_duration_since(Instant::now()),
+                None => self.config.retransmit_max,
+            };
+            let msg = match reciever.get_timeout(SLEEP_TIME, wait) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+
+            match msg {
+                Message::Adopt(_, ballot, values) => {
+                    if self.ballot == ballot {
+                        // a value accepted in phase 1 may have been chosen,
+                        // it is proposed in place of ours
+                        let value = match values.get(&self.slot()) {
+                            Some(p) => p.command.clone(),
+                            None => self.value.clone(),
+                        };
+                        self.commander(value, env);
+                    }
+                }
+                Message::Preempt(_, ballot) => {
+                    if self.ballot < ballot {
+                        self.ballot = BallotNumber::new(ballot.round + 1, self.me.clone());
+                        retry = Some(Instant::now() + delay + stagger);
+                        delay = (delay * 2).min(self.config.retransmit_max);
+                    }
+                }
+                Message::Failed(_, ballot, _) => {
+                    if self.ballot == ballot {
+                        self.scout(env);
+                    }
+                }
+                Message::Decision(_, slot, command, _) => {
+                    if slot == self.slot() {
+                        let _ = self.chosen.send(command);
+                        return;
+                    }
+                }
+                m => warn!("Proposer {} ignored {}", self.me, m),
+            }
+        }
+    }
+}
+
+/// Proposes `value` in `instance` with a new `Proposer` and waits up to
+/// `timeout` for the value chosen.
+pub fn propose<T: Router, E: Env<T>>(
+    env: &'static E,
+    me: ProcessId,
+    instance: SlotNumber,
+    value: Command,
+    timeout: Duration,
+) -> Option<Command> {
+    let (proposer, chosen) = Proposer::new(me.clone(), instance, value);
+    env.register(me, ProcessType::Proposer, proposer);
+    chosen.recv_deadline(Instant::now() + timeout).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam::channel;
+
+    use crate::{
+        env::{Env, ProcessType, Router},
+        fixture,
+        message::Message,
+        pval::{BallotNumber, Command},
+    };
+
+    use super::{propose, Proposer};
+
+    #[test]
+    fn competing_proposers_agree() {
+        let env = fixture::env();
+        let acceptors = fixture::acceptors(env, 3);
+        let (_, replica) = fixture::probe(env, ProcessType::Replica);
+
+        let values: Vec<Command> = (0..3)
+            .map(|i| {
+                let id = fixture::new_id(env);
+                Command::new_from_str(id, format!("{}", i), format!("leader {}", i))
+            })
+            .collect();
+        // all three start before any of them is waited on
+        let receivers: Vec<channel::Receiver<Command>> = values
+            .iter()
+            .map(|v| {
+                let (proposer, chosen) = Proposer::new(v.client.clone(), 1, v.clone());
+                env.register(v.client.clone(), ProcessType::Proposer, proposer);
+                chosen
+            })
+            .collect();
+        let chosen: Vec<Command> = receivers
+            .iter()
+            .map(|c| c.recv_timeout(fixture::WAIT).unwrap())
+            .collect();
+        assert!(values.contains(&chosen[0]));
+        assert!(chosen.iter().all(|c| *c == chosen[0]));
+        // proposers are not leaders, replicas never send them anything
+        assert!(env.cluster().leaders().is_empty());
+
+        // a late proposer learns the value, the next instance is a new decision
+        let v = Command::new_from_str(fixture::new_id(env), format!("3"), format!("leader 3"));
+        let me = || fixture::new_id(env);
+        assert_eq!(
+            propose(env, me(), 1, v.clone(), fixture::WAIT),
+            Some(chosen[0].clone())
+        );
+        assert_eq!(propose(env, me(), 2, v.clone(), fixture::WAIT), Some(v));
+
+        // the replicated log sees neither the decisions nor the values
+        assert!(replica
+            .try_iter()
+            .all(|m| !matches!(m, Message::Decision(..))));
+        let (scout, p1b) = fixture::probe(env, ProcessType::Scout);
+        let ballot = BallotNumber::new(0, scout.clone());
+        env.router()
+            .send(&acceptors[0], Message::P1A(scout, ballot, 1));
+        match p1b.recv_timeout(fixture::WAIT).unwrap() {
+            Message::P1B(_, _, accepted, _) => assert!(accepted.iter().next().is_none()),
+            m => panic!("unexpected {}", m),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::trace;
+use once_cell::sync::Lazy;
+
+use super::{
+    env::ProcessId,
+    pval::{Command, SlotNumber},
+};
+
+/// Number of spans kept in memory once collection is enabled.
+static CAPACITY: usize = 100_000;
+
+static COLLECT: AtomicBool = AtomicBool::new(false);
+static SPANS: Lazy<Mutex<VecDeque<Span>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+/// The steps a command goes through, in order, on its way to the state machine.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    Request,
+    Propose,
+    P2A,
+    P2B,
+    Decision,
+    Perform,
+}
+
+/// Identifies every span of one command, derived from its client and
+/// `req_id` so that all roles agree on it without sending anything extra.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TraceId(u64);
+
+impl TraceId {
+    pub fn of(c: &Command) -> TraceId {
+        // FNV-1a, stable across processes unlike the std hasher
+        let mut h: u64 = 0xcbf29ce484222325;
+        let client = format!("{}", c.client);
+        for b in client.as_bytes().iter().chain(c.req_id.iter()) {
+            h ^= *b as u64;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+        TraceId(h)
+    }
+}
+
+impl Display for TraceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub trace: TraceId,
+    pub stage: Stage,
+    pub process: ProcessId,
+    pub slot: Option<SlotNumber>,
+    pub at: SystemTime,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let at = self.at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        write!(
+            f,
+            "trace_id={} stage={:?} process={}:{}/{} slot={} at_us={}",
+            self.trace,
+            self.stage,
+            self.process.ip,
+            self.process.port,
+            self.process.id,
+            self.slot.map_or(String::from("-"), |s| s.to_string()),
+            at.as_micros()
+        )
+    }
+}
+
+/// Emits a span for `command` reaching `stage` at `process`, as a `trace!`
+/// line and, when enabled, into the in memory collector.
+pub fn record(stage: Stage, process: &ProcessId, command: &Command, slot: Option<SlotNumber>) {
+    if command.is_noop() {
+        return;
+    }
+
+    let span = Span {
+        trace: TraceId::of(command),
+        stage,
+        process: process.clone(),
+        slot,
+        at: SystemTime::now(),
+    };
+    trace!(target: "paxos::trace", "{}", span);
+
+    if COLLECT.load(Ordering::Relaxed) {
+        let mut spans = SPANS.lock().unwrap();
+        if spans.len() == CAPACITY {
+            spans.pop_front();
+        }
+        spans.push_back(span);
+    }
+}
+
+pub fn collect(enabled: bool) {
+    COLLECT.store(enabled, Ordering::Relaxed);
+}
+
+/// Every collected span of a command, oldest first.
+pub fn spans(trace: TraceId) -> Vec<Span> {
+    let mut res: Vec<Span> = SPANS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|s| s.trace == trace)
+        .cloned()
+        .collect();
+    res.sort_by_key(|s| s.at);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{fixture, pval::Command};
+
+    use super::{collect, record, spans, Stage, TraceId};
+
+    #[test]
+    fn record_and_collect() {
+        let env = fixture::env();
+        let (client, replica) = (fixture::new_id(env), fixture::new_id(env));
+        let c = Command::new_from_str(client.clone(), format!("1"), format!("PUT a 1"));
+        let other = Command::new_from_str(client.clone(), format!("2"), format!("PUT a 1"));
+
+        // nothing is kept until collection is enabled
+        record(Stage::Request, &replica, &c, None);
+        assert!(spans(TraceId::of(&c)).is_empty());
+
+        collect(true);
+        record(Stage::Request, &replica, &c, None);
+        record(Stage::Propose, &replica, &other, Some(1));
+        record(Stage::Perform, &replica, &c, Some(1));
+        record(Stage::Perform, &replica, &Command::noop(client), Some(2));
+        collect(false);
+
+        let stages: Vec<(Stage, Option<u64>)> = spans(TraceId::of(&c))
+            .iter()
+            .map(|s| (s.stage, s.slot))
+            .collect();
+        assert_eq!(stages, [(Stage::Request, None), (Stage::Perform, Some(1))]);
+        assert_eq!(spans(TraceId::of(&other)).len(), 1);
+        assert_ne!(TraceId::of(&c), TraceId::of(&other));
+    }
+}
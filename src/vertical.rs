@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+
+use super::{
+    env::ProcessId,
+    pval::{Command, SlotNumber},
+    state::{StateMachine, BAD_OP, OK},
+};
+
+pub static STALE: &[u8] = b"STALE";
+
+/// A set of acceptors, numbered by the master in the order it first hands it
+/// out. Rounds running on the same acceptors share the id.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Configuration {
+    pub id: u64,
+    pub acceptors: Vec<ProcessId>,
+}
+
+/// The configuration of a round of Vertical Paxos, and the one its phase 1
+/// reads from: the configuration of the last round known to be complete, or
+/// none for the first round, as nothing can have been accepted before it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Assignment {
+    pub round: u64,
+    pub config: Configuration,
+    pub read: Option<Configuration>,
+}
+
+impl Assignment {
+    /// The acceptors phase 1 is sent to.
+    pub fn readers(&self) -> &Vec<ProcessId> {
+        &self.read.as_ref().unwrap_or(&self.config).acceptors
+    }
+
+    /// Written as `round id acceptors [id acceptors]`, acceptors separated by
+    /// commas.
+    pub fn encode(&self) -> Bytes {
+        let mut s = format!("{} {}", self.round, encode_config(&self.config));
+        if let Some(read) = self.read.as_ref() {
+            s = format!("{} {}", s, encode_config(read));
+        }
+        Bytes::from(s)
+    }
+
+    pub fn parse(b: &[u8]) -> Option<Assignment> {
+        let s = String::from_utf8_lossy(b);
+        let parts: Vec<&str> = s.trim().split(' ').collect();
+        let config = |i: usize| -> Option<Configuration> {
+            Some(Configuration {
+                id: parts.get(i)?.parse().ok()?,
+                acceptors: parse_ids(parts.get(i + 1)?)?,
+            })
+        };
+        let read = match parts.len() {
+            3 => None,
+            5 => Some(config(3)?),
+            _ => return None,
+        };
+        Some(Assignment {
+            round: parts[0].parse().ok()?,
+            config: config(1)?,
+            read,
+        })
+    }
+}
+
+fn encode_config(c: &Configuration) -> String {
+    format!("{} {}", c.id, encode_ids(&c.acceptors))
+}
+
+fn encode_ids(ids: &[ProcessId]) -> String {
+    let ids: Vec<String> = ids
+        .iter()
+        .map(|a| format!("{}:{}:{}", a.ip, a.port, a.id))
+        .collect();
+    ids.join(",")
+}
+
+fn parse_ids(s: &str) -> Option<Vec<ProcessId>> {
+    s.split(',')
+        .map(|a| {
+            let mut parts = a.rsplitn(3, ':');
+            let id = parts.next()?.parse().ok()?;
+            let port = parts.next()?.parse().ok()?;
+            let ip = parts.next()?.parse().ok()?;
+            Some(ProcessId::new(ip, port, id))
+        })
+        .collect()
+}
+
+/// An operation on `ConfigMaster`, written on the wire as `NEW acceptors`
+/// or `COMPLETE round`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MasterOp {
+    /// A new round for the leader sending it, on these acceptors.
+    New(Vec<ProcessId>),
+    /// The leader of the round has written everything it read to its
+    /// configuration, later rounds can read from it alone.
+    Complete(u64),
+}
+
+impl MasterOp {
+    pub fn parse(b: &[u8]) -> Option<MasterOp> {
+        let s = String::from_utf8_lossy(b);
+        match s.trim().split_once(' ') {
+            Some(("NEW", ids)) => Some(MasterOp::New(parse_ids(ids)?)),
+            Some(("COMPLETE", round)) => Some(MasterOp::Complete(round.parse().ok()?)),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self) -> Bytes {
+        match self {
+            MasterOp::New(acceptors) => Bytes::from(format!("NEW {}", encode_ids(acceptors))),
+            MasterOp::Complete(round) => Bytes::from(format!("COMPLETE {}", round)),
+        }
+    }
+}
+
+/// The configuration master of Vertical Paxos, a state machine for replicas
+/// of a separate, usually small, cluster of this crate. It hands each leader
+/// that asks a round above all the earlier ones together with its
+/// configuration, so ballot `(round, leader)` belongs to one configuration
+/// only and `BallotNumber` needs no configuration of its own.
+///
+/// A new round reads from the last complete round, and a round can only be
+/// completed while it is the latest: rounds in between that never completed
+/// have not chosen anything their phase 1 did not read.
+pub struct ConfigMaster {
+    rounds: BTreeMap<u64, (ProcessId, Configuration)>,
+    complete: Option<u64>,
+    configs: u64,
+}
+
+impl ConfigMaster {
+    pub fn new() -> ConfigMaster {
+        ConfigMaster {
+            rounds: BTreeMap::new(),
+            complete: None,
+            configs: 0,
+        }
+    }
+
+    fn assign(&mut self, leader: &ProcessId, mut acceptors: Vec<ProcessId>) -> Assignment {
+        acceptors.sort();
+        let last = self.rounds.iter().next_back();
+        let round = last.map_or(1, |(r, _)| r + 1);
+        let config = match last {
+            Some((_, (_, c))) if c.acceptors == acceptors => c.clone(),
+            _ => {
+                self.configs += 1;
+                Configuration {
+                    id: self.configs,
+                    acceptors,
+                }
+            }
+        };
+        self.rounds.insert(round, (leader.clone(), config.clone()));
+        Assignment {
+            round,
+            config,
+            read: self.complete.map(|r| self.rounds[&r].1.clone()),
+        }
+    }
+}
+
+impl StateMachine for ConfigMaster {
+    fn apply(&mut self, _: SlotNumber, c: &Command) -> Bytes {
+        match MasterOp::parse(&c.operation) {
+            Some(MasterOp::New(acceptors)) => self.assign(&c.client, acceptors).encode(),
+            Some(MasterOp::Complete(round)) => {
+                let latest = self.rounds.iter().next_back();
+                match latest {
+                    Some((r, (l, _))) if *r == round && *l == c.client => {
+                        self.complete = Some(round);
+                        Bytes::from_static(OK)
+                    }
+                    _ => Bytes::from_static(STALE),
+                }
+            }
+            None => Bytes::from_static(BAD_OP),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use crate::{env::ProcessId, pval::Command, state::StateMachine};
+
+    use super::{Assignment, ConfigMaster, MasterOp, STALE};
+
+    fn pid(id: u32) -> ProcessId {
+        ProcessId::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 7000, id)
+    }
+
+    #[test]
+    fn rounds_read_from_the_last_complete_one() {
+        let mut master = ConfigMaster::new();
+        let old = vec![pid(1), pid(2), pid(3)];
+        let new = vec![pid(2), pid(3), pid(4)];
+        let mut apply = |leader: u32, op: MasterOp| {
+            let c = Command::new_from_str(pid(leader), "r".to_string(), String::new());
+            let c = Command {
+                operation: op.encode(),
+                ..c
+            };
+            master.apply(0, &c)
+        };
+
+        let first = Assignment::parse(&apply(10, MasterOp::New(old.clone()))).unwrap();
+        assert_eq!(
+            (first.round, first.config.id, first.read.clone()),
+            (1, 1, None)
+        );
+        assert_eq!(first.readers(), &old);
+
+        // round 1 is not complete, a round on the same acceptors reads from
+        // nothing either and takes over
+        let second = Assignment::parse(&apply(11, MasterOp::New(old.clone()))).unwrap();
+        assert_eq!(
+            (second.round, second.config.id, second.read.clone()),
+            (2, 1, None)
+        );
+        assert_eq!(apply(10, MasterOp::Complete(1)), STALE);
+        assert_eq!(apply(10, MasterOp::Complete(2)), STALE);
+        assert_eq!(apply(11, MasterOp::Complete(2)), "OK");
+
+        let third = Assignment::parse(&apply(10, MasterOp::New(new.clone()))).unwrap();
+        assert_eq!((third.round, third.config.id), (3, 2));
+        assert_eq!(third.config.acceptors, new);
+        assert_eq!(third.readers(), &old);
+        assert_eq!(Assignment::parse(&third.encode()), Some(third));
+    }
+}
@@ -0,0 +1,57 @@
+use super::{
+    acceptor::{accept, promise},
+    constants::SLEEP_TIME,
+    ds::Accepted,
+    env::{Env, Executor, ProcessId, Receiver, Router},
+    message::Message,
+    pval::BallotNumber,
+};
+
+/// An auxiliary acceptor from Cheap Paxos. It counts in the quorums like an
+/// acceptor, but scouts and commanders only ask it while one of the main
+/// acceptors is suspected to be down, so it is idle as long as they are all
+/// up and only holds the values accepted during failures. The leader sends
+/// it the decision of such a slot once the main acceptors have accepted the
+/// value again, and it drops the value. It keeps no more than its ballot and
+/// those values, and takes no part in fast rounds.
+pub struct Witness {
+    me: ProcessId,
+    ballot: BallotNumber,
+    accepted: Accepted,
+}
+
+impl Witness {
+    pub fn new(id: ProcessId) -> Witness {
+        Witness {
+            me: id.clone(),
+            ballot: BallotNumber::first(id),
+            accepted: Accepted::new(),
+        }
+    }
+
+    fn adopt(&mut self, ballot: BallotNumber) {
+        if self.ballot < ballot {
+            self.ballot = ballot;
+        }
+    }
+}
+
+impl Executor for Witness {
+    fn exec<R: Receiver, T: Router, E: Env<T>>(mut self, reciever: R, env: &E) {
+        loop {
+            match reciever.get(SLEEP_TIME) {
+                Message::P1A(src, ballot, from) => {
+                    self.adopt(ballot);
+                    promise(&self.me, &self.ballot, &self.accepted, &src, from, env);
+                }
+                Message::P2A(src, ballot, slot, command) => {
+                    self.adopt(ballot.clone());
+                    let p2a = (ballot, slot, command);
+                    accept(&self.me, &self.ballot, &mut self.accepted, &src, p2a, env);
+                }
+                Message::Decision(_, slot, _, _) => self.accepted.remove(&slot),
+                _ => panic!("unexpected message"),
+            }
+        }
+    }
+}
@@ -1,18 +1,27 @@
 use std::collections::HashMap;
-use std::sync::atomic::AtomicU32;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::task::Context;
 use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
+use bytes::Bytes;
 use chashmap::CHashMap;
 use log::debug;
 use log::info;
-use protobuf::Message;
-use protobuf::MessageField;
+use log::warn;
 use zmq::Socket;
 
-use crate::constants::INCOMING_PORT;
+use crate::auth::Keyring;
+use crate::codec::Codec;
+use crate::codec::ProtobufCodec;
+use crate::codec::WireMessage;
+use crate::constants::HELLO_INTERVAL_MS;
+use crate::constants::LINGER_MS;
+use crate::constants::MIN_PROTOCOL_VERSION;
+use crate::constants::PEER_IDLE_MS;
+use crate::constants::SEND_TIMEOUT_MS;
 use crate::env::Cluster;
 use crate::env::Env;
 use crate::env::Executor;
@@ -22,66 +31,151 @@ use crate::env::Receiver;
 use crate::env::Router;
 use crate::env::Sender;
 use crate::local::EnvState;
-use crate::proto::proto;
+use crate::message::DecodeErr;
+use crate::metrics::REGISTRY;
 
 impl ProcessId {
     pub fn addr_sender(&self) -> String {
-        format!("tcp://{}:{}", self.ip, INCOMING_PORT)
+        format!("tcp://{}:{}", self.ip, self.port)
     }
+
+    fn endpoint(&self) -> String {
+        format!("{}:{}", self.ip, self.port)
+    }
+}
+
+/// Whether this node talks to a peer on protocol version `v`. Newer peers are
+/// fine, messages of types this node does not know are dropped on decoding.
+pub fn compatible(v: u32) -> bool {
+    v >= MIN_PROTOCOL_VERSION
 }
 
-pub struct WireMessage {
-    to: ProcessId,
-    message: crate::message::Message,
+/// Protocol versions that peers announced in their hello, by endpoint.
+#[derive(Default)]
+pub(crate) struct Versions {
+    m: Mutex<HashMap<String, u32>>,
 }
 
-impl Into<proto::WireMessage> for WireMessage {
-    fn into(self) -> proto::WireMessage {
-        let mut def = proto::WireMessage::default();
-        def.to = MessageField::some(self.to.into());
-        def.message = MessageField::some(self.message.into());
-        def
+impl Versions {
+    fn set(&self, id: &ProcessId, v: u32) {
+        self.m.lock().unwrap().insert(id.endpoint(), v);
+    }
+
+    fn get(&self, id: &ProcessId) -> Option<u32> {
+        self.m.lock().unwrap().get(&id.endpoint()).cloned()
     }
 }
 
-impl From<proto::WireMessage> for WireMessage {
-    fn from(value: proto::WireMessage) -> Self {
-        Self {
-            to: ProcessId::from(value.to.unwrap()),
-            message: crate::message::Message::from(value.message.unwrap()),
+/// How the router and the poller of an env put messages on the wire, shared
+/// between the two.
+pub(crate) struct Wire {
+    codec: Box<dyn Codec>,
+    // signs every message when set, see `ZMQEnv::with_keyring`
+    keyring: Option<Keyring>,
+    versions: Versions,
+}
+
+impl Wire {
+    pub(crate) fn new(codec: Box<dyn Codec>, keyring: Option<Keyring>) -> Wire {
+        Wire {
+            codec,
+            keyring,
+            versions: Versions::default(),
         }
     }
 }
 
-static SOCK_COUNT: AtomicU32 = AtomicU32::new(0);
+struct Connection {
+    socket: Socket,
+    greeted: Option<Instant>,
+    used: Instant,
+}
 
 #[derive(Clone)]
 pub struct ZMQRouter {
     context: zmq::Context,
+    wire: Arc<Wire>,
+    // one PUSH socket per endpoint, each opened with a hello
+    connections: Arc<Mutex<HashMap<String, Connection>>>,
 }
 
 impl ZMQRouter {
-    fn new(c: zmq::Context) -> Self {
-        Self { context: c }
+    pub(crate) fn new(c: zmq::Context, wire: Arc<Wire>) -> Self {
+        Self {
+            context: c,
+            wire,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn connect(&self, id: &ProcessId) -> Connection {
+        let s = self.context.socket(zmq::PUSH).unwrap();
+        // do not hold on to messages for peers that are gone, e.g. clients
+        s.set_linger(LINGER_MS).unwrap();
+        // a peer that stopped reading blocks a send for this long at most
+        s.set_sndtimeo(SEND_TIMEOUT_MS).unwrap();
+        assert!(s.connect(&id.addr_sender()).is_ok());
+        Connection {
+            socket: s,
+            greeted: None,
+            used: Instant::now(),
+        }
+    }
+
+    fn write(&self, s: &Socket, to: &ProcessId, m: crate::message::Message) {
+        let from = m.id().clone();
+        // messages sent again anyway are dropped rather than wait for a slow
+        // peer, the others wait up to the send timeout
+        let flags = if m.retransmitted() { zmq::DONTWAIT } else { 0 };
+        let payload = self.wire.codec.encode(WireMessage::new(to.clone(), m));
+        // the signature goes in a second frame
+        let res = match self
+            .wire
+            .keyring
+            .as_ref()
+            .and_then(|k| k.sign(&from, &payload))
+        {
+            Some(tag) => s.send_multipart([payload, tag], flags),
+            None => s.send(payload, flags),
+        };
+        if let Err(e) = res {
+            debug!("dropped message for {}: {}", to, e);
+            REGISTRY.inc("paxos_messages_dropped_total", &[]);
+        }
     }
 }
 
 impl Router for ZMQRouter {
     fn send(&self, id: &ProcessId, m: crate::message::Message) {
-        let s = self.context.socket(zmq::PUSH).unwrap();
-        assert!(s.connect(&id.addr_sender()).is_ok());
-        // info!(
-        //     "socket count: {}, from: {}, message: {}",
-        //     SOCK_COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
-        //     id,
-        //     m
-        // );
-        let p: crate::proto::proto::WireMessage = WireMessage {
-            to: id.clone(),
-            message: m,
+        REGISTRY.inc("paxos_messages_sent_total", &[("type", m.name())]);
+        if let Some(v) = self.wire.versions.get(id) {
+            if v < m.since() {
+                debug!("not sending {} to {} on version {}", m.name(), id, v);
+                return;
+            }
         }
-        .into();
-        s.send(p.write_to_bytes().unwrap(), 0).unwrap()
+
+        let mut connections = self.connections.lock().unwrap();
+        let endpoint = id.endpoint();
+        if !connections.contains_key(&endpoint) {
+            let idle = Duration::from_millis(PEER_IDLE_MS);
+            connections.retain(|_, c| c.used.elapsed() < idle);
+            connections.insert(endpoint.clone(), self.connect(id));
+        }
+
+        let c = connections.get_mut(&endpoint).unwrap();
+        // greeted again from time to time, in case the peer restarted
+        let interval = Duration::from_millis(HELLO_INTERVAL_MS);
+        if c.greeted.map_or(true, |g| g.elapsed() >= interval) {
+            self.write(
+                &c.socket,
+                id,
+                crate::message::Message::Hello(m.id().clone()),
+            );
+            c.greeted = Some(Instant::now());
+        }
+        c.used = Instant::now();
+        self.write(&c.socket, id, m);
     }
 }
 
@@ -91,16 +185,19 @@ where
 {
     context: zmq::Context,
     m: Arc<CHashMap<ProcessId, S>>,
+    // only messages signed by their sender are accepted with a keyring
+    wire: Arc<Wire>,
 }
 
 impl<S> ZMQPoller<S>
 where
     S: Sender,
 {
-    fn new(c: zmq::Context) -> ZMQPoller<S> {
+    pub(crate) fn new(c: zmq::Context, wire: Arc<Wire>) -> ZMQPoller<S> {
         Self {
             context: c,
             m: Arc::new(CHashMap::new()),
+            wire,
         }
     }
 
@@ -109,24 +206,84 @@ where
         assert!(server.bind(addr).is_ok());
 
         loop {
-            match Socket::recv_bytes(&server, 0) {
-                Ok(b) => match crate::proto::proto::WireMessage::parse_from_bytes(&b) {
-                    Ok(m) => self.handle(m.into()),
-                    Err(e) => panic!("unexpected error while parsing {}", e),
+            match Socket::recv_multipart(&server, 0) {
+                Ok(frames) => match self.accept(frames) {
+                    Some(m) => self.handle(m),
+                    None => REGISTRY.inc("paxos_messages_rejected_total", &[]),
                 },
                 Err(e) => panic!("polling encountered error {}", e),
             }
         }
     }
 
+    /// Parses a received message, checks that its sender speaks a compatible
+    /// protocol version, and its signature against the process it claims to
+    /// come from when there is a keyring. Anyone can connect to the poller,
+    /// so bad input is dropped rather than trusted.
+    fn accept(&self, frames: Vec<Vec<u8>>) -> Option<WireMessage> {
+        let mut frames = frames.into_iter();
+        // decoded messages keep pointing into the payload, e.g. operations
+        let payload = Bytes::from(frames.next()?);
+        let tag = frames.next();
+        let m = match self.wire.codec.decode(payload.clone()) {
+            Ok(m) => m,
+            Err(DecodeErr::UnknownType(t)) => {
+                debug!("dropped a message of unknown type {}", t);
+                return None;
+            }
+            Err(DecodeErr::Malformed(e)) => {
+                warn!("dropped a message that does not parse: {}", e);
+                return None;
+            }
+        };
+        let version = m.version;
+        if !compatible(version) {
+            debug!(
+                "dropped a message on incompatible protocol version {}",
+                version
+            );
+            return None;
+        }
+
+        if let Some(k) = self.wire.keyring.as_ref() {
+            let from = m.message.id();
+            let signed = tag.map_or(false, |tag| k.verify(from, &payload, &tag));
+            if !signed {
+                warn!(
+                    "dropped {} with a bad signature from {}",
+                    m.message.name(),
+                    from
+                );
+                return None;
+            }
+        }
+
+        if let crate::message::Message::Hello(from) = &m.message {
+            let versions = &self.wire.versions;
+            if versions.get(from) != Some(version) {
+                info!(
+                    "peer {} speaks protocol version {}",
+                    from.endpoint(),
+                    version
+                );
+            }
+            versions.set(from, version);
+        }
+        Some(m)
+    }
+
     fn handle(&self, m: WireMessage) {
-        self.m
-            .get(&m.to)
-            .map(|s| {
+        if let crate::message::Message::Hello(_) = m.message {
+            return;
+        }
+        match self.m.get(&m.to) {
+            Some(s) => {
                 debug!("polled message {}: {}", m.to, m.message);
                 s.send(&m.message)
-            })
-            .unwrap_or_else(|| panic!("unable to find the sender for id {}", m.to))
+            }
+            // e.g. a response for a client that has already gone away
+            None => debug!("dropped message for unknown process {}", m.to),
+        }
     }
 }
 
@@ -134,7 +291,7 @@ impl<S> ZMQPoller<S>
 where
     S: Sender,
 {
-    fn add(&self, id: ProcessId, new_sender: S) {
+    pub(crate) fn add(&self, id: ProcessId, new_sender: S) {
         self.m.insert(id, new_sender);
     }
 }
@@ -185,11 +342,27 @@ where
 
 impl<R: Receiver, S: Sender> ZMQEnv<R, S> {
     pub fn new(new_channel_fn: fn() -> (R, S)) -> ZMQEnv<R, S> {
+        Self::with_codec(new_channel_fn, Box::new(ProtobufCodec), None)
+    }
+
+    /// Signs every message sent with the key of its sender, and drops every
+    /// received message that is not signed by the process it claims to be from.
+    pub fn with_keyring(new_channel_fn: fn() -> (R, S), keyring: Keyring) -> ZMQEnv<R, S> {
+        Self::with_codec(new_channel_fn, Box::new(ProtobufCodec), Some(keyring))
+    }
+
+    /// Encodes messages with `codec` rather than protobuf, see `codec::Format`.
+    pub fn with_codec(
+        new_channel_fn: fn() -> (R, S),
+        codec: Box<dyn Codec>,
+        keyring: Option<Keyring>,
+    ) -> ZMQEnv<R, S> {
         let context = zmq::Context::new();
+        let wire = Arc::new(Wire::new(codec, keyring));
         ZMQEnv {
             new_channel_fn: new_channel_fn,
-            router: ZMQRouter::new(context.clone()),
-            poller: ZMQPoller::new(context),
+            router: ZMQRouter::new(context.clone(), wire.clone()),
+            poller: ZMQPoller::new(context, wire),
             state: EnvState::new(),
         }
     }
@@ -203,13 +376,102 @@ mod tests {
         time::Duration,
     };
 
+    use protobuf::{EnumOrUnknown, Message as _};
+
     use crate::{
+        auth::Keyring,
+        codec::{ProtobufCodec, WireMessage},
+        constants::PROTOCOL_VERSION,
         env::{Env, ProcessId, Router},
-        message::Message,
+        message::{DecodeErr, Message},
+        proto::proto,
         pval::Command,
     };
 
-    use super::ZMQEnv;
+    use super::{compatible, Wire, ZMQEnv, ZMQRouter};
+
+    fn request(id: &ProcessId, op: &str) -> Message {
+        Message::Request(
+            id.clone(),
+            Command::new_from_str(id.clone(), format!("Request:1"), op.to_string()),
+        )
+    }
+
+    /// `m` as encoded by a peer on protocol `version`, with a field this
+    /// version does not know about.
+    fn encode(id: &ProcessId, m: Message, version: u32, t: Option<i32>) -> Vec<u8> {
+        let mut p: proto::WireMessage = WireMessage::new(id.clone(), m).into();
+        p.version = version;
+        if let Some(t) = t {
+            p.message.as_mut().unwrap().type_ = EnumOrUnknown::from_i32(t);
+        }
+        let mut b = p.write_to_bytes().unwrap();
+        // field 100, varint 1
+        b.extend([0xa0, 0x06, 0x01]);
+        b
+    }
+
+    #[test]
+    fn decode_newer() {
+        let local_host = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let id = ProcessId::new(local_host, 0, 0);
+
+        let b = encode(&id, request(&id, "Op:1"), PROTOCOL_VERSION + 1, None);
+        let p = proto::WireMessage::parse_from_bytes(&b).unwrap();
+        assert!(compatible(p.version));
+        assert!(WireMessage::try_from(p).is_ok());
+
+        let b = encode(&id, request(&id, "Op:1"), PROTOCOL_VERSION + 1, Some(42));
+        let p = proto::WireMessage::parse_from_bytes(&b).unwrap();
+        assert_eq!(
+            WireMessage::try_from(p).err(),
+            Some(DecodeErr::UnknownType(42))
+        );
+
+        // peers from before versioning do not send the header
+        assert!(!compatible(0));
+    }
+
+    #[test]
+    fn zmq_versions() {
+        let env = ZMQEnv::new(|| {
+            let (s, r) = crossbeam::channel::unbounded();
+            return (r, s);
+        });
+
+        let local_host = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let id = ProcessId::new(local_host, 6062, env.new_id());
+        let peer = ProcessId::new(local_host, 6063, 0);
+        let addr = id.addr_sender();
+
+        let (s, r) = crossbeam::channel::unbounded();
+        let wire = env.poller.wire.clone();
+        let poller = env.poller;
+        poller.add(id.clone(), s);
+        thread::spawn(move || {
+            poller.start(&addr);
+        });
+
+        let ctx = zmq::Context::new();
+        let push = ctx.socket(zmq::PUSH).unwrap();
+        push.connect(&id.addr_sender()).unwrap();
+        let newer = PROTOCOL_VERSION + 1;
+        for b in [
+            encode(&id, Message::Hello(peer.clone()), newer, None),
+            encode(&id, request(&peer, "Op:old"), 0, None),
+            encode(&id, request(&peer, "Op:unknown"), newer, Some(42)),
+            encode(&id, request(&peer, "Op:new"), newer, None),
+        ] {
+            push.send(b, 0).unwrap();
+        }
+
+        match r.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Message::Request(_, c) => assert_eq!(c.op_str(), "Op:new"),
+            _ => panic!("unexpected"),
+        }
+        assert!(r.recv_timeout(Duration::from_millis(200)).is_err());
+        assert_eq!(wire.versions.get(&peer), Some(newer));
+    }
 
     #[test]
     fn zmq_mock() {
@@ -245,6 +507,51 @@ mod tests {
         assert_eq!(r.recv().unwrap().id(), msg.id());
     }
 
+    #[test]
+    fn zmq_auth() {
+        let mut keyring = Keyring::new();
+        keyring.add("127.0.0.1", b"secret".to_vec()).unwrap();
+        let env = ZMQEnv::with_keyring(
+            || {
+                let (s, r) = crossbeam::channel::unbounded();
+                return (r, s);
+            },
+            keyring,
+        );
+
+        let local_host = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let id = ProcessId::new(local_host, 6061, env.new_id());
+        let addr = id.addr_sender();
+
+        let (s, r) = crossbeam::channel::unbounded();
+        let poller = env.poller;
+        poller.add(id.clone(), s);
+        thread::spawn(move || {
+            poller.start(&addr);
+        });
+
+        let request = |op: &str| request(&id, op);
+
+        // unsigned, and signed with a key the poller does not know
+        let router = |k| {
+            ZMQRouter::new(
+                zmq::Context::new(),
+                Wire::new(Box::new(ProtobufCodec), k).into(),
+            )
+        };
+        router(None).send(&id, request("Op:unsigned"));
+        let mut forged = Keyring::new();
+        forged.add("127.0.0.1", b"guess".to_vec()).unwrap();
+        router(Some(forged)).send(&id, request("Op:forged"));
+        env.router.send(&id, request("Op:signed"));
+
+        match r.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Message::Request(_, c) => assert_eq!(c.op_str(), "Op:signed"),
+            _ => panic!("unexpected"),
+        }
+        assert!(r.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
     #[test]
     fn zmp_multi_message() {
         let ctx = zmq::Context::new();